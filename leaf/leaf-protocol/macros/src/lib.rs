@@ -323,3 +323,160 @@ pub fn derive_has_borsh_schema(input: TokenStream) -> TokenStream {
     }
     .into()
 }
+
+/// Derive macro for the `EntityView` trait.
+///
+/// ```ignore
+/// #[derive(EntityView)]
+/// struct Profile {
+///     username: Username,
+///     name: Option<Name>,
+///     tags: Option<Tags>,
+///     links: Vec<WebLinks>,
+/// }
+/// ```
+///
+/// Every field must be a component, an [`Option`] of a component, or a [`Vec`] of components:
+///
+/// - A component field is required, and loading the view fails if the entity doesn't have it.
+/// - An [`Option`] field is loaded from the first component of that type, if there is one.
+///   Applying a [`None`] deletes the components of that type from the entity.
+/// - A [`Vec`] field is loaded from all of the components of that type, and applying it replaces
+///   them.
+///
+/// Each component type may only be used by one field, because loading the first field would take
+/// all of the components of that type and leave the others empty.
+#[proc_macro_derive(EntityView)]
+pub fn derive_entity_view(input: TokenStream) -> TokenStream {
+    let input = venial::parse_item(input.into()).unwrap();
+
+    let venial::Item::Struct(s) = &input else {
+        throw!(
+            input,
+            "You may only derive EntityView on structs with named fields."
+        );
+    };
+    let venial::Fields::Named(fields) = &s.fields else {
+        throw!(
+            s,
+            "You may only derive EntityView on structs with named fields."
+        );
+    };
+    let name = &s.name;
+
+    let mut schemas = Vec::new();
+    let mut loads = Vec::new();
+    let mut applies = Vec::new();
+    let mut component_types = Vec::<proc_macro2::TokenStream>::new();
+    for field in fields.fields.items() {
+        let field_name = &field.name;
+        let (kind, inner) = field_kind(&field.ty.tokens);
+        if component_types
+            .iter()
+            .any(|x| x.to_string() == inner.to_string())
+        {
+            throw!(
+                field.ty,
+                "Each component type may only be used by one field of an EntityView."
+            );
+        }
+        component_types.push(inner.clone());
+        schemas.push(quote! {
+            <#inner as ::leaf_protocol::Component>::schema_id()
+        });
+        match kind {
+            FieldKind::Required => {
+                loads.push(quote! { #field_name: components.take_required::<#inner>()? });
+                applies.push(quote! {
+                    entity.replace_components::<#inner>([&self.#field_name])?;
+                });
+            }
+            FieldKind::Optional => {
+                loads.push(quote! { #field_name: components.take_one::<#inner>()? });
+                applies.push(quote! {
+                    entity.replace_components::<#inner>(&self.#field_name)?;
+                });
+            }
+            FieldKind::Many => {
+                loads.push(quote! { #field_name: components.take_all::<#inner>()? });
+                applies.push(quote! {
+                    entity.replace_components::<#inner>(&self.#field_name)?;
+                });
+            }
+        }
+    }
+
+    quote! {
+        // Component types that are written differently, like through a type alias, are only found
+        // to be the same by the compiler, which rejects the conflicting implementations.
+        const _: () = {
+            trait EntityViewComponent {}
+            #(impl EntityViewComponent for #component_types {})*
+        };
+
+        impl ::leaf_protocol::EntityView for #name {
+            fn schemas() -> ::std::vec::Vec<::leaf_protocol::types::Digest> {
+                ::std::vec![#(#schemas),*]
+            }
+
+            fn from_components(
+                components: &mut ::leaf_protocol::ComponentMap,
+            ) -> ::leaf_protocol::anyhow::Result<Self> {
+                Ok(Self {
+                    #(#loads),*
+                })
+            }
+
+            fn load<S: ::leaf_protocol::store::LeafStore>(
+                entity: &::leaf_protocol::LoadedEntity<S>,
+            ) -> impl ::std::future::Future<Output = ::leaf_protocol::anyhow::Result<Self>> + ::std::marker::Send
+            {
+                async move {
+                    let mut components = entity
+                        .get_components_by_schemas(&<Self as ::leaf_protocol::EntityView>::schemas())
+                        .await?;
+                    <Self as ::leaf_protocol::EntityView>::from_components(&mut components)
+                }
+            }
+
+            fn apply<S: ::leaf_protocol::store::LeafStore>(
+                &self,
+                entity: &mut ::leaf_protocol::LoadedEntity<S>,
+            ) -> ::leaf_protocol::anyhow::Result<()> {
+                #(#applies)*
+                Ok(())
+            }
+        }
+    }
+    .into()
+}
+
+/// How a field of an `EntityView` maps to the components on the entity.
+enum FieldKind {
+    Required,
+    Optional,
+    Many,
+}
+
+/// Get the kind of an `EntityView` field and its component type from the field's type.
+fn field_kind(ty: &[TokenTree]) -> (FieldKind, proc_macro2::TokenStream) {
+    let is_punct = |tt: &TokenTree, c: char| matches!(tt, TokenTree::Punct(p) if p.as_char() == c);
+    let whole = || ty.iter().cloned().collect::<proc_macro2::TokenStream>();
+
+    // Look for a type like `Option<C>` or `std::vec::Vec<C>`.
+    let Some(open) = ty.iter().position(|tt| is_punct(tt, '<')) else {
+        return (FieldKind::Required, whole());
+    };
+    if open == 0 || !ty.last().map(|tt| is_punct(tt, '>')).unwrap_or(false) {
+        return (FieldKind::Required, whole());
+    }
+    let inner = ty[open + 1..ty.len() - 1]
+        .iter()
+        .cloned()
+        .collect::<proc_macro2::TokenStream>();
+    match &ty[open - 1] {
+        TokenTree::Ident(ident) if ident == "Option" => (FieldKind::Optional, inner),
+        TokenTree::Ident(ident) if ident == "Vec" => (FieldKind::Many, inner),
+        _ => (FieldKind::Required, whole()),
+    }
+}
@@ -0,0 +1,468 @@
+//! A streamable archive format for exporting and importing whole stores.
+//!
+//! An archive starts with an [`ArchiveHeader`], followed by a sequence of [`ArchiveRecord`]s and
+//! ends with an [`ArchiveRecord::End`] record. Each record is written as its length, as a
+//! little-endian `u32`, the [`Digest`] of the record, and then the borsh encoded record, so that
+//! archives can be written and read one record at a time without holding the whole store in
+//! memory.
+//!
+//! [`export_archive()`] writes the namespace and subspace secrets first, and then each entity
+//! as the blobs of its components, the blob of its snapshot, and an [`ArchiveRecord::Entity`]
+//! record. A blob is only written the first time it is used by an entity. Entities in the
+//! [trash][crate::trash] are archived too.
+//!
+//! Every entity is written with the [`EntityAuthorship`] that proves that the author of its
+//! subspace wrote it, which [`import_archive()`] checks before storing it. This lets an archive
+//! carry the entities of subspaces that we don't have the secret of, without anyone being able to
+//! forge them. Archives exported from the Iroh store carry the signed Iroh entries of their
+//! entities, so importing them into an Iroh store restores the entries exactly as their authors
+//! wrote them.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use borsh::{BorshDeserialize, BorshSerialize};
+use futures::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    pin_mut, TryStreamExt,
+};
+
+use crate::{
+    store::{EntityAuthorship, LeafStore},
+    types::{Entity, ExactLink, NamespaceSecretKey, SubspaceSecretKey},
+    Digest, EntityEntry, Leaf,
+};
+
+/// The bytes at the start of every archive.
+pub const ARCHIVE_MAGIC: [u8; 8] = *b"LEAFARCH";
+/// The version of the archive format written by this crate.
+pub const ARCHIVE_VERSION: u32 = 1;
+/// The largest record that will be read from an archive, to avoid allocating a huge buffer for a
+/// corrupted length.
+pub const MAX_RECORD_LEN: u32 = 512 * 1024 * 1024;
+
+/// The header at the start of an archive.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchiveHeader {
+    pub magic: [u8; 8],
+    pub version: u32,
+}
+
+impl Default for ArchiveHeader {
+    fn default() -> Self {
+        Self {
+            magic: ARCHIVE_MAGIC,
+            version: ARCHIVE_VERSION,
+        }
+    }
+}
+
+/// A record in an archive.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveRecord {
+    SubspaceSecret(SubspaceSecretKey),
+    NamespaceSecret(NamespaceSecretKey),
+    /// A component or entity snapshot blob, and its digest.
+    Blob {
+        digest: Digest,
+        data: Vec<u8>,
+    },
+    /// An entity, the digest of its snapshot, and the proof that the author of its subspace wrote
+    /// it.
+    ///
+    /// The snapshot and the components of the entity are written as blobs before the entity.
+    Entity {
+        link: ExactLink,
+        digest: Digest,
+        authorship: EntityAuthorship,
+    },
+    /// The end of the archive, with the total number of each kind of record, so that truncated
+    /// archives can be detected.
+    End(ArchiveProgress),
+}
+
+/// The number of each kind of record that has been written to or read from an archive.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArchiveProgress {
+    pub subspaces: u64,
+    pub namespaces: u64,
+    pub entities: u64,
+    pub blobs: u64,
+    /// The total size of the blobs, in bytes.
+    pub blob_bytes: u64,
+    /// The number of entities that were left out of the archive because we don't have their
+    /// authorship. When reading, this is only known once the end of the archive has been read.
+    pub skipped: u64,
+}
+
+impl ArchiveProgress {
+    fn count(&mut self, record: &ArchiveRecord) {
+        match record {
+            ArchiveRecord::SubspaceSecret(_) => self.subspaces += 1,
+            ArchiveRecord::NamespaceSecret(_) => self.namespaces += 1,
+            ArchiveRecord::Blob { data, .. } => {
+                self.blobs += 1;
+                self.blob_bytes += data.len() as u64;
+            }
+            ArchiveRecord::Entity { .. } => self.entities += 1,
+            ArchiveRecord::End(_) => (),
+        }
+    }
+}
+
+/// Writes an archive one record at a time.
+#[derive(Debug)]
+pub struct ArchiveWriter<W> {
+    writer: W,
+    progress: ArchiveProgress,
+}
+
+impl<W: AsyncWrite + Unpin> ArchiveWriter<W> {
+    /// Start an archive by writing the header to `writer`.
+    pub async fn new(mut writer: W) -> Result<Self> {
+        writer
+            .write_all(&borsh::to_vec(&ArchiveHeader::default())?)
+            .await?;
+        Ok(Self {
+            writer,
+            progress: ArchiveProgress::default(),
+        })
+    }
+
+    /// The number of records that have been written so far.
+    pub fn progress(&self) -> ArchiveProgress {
+        self.progress
+    }
+
+    /// Write a record to the archive.
+    pub async fn write_record(&mut self, record: &ArchiveRecord) -> Result<()> {
+        let data = borsh::to_vec(record)?;
+        let len = u32::try_from(data.len())
+            .ok()
+            .filter(|&len| len <= MAX_RECORD_LEN)
+            .ok_or_else(|| anyhow::format_err!("Archive record is too large: {}", data.len()))?;
+        self.writer.write_all(&len.to_le_bytes()).await?;
+        self.writer
+            .write_all(Digest::new(&data).0.as_bytes())
+            .await?;
+        self.writer.write_all(&data).await?;
+        self.progress.count(record);
+        Ok(())
+    }
+
+    /// Count an entity that was left out of the archive in [`ArchiveProgress::skipped`].
+    pub fn skip_entity(&mut self) {
+        self.progress.skipped += 1;
+    }
+
+    /// Write the [`ArchiveRecord::End`] record and flush the writer, returning it along with the
+    /// number of records that were written.
+    pub async fn finish(mut self) -> Result<(W, ArchiveProgress)> {
+        let progress = self.progress;
+        self.write_record(&ArchiveRecord::End(progress)).await?;
+        self.writer.flush().await?;
+        Ok((self.writer, progress))
+    }
+}
+
+/// Reads an archive one record at a time, verifying the digest of each record.
+#[derive(Debug)]
+pub struct ArchiveReader<R> {
+    reader: R,
+    progress: ArchiveProgress,
+    finished: bool,
+}
+
+impl<R: AsyncRead + Unpin> ArchiveReader<R> {
+    /// Start reading an archive, checking its header.
+    pub async fn new(mut reader: R) -> Result<Self> {
+        let mut buf = [0; 12];
+        reader.read_exact(&mut buf).await?;
+        let header = ArchiveHeader::try_from_slice(&buf)?;
+        if header.magic != ARCHIVE_MAGIC {
+            anyhow::bail!("Not a Leaf archive.");
+        }
+        if header.version != ARCHIVE_VERSION {
+            anyhow::bail!(
+                "Unsupported Leaf archive version {}, expected version {ARCHIVE_VERSION}.",
+                header.version
+            );
+        }
+        Ok(Self {
+            reader,
+            progress: ArchiveProgress::default(),
+            finished: false,
+        })
+    }
+
+    /// The number of records that have been read so far.
+    pub fn progress(&self) -> ArchiveProgress {
+        self.progress
+    }
+
+    /// Read the next record, or [`None`] once the [`ArchiveRecord::End`] record has been read.
+    ///
+    /// Returns an error if the archive ends early, if a record doesn't match its digest, or if a
+    /// blob doesn't match its digest.
+    pub async fn next_record(&mut self) -> Result<Option<ArchiveRecord>> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        let mut len = [0; 4];
+        self.read_exact(&mut len).await?;
+        let len = u32::from_le_bytes(len);
+        if len > MAX_RECORD_LEN {
+            anyhow::bail!("Archive record is too large: {len}");
+        }
+        let mut digest = [0; 32];
+        self.read_exact(&mut digest).await?;
+        let mut data = vec![0; len as usize];
+        self.read_exact(&mut data).await?;
+        if Digest::new(&data) != Digest::from_bytes(digest) {
+            anyhow::bail!("Archive record does not match its digest.");
+        }
+
+        let record = ArchiveRecord::try_from_slice(&data)?;
+        match &record {
+            ArchiveRecord::Blob { digest, data } if Digest::new(data) != *digest => {
+                anyhow::bail!("Archive blob does not match its digest: {digest}");
+            }
+            ArchiveRecord::End(expected) => {
+                self.progress.skipped = expected.skipped;
+                if *expected != self.progress {
+                    anyhow::bail!(
+                        "Archive is incomplete: expected {expected:?}, but read {:?}.",
+                        self.progress
+                    );
+                }
+                self.finished = true;
+                return Ok(None);
+            }
+            _ => (),
+        }
+        self.progress.count(&record);
+        Ok(Some(record))
+    }
+
+    async fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        match self.reader.read_exact(buf).await {
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Err(anyhow::format_err!(
+                "Archive is incomplete: it ended after {:?}.",
+                self.progress
+            )),
+            result => Ok(result?),
+        }
+    }
+}
+
+/// Export every namespace and subspace that we have the secret of, and all of the entities in
+/// those namespaces, to an archive.
+///
+/// Entities are exported from every subspace of the namespaces, including the subspaces that we
+/// don't have the secret of, as long as the store has the proof that their author wrote them.
+/// Entities without it are left out and counted in [`ArchiveProgress::skipped`].
+///
+/// `on_progress` is called after each entity is written. Returns the number of records that
+/// were written.
+pub async fn export_archive<S: LeafStore + Clone, W: AsyncWrite + Unpin>(
+    leaf: &Leaf<S>,
+    writer: W,
+    mut on_progress: impl FnMut(ArchiveProgress),
+) -> Result<ArchiveProgress> {
+    let mut archive = ArchiveWriter::new(writer).await?;
+
+    let stream = leaf.list_subspaces().await?;
+    pin_mut!(stream);
+    while let Some(subspace) = stream.try_next().await? {
+        let Some(secret) = leaf.get_subspace_secret(subspace).await? else {
+            continue;
+        };
+        archive
+            .write_record(&ArchiveRecord::SubspaceSecret(secret))
+            .await?;
+    }
+
+    let mut namespaces = Vec::new();
+    let stream = leaf.list_namespaces().await?;
+    pin_mut!(stream);
+    while let Some((namespace, _)) = stream.try_next().await? {
+        let Some(secret) = leaf.get_namespace_secret(namespace).await? else {
+            continue;
+        };
+        archive
+            .write_record(&ArchiveRecord::NamespaceSecret(secret))
+            .await?;
+        namespaces.push(namespace);
+    }
+
+    let mut written_blobs = HashSet::new();
+    for &namespace in &namespaces {
+        let subspaces = leaf
+            .store
+            .list_namespace_subspaces(namespace)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        for subspace in subspaces {
+            // List from the store so that the entities in the trash are archived too.
+            let root = (namespace, subspace, ()).into();
+            let stream = leaf.store.list(root, None, None, None).await?;
+            pin_mut!(stream);
+            while let Some(link) = stream.try_next().await? {
+                let Some(digest) = leaf.store.get_entity(&link).await? else {
+                    continue;
+                };
+                let Some(authorship) = leaf.store.get_entity_signature(&link).await? else {
+                    archive.skip_entity();
+                    continue;
+                };
+                let snapshot = leaf.store.get_blob(digest).await?;
+                let entity = Entity::deserialize(&mut &snapshot[..])?;
+                for entry in &entity.components {
+                    if written_blobs.insert(entry.component_id) {
+                        let data = leaf.store.get_blob(entry.component_id).await?;
+                        archive
+                            .write_record(&ArchiveRecord::Blob {
+                                digest: entry.component_id,
+                                data,
+                            })
+                            .await?;
+                    }
+                }
+                if written_blobs.insert(digest) {
+                    archive
+                        .write_record(&ArchiveRecord::Blob {
+                            digest,
+                            data: snapshot,
+                        })
+                        .await?;
+                }
+                archive
+                    .write_record(&ArchiveRecord::Entity {
+                        link,
+                        digest,
+                        authorship,
+                    })
+                    .await?;
+                on_progress(archive.progress());
+            }
+        }
+    }
+
+    let (_, progress) = archive.finish().await?;
+    Ok(progress)
+}
+
+/// Import an archive written by [`export_archive()`].
+///
+/// The digest of every record and blob is verified as it is read, and the authorship of each
+/// entity is checked before it is written to the store with [`LeafStore::store_signed_entity()`],
+/// replacing any existing entity at the same link. An archive with an invalid signature fails
+/// with an [`InvalidEntitySignature`][crate::store::InvalidEntitySignature] error, and the
+/// entities before it will already have been imported. The search, backlink, and quota indexes of
+/// `leaf` are updated for each entity if they are enabled, but quotas are not enforced.
+///
+/// `on_progress` is called after each entity is imported. Returns the number of records that were
+/// read.
+pub async fn import_archive<S: LeafStore + Clone, R: AsyncRead + Unpin>(
+    leaf: &Leaf<S>,
+    reader: R,
+    mut on_progress: impl FnMut(ArchiveProgress),
+) -> Result<ArchiveProgress> {
+    let mut archive = ArchiveReader::new(reader).await?;
+
+    // The blobs read since the last entity. Blobs that were written for an earlier entity have
+    // already been imported, so they are loaded from the store instead.
+    let mut blobs = HashMap::new();
+    while let Some(record) = archive.next_record().await? {
+        match record {
+            ArchiveRecord::SubspaceSecret(secret) => {
+                leaf.import_subspace_secret(secret).await?;
+            }
+            ArchiveRecord::NamespaceSecret(secret) => {
+                leaf.import_namespace_secret(secret).await?;
+            }
+            ArchiveRecord::Blob { digest, data } => {
+                blobs.insert(digest, data);
+            }
+            ArchiveRecord::Entity {
+                link,
+                digest,
+                authorship,
+            } => {
+                import_entity(leaf, &link, digest, authorship, &mut blobs).await?;
+                blobs.clear();
+                on_progress(archive.progress());
+            }
+            ArchiveRecord::End(_) => unreachable!("the end record is not returned by the reader"),
+        }
+    }
+
+    Ok(archive.progress())
+}
+
+async fn import_entity<S: LeafStore + Clone>(
+    leaf: &Leaf<S>,
+    link: &ExactLink,
+    digest: Digest,
+    authorship: EntityAuthorship,
+    blobs: &mut HashMap<Digest, Vec<u8>>,
+) -> Result<()> {
+    // Check the authorship before writing any blobs, so that a forged entity doesn't leave blob
+    // pins behind.
+    authorship.verify(link, digest)?;
+
+    let snapshot = match blobs.remove(&digest) {
+        Some(data) => data,
+        None => leaf.store.get_blob(digest).await?,
+    };
+    let entity = Entity::deserialize(&mut &snapshot[..])?;
+
+    let old_snapshot_id = leaf.store.get_entity(link).await?;
+    let mut size = snapshot.len() as u64;
+    for entry in &entity.components {
+        let data = match blobs.get(&entry.component_id) {
+            Some(data) => data.clone(),
+            None => leaf.store.get_blob(entry.component_id).await?,
+        };
+        size += data.len() as u64;
+        leaf.store.store_blob(&data, link, digest).await?;
+    }
+    let imported = leaf
+        .store
+        .store_signed_entity(link, snapshot, authorship)
+        .await?;
+    if imported != digest {
+        anyhow::bail!(
+            "Imported entity at {link:?} has digest {imported}, but the archive has {digest}."
+        );
+    }
+    if let Some(old_snapshot_id) = old_snapshot_id {
+        if old_snapshot_id != digest {
+            leaf.store.del_blobs(link, old_snapshot_id).await?;
+        }
+    }
+
+    if let Some(quotas) = &leaf.config.quotas {
+        quotas.set_entity_size(link, Some(size)).await?;
+    }
+    // Imported entities aren't checked against the unique constraints, like entities synced from
+    // other nodes, but they do hold their values from now on.
+    leaf.config.unique.index_entity(link, &entity.components);
+    if leaf.config.search.is_some() || leaf.config.backlinks.is_some() {
+        let EntityEntry::Entity(entity) = leaf.entity(link.clone()).await? else {
+            return Ok(());
+        };
+        if let Some(search) = &leaf.config.search {
+            let texts = search.entity_texts(&entity).await?;
+            search.index_entity(link, &texts);
+        }
+        if let Some(backlinks) = &leaf.config.backlinks {
+            let targets = backlinks.entity_links(&entity).await?;
+            backlinks.index_entity(link, targets).await?;
+        }
+    }
+
+    Ok(())
+}
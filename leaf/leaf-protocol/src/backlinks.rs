@@ -0,0 +1,366 @@
+//! Reverse index of the links found in component data.
+//!
+//! The [`BacklinkIndex`] finds the [`Link`]s inside of components by walking the [`BorshSchema`] of
+//! each indexed component type, and keeps track of which entities link to which. This makes it
+//! possible to answer questions like "what replies to this post?" without scanning the namespace.
+//!
+//! Like the [`SearchIndex`][crate::search::SearchIndex], a namespace is indexed the first time
+//! that backlinks into it are requested, and the index is kept up to date as entities are saved or
+//! deleted through the [`Leaf`][crate::Leaf] it is attached to. Changes from somewhere else are
+//! only picked up when the namespace is re-indexed with [`Leaf::reindex()`][crate::Leaf::reindex].
+//!
+//! With the `metadata` feature, the index can be persisted in a `redb` database with
+//! [`BacklinkIndex::with_database()`], so that namespaces don't have to be indexed again every
+//! time that the index is created.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Result;
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::{
+    components::{Embed, ReplyTo},
+    store::LeafStore,
+    types::{BorshSchema, EntityPath, ExactLink, KeyResolverKind, Link, NamespaceId},
+    Component, Digest, LoadedEntity,
+};
+
+#[cfg(feature = "metadata")]
+mod redb;
+
+/// An entity that links to another entity.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Backlink {
+    /// The entity that contains the link.
+    pub link: ExactLink,
+    /// The schema of the component that contains the link.
+    pub schema: Digest,
+}
+
+/// An in-memory reverse index of the links in the components of entities.
+///
+/// Cloning the index is cheap and the clones share the same underlying index.
+#[derive(Debug, Clone)]
+pub struct BacklinkIndex {
+    /// The schemas of the components to index, and their borsh schemas, which are used to find the
+    /// links inside of them.
+    schemas: HashMap<Digest, BorshSchema>,
+    inner: Arc<RwLock<BacklinkIndexInner>>,
+    /// The database that changes to the index are written to, if it is persisted.
+    #[cfg(feature = "metadata")]
+    db: Option<redb::BacklinkDb>,
+}
+
+#[derive(Debug, Default)]
+struct BacklinkIndexInner {
+    /// The namespaces that have been indexed.
+    namespaces: HashSet<NamespaceId>,
+    /// The entities that link to each entity.
+    backlinks: HashMap<ExactLink, HashSet<Backlink>>,
+    /// The entities that each entity links to, so that we can clean up its backlinks when it
+    /// changes.
+    forward: HashMap<ExactLink, Vec<ExactLink>>,
+}
+
+impl Default for BacklinkIndex {
+    /// Create a backlink index over the [`ReplyTo`] and [`Embed`] components.
+    fn default() -> Self {
+        Self::empty()
+            .with_component::<ReplyTo>()
+            .with_component::<Embed>()
+    }
+}
+
+impl BacklinkIndex {
+    /// Create a backlink index that doesn't index any components.
+    pub fn empty() -> Self {
+        Self {
+            schemas: Default::default(),
+            inner: Default::default(),
+            #[cfg(feature = "metadata")]
+            db: None,
+        }
+    }
+
+    /// Index the links in components with the given schema, using `borsh_schema` to find them.
+    pub fn with_schema(mut self, schema: Digest, borsh_schema: BorshSchema) -> Self {
+        self.schemas.insert(schema, borsh_schema);
+        // Anything that was indexed or loaded so far is missing the links in this schema.
+        self.inner = Default::default();
+        self
+    }
+
+    /// Persist the index in `db`, loading the namespaces that have already been indexed in it.
+    ///
+    /// Namespaces are only loaded if they were indexed with the same component schemas, so this
+    /// should be called after the components to index have been added. The index only uses tables
+    /// with names starting with `leaf_`, so the database may be shared with a
+    /// [`LeafMetadataStore`][crate::metadata::LeafMetadataStore] or a
+    /// [`LeafRedbStore`][crate::store::redb::LeafRedbStore].
+    ///
+    /// Changes that were made to the store while the index wasn't in use are only picked up by
+    /// re-indexing the namespace with [`Leaf::reindex()`][crate::Leaf::reindex].
+    #[cfg(feature = "metadata")]
+    pub fn with_database(mut self, db: Arc<::redb::Database>) -> Result<Self> {
+        let db = redb::BacklinkDb::new(db)?;
+        let (namespaces, entities) = db.load(&self.sorted_schemas())?;
+        let mut inner = BacklinkIndexInner::default();
+        for (link, targets) in entities {
+            inner.insert(link, targets);
+        }
+        inner.namespaces.extend(namespaces);
+        self.inner = Arc::new(RwLock::new(inner));
+        self.db = Some(db);
+        Ok(self)
+    }
+
+    #[cfg(feature = "metadata")]
+    fn sorted_schemas(&self) -> Vec<Digest> {
+        let mut schemas = self.schemas.keys().copied().collect::<Vec<_>>();
+        schemas.sort();
+        schemas
+    }
+
+    /// Index the links in the given component type.
+    pub fn with_component<C: Component>(self) -> Self {
+        self.with_schema(C::schema_id(), C::borsh_schema())
+    }
+
+    /// The schemas of the components that are indexed.
+    pub fn schemas(&self) -> impl Iterator<Item = Digest> + '_ {
+        self.schemas.keys().copied()
+    }
+
+    /// Whether the given namespace has been indexed yet.
+    pub fn is_indexed(&self, namespace: NamespaceId) -> bool {
+        self.inner.read().unwrap().namespaces.contains(&namespace)
+    }
+
+    /// Replace the index for the entities in a namespace with the given entities and their links.
+    pub async fn index_namespace(
+        &self,
+        namespace: NamespaceId,
+        entities: impl IntoIterator<Item = (ExactLink, Vec<Backlink>)>,
+    ) -> Result<()> {
+        let entities = entities.into_iter().collect::<Vec<_>>();
+        #[cfg(feature = "metadata")]
+        if let Some(db) = &self.db {
+            db.put_namespace(namespace, self.sorted_schemas(), entities.clone())
+                .await?;
+        }
+
+        let mut inner = self.inner.write().unwrap();
+        let stale = inner
+            .forward
+            .keys()
+            .filter(|x| x.namespace == namespace)
+            .cloned()
+            .collect::<Vec<_>>();
+        for link in stale {
+            inner.remove(&link);
+        }
+        for (link, targets) in entities {
+            inner.insert(link, targets);
+        }
+        inner.namespaces.insert(namespace);
+        Ok(())
+    }
+
+    /// Update the links from an entity.
+    ///
+    /// `targets` are the entities that it links to, with the [`Backlink::schema`] of the component
+    /// that the link was found in. This does nothing if the entity's namespace hasn't been indexed
+    /// yet.
+    pub async fn index_entity(&self, link: &ExactLink, targets: Vec<Backlink>) -> Result<()> {
+        {
+            let inner = self.inner.read().unwrap();
+            let unchanged = targets.is_empty() && !inner.forward.contains_key(link);
+            if !inner.namespaces.contains(&link.namespace) || unchanged {
+                return Ok(());
+            }
+        }
+        #[cfg(feature = "metadata")]
+        if let Some(db) = &self.db {
+            db.put_entity(link.clone(), targets.clone()).await?;
+        }
+
+        let mut inner = self.inner.write().unwrap();
+        inner.remove(link);
+        inner.insert(link.clone(), targets);
+        Ok(())
+    }
+
+    /// Remove the links from an entity.
+    pub async fn remove_entity(&self, link: &ExactLink) -> Result<()> {
+        if !self.inner.read().unwrap().forward.contains_key(link) {
+            return Ok(());
+        }
+        #[cfg(feature = "metadata")]
+        if let Some(db) = &self.db {
+            db.put_entity(link.clone(), Vec::new()).await?;
+        }
+
+        self.inner.write().unwrap().remove(link);
+        Ok(())
+    }
+
+    /// Get the entities that link to `link`, optionally only through components with the given
+    /// schema.
+    ///
+    /// Only links from entities in namespaces that have been indexed are returned.
+    pub fn backlinks(&self, link: &ExactLink, schema: Option<Digest>) -> Vec<Backlink> {
+        let inner = self.inner.read().unwrap();
+        let mut backlinks = inner
+            .backlinks
+            .get(link)
+            .into_iter()
+            .flatten()
+            .filter(|x| schema.map(|s| s == x.schema).unwrap_or(true))
+            .cloned()
+            .collect::<Vec<_>>();
+        backlinks.sort();
+        backlinks
+    }
+
+    /// Get the entities that an entity links to in its indexed components.
+    ///
+    /// The returned [`Backlink`]s point at the link targets.
+    pub(crate) async fn entity_links<S: LeafStore>(
+        &self,
+        entity: &LoadedEntity<S>,
+    ) -> Result<Vec<Backlink>> {
+        let schemas = self.schemas.keys().copied().collect::<Vec<_>>();
+        let components = entity.get_components_by_schemas(&schemas).await?;
+        let mut targets = Vec::new();
+        for (schema, borsh_schema) in &self.schemas {
+            for data in components.get(*schema) {
+                let mut links = Vec::new();
+                // Ignore components that don't match their schema, since we can't find the links
+                // in them.
+                if find_links(borsh_schema, &mut &data[..], &mut links).is_err() {
+                    continue;
+                }
+                targets.extend(links.iter().filter_map(resolve_link).map(|link| Backlink {
+                    link,
+                    schema: *schema,
+                }));
+            }
+        }
+        targets.sort();
+        targets.dedup();
+        Ok(targets)
+    }
+}
+
+impl BacklinkIndexInner {
+    fn insert(&mut self, link: ExactLink, targets: Vec<Backlink>) {
+        let mut forward = Vec::with_capacity(targets.len());
+        for target in targets {
+            self.backlinks
+                .entry(target.link.clone())
+                .or_default()
+                .insert(Backlink {
+                    link: link.clone(),
+                    schema: target.schema,
+                });
+            forward.push(target.link);
+        }
+        if !forward.is_empty() {
+            self.forward.insert(link, forward);
+        }
+    }
+
+    fn remove(&mut self, link: &ExactLink) {
+        let Some(targets) = self.forward.remove(link) else {
+            return;
+        };
+        for target in targets {
+            if let Some(backlinks) = self.backlinks.get_mut(&target) {
+                backlinks.retain(|x| &x.link != link);
+                if backlinks.is_empty() {
+                    self.backlinks.remove(&target);
+                }
+            }
+        }
+    }
+}
+
+/// Resolve a link to the entity it points to, if its namespace and subspace keys are inline.
+fn resolve_link(link: &Link) -> Option<ExactLink> {
+    let (KeyResolverKind::Inline(namespace), KeyResolverKind::Inline(subspace)) =
+        (&link.namespace, &link.subspace)
+    else {
+        return None;
+    };
+    Some(ExactLink {
+        namespace: *namespace,
+        subspace: *subspace,
+        path: EntityPath(link.path.clone()),
+    })
+}
+
+/// Walk over borsh `data` in the format described by `schema`, collecting all of the [`Link`]s
+/// inside it.
+fn find_links(schema: &BorshSchema, data: &mut &[u8], links: &mut Vec<Link>) -> Result<()> {
+    fn skip(data: &mut &[u8], len: usize) -> Result<()> {
+        if data.len() < len {
+            anyhow::bail!("Unexpected end of component data");
+        }
+        *data = &data[len..];
+        Ok(())
+    }
+
+    match schema {
+        BorshSchema::Null => (),
+        BorshSchema::Bool | BorshSchema::U8 | BorshSchema::I8 => skip(data, 1)?,
+        BorshSchema::U16 | BorshSchema::I16 => skip(data, 2)?,
+        BorshSchema::U32 | BorshSchema::I32 | BorshSchema::F32 => skip(data, 4)?,
+        BorshSchema::U64 | BorshSchema::I64 | BorshSchema::F64 => skip(data, 8)?,
+        BorshSchema::U128 | BorshSchema::I128 => skip(data, 16)?,
+        BorshSchema::String => {
+            let len = u32::deserialize(data)?;
+            skip(data, len as usize)?;
+        }
+        BorshSchema::Option { schema } => {
+            if u8::deserialize(data)? != 0 {
+                find_links(schema, data, links)?;
+            }
+        }
+        BorshSchema::Array { schema, len } => {
+            for _ in 0..*len {
+                find_links(schema, data, links)?;
+            }
+        }
+        BorshSchema::Struct { fields } => {
+            for (_, schema) in fields {
+                find_links(schema, data, links)?;
+            }
+        }
+        BorshSchema::Enum { variants } => {
+            let variant = u8::deserialize(data)?;
+            let Some((_, schema)) = variants.get(variant as usize) else {
+                anyhow::bail!("Invalid enum variant in component data");
+            };
+            find_links(schema, data, links)?;
+        }
+        BorshSchema::Vector { schema } | BorshSchema::Set { schema } => {
+            let len = u32::deserialize(data)?;
+            for _ in 0..len {
+                find_links(schema, data, links)?;
+            }
+        }
+        BorshSchema::Map { key, value } => {
+            let len = u32::deserialize(data)?;
+            for _ in 0..len {
+                find_links(key, data, links)?;
+                find_links(value, data, links)?;
+            }
+        }
+        BorshSchema::Blob | BorshSchema::Snapshot => skip(data, 32)?,
+        BorshSchema::Link => links.push(Link::deserialize(data)?),
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+//! A [`redb`] database that persists a [`BacklinkIndex`][super::BacklinkIndex].
+
+use std::{collections::HashSet, sync::Arc};
+
+use borsh::BorshDeserialize;
+use redb::{Database, ReadableTable, TableDefinition, WriteTransaction};
+
+use super::Backlink;
+use crate::{
+    types::{ExactLink, NamespaceId},
+    Digest,
+};
+
+/// The namespaces that have been indexed, with the sorted schemas that they were indexed with.
+const BACKLINK_NAMESPACES: TableDefinition<&[u8], &[u8]> =
+    TableDefinition::new("leaf_backlink_namespaces");
+/// The entities that each entity links to, keyed by the encoded [`ExactLink`] of the entity, which
+/// starts with its namespace.
+const BACKLINK_TARGETS: TableDefinition<&[u8], &[u8]> =
+    TableDefinition::new("leaf_backlink_targets");
+
+/// The namespaces and links loaded from a [`BacklinkDb`].
+pub(super) type LoadedBacklinks = (Vec<NamespaceId>, Vec<(ExactLink, Vec<Backlink>)>);
+
+/// The tables that a backlink index is persisted in.
+///
+/// Cloning the database is cheap and the clones share the same database.
+#[derive(Debug, Clone)]
+pub(super) struct BacklinkDb {
+    db: Arc<Database>,
+}
+
+impl BacklinkDb {
+    /// Use the backlink tables in `db`, creating them if they don't exist yet.
+    pub fn new(db: Arc<Database>) -> anyhow::Result<Self> {
+        let tx = db.begin_write()?;
+        {
+            // Make sure that the tables exist so that reads don't fail on a new database.
+            tx.open_table(BACKLINK_NAMESPACES)?;
+            tx.open_table(BACKLINK_TARGETS)?;
+        }
+        tx.commit()?;
+        Ok(Self { db })
+    }
+
+    /// Load the namespaces that were indexed with exactly the given sorted `schemas`, and the links
+    /// from the entities in them.
+    ///
+    /// Namespaces that were indexed with other schemas are left out, so that they are indexed
+    /// again.
+    pub fn load(&self, schemas: &[Digest]) -> anyhow::Result<LoadedBacklinks> {
+        let tx = self.db.begin_read()?;
+        let mut namespaces = HashSet::new();
+        for entry in tx.open_table(BACKLINK_NAMESPACES)?.iter()? {
+            let (namespace, indexed) = entry?;
+            if Vec::<Digest>::try_from_slice(indexed.value())? == schemas {
+                namespaces.insert(NamespaceId::try_from_slice(namespace.value())?);
+            }
+        }
+        let mut entities = Vec::new();
+        for entry in tx.open_table(BACKLINK_TARGETS)?.iter()? {
+            let (link, targets) = entry?;
+            let link = ExactLink::try_from_slice(link.value())?;
+            if namespaces.contains(&link.namespace) {
+                entities.push((link, Vec::<Backlink>::try_from_slice(targets.value())?));
+            }
+        }
+        Ok((namespaces.into_iter().collect(), entities))
+    }
+
+    /// Replace the links from the entities in a namespace, and record that it has been indexed with
+    /// the given sorted `schemas`.
+    pub async fn put_namespace(
+        &self,
+        namespace: NamespaceId,
+        schemas: Vec<Digest>,
+        entities: Vec<(ExactLink, Vec<Backlink>)>,
+    ) -> anyhow::Result<()> {
+        self.write(move |tx| {
+            let mut table = tx.open_table(BACKLINK_TARGETS)?;
+            let stale = table
+                .range::<&[u8]>(&namespace[..]..)?
+                .map(|entry| entry.map(|(key, _)| key.value().to_vec()))
+                .take_while(|key| {
+                    key.as_ref()
+                        .map(|key| key.starts_with(&namespace))
+                        .unwrap_or(true)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            for key in stale {
+                table.remove(&key[..])?;
+            }
+            for (link, targets) in entities {
+                if !targets.is_empty() {
+                    let link = borsh::to_vec(&link)?;
+                    table.insert(&link[..], &borsh::to_vec(&targets)?[..])?;
+                }
+            }
+            tx.open_table(BACKLINK_NAMESPACES)?
+                .insert(&namespace[..], &borsh::to_vec(&schemas)?[..])?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Set the links from an entity, removing them if `targets` is empty.
+    pub async fn put_entity(&self, link: ExactLink, targets: Vec<Backlink>) -> anyhow::Result<()> {
+        self.write(move |tx| {
+            let link = borsh::to_vec(&link)?;
+            let mut table = tx.open_table(BACKLINK_TARGETS)?;
+            if targets.is_empty() {
+                table.remove(&link[..])?;
+            } else {
+                table.insert(&link[..], &borsh::to_vec(&targets)?[..])?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Run a write transaction on a blocking thread, committing it if `f` succeeds.
+    async fn write<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&WriteTransaction) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || {
+            let tx = db.begin_write()?;
+            let value = f(&tx)?;
+            tx.commit()?;
+            Ok(value)
+        })
+        .await
+        .map_err(|_| anyhow::format_err!("Error executing database operation"))?
+    }
+}
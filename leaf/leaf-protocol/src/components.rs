@@ -4,7 +4,7 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use leaf_protocol_macros::HasBorshSchema;
 
 use crate::{
-    types::{BorshSchema, HasBorshSchema},
+    types::{BorshSchema, HasBorshSchema, Link},
     Component, Digest,
 };
 
@@ -24,47 +24,130 @@ pub struct Utf8(pub String);
 )]
 pub struct Name(pub String);
 
+/// A description of an entity, formatted as [`CommonMark`].
+///
+/// Usually this is a short description, often used for things like link previews or search-engine
+/// metadata, but there is no hard limit on its length.
 #[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Component, Debug)]
 #[component(
-    schema_id = "bt6wzovlhtmwnqk4o4pqpfutjkakfzaglvhg4pd26mmp3sgakg3a",
+    schema_id = "adoh2ouayyv6ywvy7cvcsnqvkpx72udgnjaq2yo4ddxrtgvszwsq",
     no_compute_schema_id
 )]
 pub struct Description(pub String);
 
-// #[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Component, Debug)]
-// #[component(
-//     specification = "leaf-schemas/DateCreated",
-//     schema_id = "6qrsqqeso44tmy4y2gpmb7o4x2j3kdzjeuypfnpvmc74zahhsiwa"
-// )]
-// pub struct DateCreated(pub u64);
+/// The time that the entity, or what it represents, was created, in seconds since the Unix epoch.
+#[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Component, Debug)]
+#[component(
+    schema_id = "w446roxsr6l3wclga7e4r65qvbhksawgg3l3doijeuoqj7k2xaya",
+    no_compute_schema_id
+)]
+pub struct DateCreated(pub u64);
 
-// #[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Component, Debug)]
-// #[component(
-//     specification = "leaf-schemas/DateUpdated",
-//     schema_id = "3qdqzps5y3zu4sc4vc737xkwzl6va2vatcmtxplgruu2fd4joeqq"
-// )]
-// pub struct DateUpdated(pub u64);
+impl DateCreated {
+    /// Create a [`DateCreated`] with the current time.
+    pub fn now() -> Self {
+        Self(unix_timestamp())
+    }
+}
 
-// #[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Component, Debug)]
-// #[component(
-//     specification = "leaf-schemas/CommonMark",
-//     schema_id = "uy5tkom2uchinxo5pyouwwobj4efhsvw7bws2rjkobl66je7cnbq"
-// )]
-// pub struct CommonMark(pub u64);
+/// The time that the entity, or what it represents, was updated, in seconds since the Unix epoch.
+#[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Component, Debug)]
+#[component(
+    schema_id = "kjyvrj2w4zsn5jk7rkghxpvuronxhpwdycpqbcqhfgvoezqb74qa",
+    no_compute_schema_id
+)]
+pub struct DateUpdated(pub u64);
 
-// #[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Component, Debug)]
-// #[component(
-//     specification = "leaf-schemas/ReplyTo",
-//     schema_id = "wodv3ehssd64ooyl7bgzocbyvznihzranh4b5lgi6e7uh6wvbczq"
-// )]
-// pub struct ReplyTo(pub Link);
+impl DateUpdated {
+    /// Create a [`DateUpdated`] with the current time.
+    pub fn now() -> Self {
+        Self(unix_timestamp())
+    }
+}
 
-// #[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Component, Debug)]
-// #[component(
-//     specification = "leaf-schemas/Embed",
-//     schema_id = "wvnxhyf4p3ido3pfvvfjth4g3qt7sjusf3plfrqpo44l7o6cfa4a"
-// )]
-// pub struct Embed(pub Link);
+/// Text formatted as [CommonMark](https://spec.commonmark.org/0.31.2/) markdown.
+#[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Component, Debug)]
+#[component(
+    schema_id = "tdpzll7gid2vpf7y3h24qubce3w3qkbuqj3brxvqrcmc7kaxur4q",
+    no_compute_schema_id
+)]
+pub struct CommonMark(pub String);
+
+/// Indicates that the entity is a reply to another entity, such as a chat message reply or a
+/// comment on a blog post.
+#[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Component, Debug)]
+#[component(
+    schema_id = "tx7rebqtmkaj2fcgrrpmrhgjwa3vcfrxzdmsxcibvrkaheagfqrq",
+    no_compute_schema_id
+)]
+pub struct ReplyTo(pub Link);
+
+/// Links to another entity that is meant to be embedded in this one.
+///
+/// Other components on the entity, such as its own [`Name`] or [`Description`], should take
+/// precedence over the ones on the embedded entity.
+#[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Component, Debug)]
+#[component(
+    schema_id = "en7dxldpu44xixvkv5zdvkw3shwiitbxuurevnivuiskxlwiklfa",
+    no_compute_schema_id
+)]
+pub struct Embed(pub Link);
+
+/// A raw image associated to the entity, such as its feature image, icon, or avatar.
+///
+/// The image data is stored inline in the component.
+#[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Component, Debug)]
+#[component(
+    schema_id = "fe3kk5lz2xqx4rxmbx6xjafxqkzujn7733fcfziyqrnwnozeanfq",
+    no_compute_schema_id
+)]
+pub struct RawImage {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// The username of the user represented by the entity.
+#[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Component, Debug)]
+#[component(
+    schema_id = "35zvuztcmdvq6mpbpyrgp2dmtzedfy6643zpaijvpj7n6uwkeqda",
+    no_compute_schema_id
+)]
+pub struct Username(pub String);
+
+/// A list of string tags associated to the entity, such as hashtags.
+///
+/// There is no restriction on the format of a tag. Any valid UTF-8 is accepted.
+#[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Component, Debug)]
+#[component(
+    schema_id = "wafvtijriv4qg67evyhugpbhcphauvcgtf6vhkw63jda2pw4fgxa",
+    no_compute_schema_id
+)]
+pub struct Tags(pub Vec<String>);
+
+/// A list of web links associated to the entity.
+#[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Component, Debug)]
+#[component(
+    schema_id = "bsfeqcp3o3btlzacbgpcxib56xhyeocla77s3dowrttvbkiqsarq",
+    no_compute_schema_id
+)]
+pub struct WebLinks(pub Vec<WebLink>);
+
+/// A single link in a [`WebLinks`] component.
+#[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Debug)]
+pub struct WebLink {
+    /// An optional label for the link.
+    pub label: Option<String>,
+    /// The URL, which must be a valid URL.
+    pub url: String,
+}
+
+/// Get the current time in seconds since the Unix epoch.
+fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0)
+}
 
 // #[derive(BorshDeserialize, BorshSerialize, HasBorshSchema, Component, Debug)]
 // #[component(
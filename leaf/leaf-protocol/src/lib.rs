@@ -2,31 +2,58 @@
 //!
 //! [lp]: https://github.com/muni-town/agentic-fediverse/blob/49791e6b3ec1df5e0a8604476417e88eed1f9497/leaf-protocol-draft.md
 
+pub mod archive;
+pub mod backlinks;
 pub mod components;
+pub mod merge;
+pub mod metadata;
+pub mod quota;
+pub mod search;
 pub mod store;
+pub mod trash;
+pub mod unique;
+pub mod view;
 pub use leaf_protocol_types as types;
 use leaf_protocol_types::Digest;
 
+use std::{sync::Arc, time::Duration};
+
 use anyhow::Result;
 use borsh::{BorshDeserialize, BorshSerialize};
 
+pub use anyhow;
 pub use borsh;
 
-use futures::stream::Stream;
+use backlinks::{Backlink, BacklinkIndex};
+use futures::{pin_mut, stream::Stream, StreamExt, TryStreamExt};
 pub use leaf_protocol_macros::*;
-use store::LeafStore;
+use merge::{MergePolicies, MergedEntity};
+use quota::{SubspaceQuotas, SubspaceUsage};
+use search::{SearchIndex, SearchResult};
+use store::{LeafStore, NamespaceCapability, ShareMode, SyncPeer};
+use trash::TrashEntry;
 use types::{
-    ComponentData, ComponentEntry, ComponentKind, Entity, ExactLink, NamespaceId,
+    ComponentData, ComponentEntry, ComponentKind, Entity, EntityPath, ExactLink, NamespaceId,
     NamespaceSecretKey, SubspaceId, SubspaceSecretKey,
 };
+use unique::{UniqueConstraint, UniqueConstraintViolation, UniqueConstraints, UniqueScope};
+pub use view::{ComponentMap, EntityView};
 
 #[cfg(feature = "backend_iroh")]
 pub use iroh;
 
 pub mod prelude {
     pub use crate::components::*;
+    #[cfg(feature = "cache")]
+    pub use crate::store::cache::*;
+    #[cfg(feature = "instrument")]
+    pub use crate::store::instrument::*;
     #[cfg(feature = "backend_iroh")]
     pub use crate::store::iroh::*;
+    #[cfg(feature = "backend_memory")]
+    pub use crate::store::memory::*;
+    #[cfg(feature = "backend_redb")]
+    pub use crate::store::redb::*;
     pub use crate::store::{EncryptionAlgorithmImpl, KeyResolverImpl, LeafStore};
     pub use crate::types::*;
     pub use crate::*;
@@ -55,11 +82,36 @@ pub trait Component: types::HasBorshSchema + BorshDeserialize + BorshSerialize {
 pub struct Leaf<Store: LeafStore> {
     /// The backend store.
     pub store: Store,
+    /// The configuration shared with every entity loaded from this leaf store.
+    pub config: Arc<LeafConfig>,
+}
+
+/// Configuration for a [`Leaf`] store that also applies to the [`LoadedEntity`]s loaded from it.
+#[derive(Clone, Debug, Default)]
+pub struct LeafConfig {
+    /// Component schemas that must have unique values within a namespace or subspace.
+    pub unique: UniqueConstraints,
+    /// The full-text search index, if search is enabled.
+    pub search: Option<SearchIndex>,
+    /// The index of links between entities, if backlinks are enabled.
+    pub backlinks: Option<BacklinkIndex>,
+    /// Whether to maintain the [`DateCreated`][components::DateCreated] and
+    /// [`DateUpdated`][components::DateUpdated] components every time an entity is saved.
+    pub timestamps: bool,
+    /// The storage quotas of each subspace, if quotas are enabled.
+    pub quotas: Option<SubspaceQuotas>,
+    /// How long deleted entities are kept in the trash before they are purged, if the trash is
+    /// enabled.
+    pub trash_retention: Option<Duration>,
 }
 
 pub enum EntityEntry<S: LeafStore> {
     Entity(LoadedEntity<S>),
-    Empty { link: ExactLink, store: S },
+    Empty {
+        link: ExactLink,
+        store: S,
+        config: Arc<LeafConfig>,
+    },
 }
 
 impl<S: LeafStore> EntityEntry<S> {
@@ -77,8 +129,13 @@ impl<S: LeafStore> EntityEntry<S> {
     pub fn get_or_init(self) -> LoadedEntity<S> {
         match self {
             EntityEntry::Entity(e) => e,
-            EntityEntry::Empty { link, store } => LoadedEntity {
+            EntityEntry::Empty {
+                link,
+                store,
+                config,
+            } => LoadedEntity {
                 store,
+                config,
                 link,
                 entity: Entity::default(),
                 digest: Digest::from_bytes([0; 32]),
@@ -88,9 +145,17 @@ impl<S: LeafStore> EntityEntry<S> {
     }
 }
 
+/// A component that is being written by [`LoadedEntity::save()`].
+struct PendingComponent {
+    schema: Option<Digest>,
+    data_hash: Digest,
+    data: Vec<u8>,
+}
+
 #[derive(Debug)]
 pub struct LoadedEntity<S: LeafStore> {
     pub store: S,
+    pub config: Arc<LeafConfig>,
     pub link: ExactLink,
     pub entity: Entity,
     /// The digest of the entity. This may be a null digest if you have just called
@@ -126,39 +191,24 @@ impl<S: LeafStore> LoadedEntity<S> {
     /// Get the first component of a given type on the entity, or [`None`] if there is no component
     /// of that type.
     pub async fn get_component<C: Component>(&self) -> Result<Option<C>> {
-        for comp in &self.pending_components {
-            if let Some(comp) = comp.unencrypted() {
-                if comp.schema == C::schema_id() {
-                    return Ok(Some(C::deserialize(&mut &comp.data[..])?));
-                }
-            }
-        }
-        for entry in &self.entity.components {
-            if entry.schema_id == Some(C::schema_id()) {
-                let data = self.store.get_blob(entry.component_id).await?;
-                return Ok(Some(C::deserialize(&mut &data[..])?));
-            }
-        }
-        Ok(None)
+        let Some(data) = self
+            .get_components_by_schema(C::schema_id())
+            .await?
+            .into_iter()
+            .next()
+        else {
+            return Ok(None);
+        };
+        Ok(Some(C::deserialize(&mut &data[..])?))
     }
 
     /// Get all components of the given type on the entity.
     pub async fn get_components<C: Component>(&self) -> Result<Vec<C>> {
-        let mut res = Vec::new();
-        for comp in &self.pending_components {
-            if let Some(comp) = comp.unencrypted() {
-                if comp.schema == C::schema_id() {
-                    res.push(C::deserialize(&mut &comp.data[..])?);
-                }
-            }
-        }
-        for entry in &self.entity.components {
-            if entry.schema_id == Some(C::schema_id()) {
-                let data = self.store.get_blob(entry.component_id).await?;
-                res.push(C::deserialize(&mut &data[..])?);
-            }
-        }
-        Ok(res)
+        self.get_components_by_schema(C::schema_id())
+            .await?
+            .into_iter()
+            .map(|data| Ok(C::deserialize(&mut &data[..])?))
+            .collect()
     }
 
     pub async fn get_components_by_schema(&self, schema: Digest) -> Result<Vec<Vec<u8>>> {
@@ -184,6 +234,45 @@ impl<S: LeafStore> LoadedEntity<S> {
         Ok(res)
     }
 
+    /// Get the data of all components with any of the given schemas, grouped by schema.
+    ///
+    /// The component blobs are fetched concurrently.
+    pub async fn get_components_by_schemas(&self, schemas: &[Digest]) -> Result<ComponentMap> {
+        let mut map = ComponentMap::default();
+        for comp in &self.pending_components {
+            if let Some(comp) = comp.unencrypted() {
+                if schemas.contains(&comp.schema) {
+                    map.0
+                        .entry(comp.schema)
+                        .or_default()
+                        .push(comp.data.clone());
+                }
+            }
+        }
+        let blobs = futures::future::try_join_all(
+            self.entity
+                .components
+                .iter()
+                .filter_map(|entry| {
+                    let schema = entry.schema_id.filter(|x| schemas.contains(x))?;
+                    Some((schema, entry))
+                })
+                .map(|(schema, entry)| async move {
+                    let data = self.store.get_blob(entry.component_id).await?;
+                    anyhow::Ok((schema, ComponentKind::deserialize(&mut &data[..])?))
+                }),
+        )
+        .await?;
+        for (schema, component_kind) in blobs {
+            if let ComponentKind::Unencrypted(data) = component_kind {
+                if schema == data.schema {
+                    map.0.entry(schema).or_default().push(data.data);
+                }
+            }
+        }
+        Ok(map)
+    }
+
     /// Remove all components of the same type that are already on the entity, and then add the
     /// component to the entity.
     ///
@@ -219,20 +308,69 @@ impl<S: LeafStore> LoadedEntity<S> {
 
     /// Persist updates made to this entity's components, writing updated entity and components to
     /// the store.
+    ///
+    /// Returns a [`UniqueConstraintViolation`] error, without writing anything, if one of the added
+    /// components must be unique and another entity already has a component with the same value.
+    ///
+    /// Returns a [`QuotaExceeded`][quota::QuotaExceeded] error, also without writing anything, if
+    /// quotas are enabled with [`Leaf::with_quotas()`] and saving the entity would exceed the quota
+    /// of its subspace.
+    ///
+    /// If timestamps are enabled with [`Leaf::with_timestamps()`] the timestamp components will
+    /// also be updated.
     pub async fn save(&mut self) -> anyhow::Result<()> {
-        // Clean up old blob pins if there was a previous version of this entity.
-        if let Some(old_snapshot_id) = self.store.get_entity(&self.link).await? {
-            self.store.del_blobs(&self.link, old_snapshot_id).await?;
+        self.save_inner(self.config.timestamps).await
+    }
+
+    /// Save the entity like [`save()`][Self::save], but also stamp the timestamp components, even
+    /// if timestamps are not enabled on the [`Leaf`].
+    ///
+    /// See [`Leaf::with_timestamps()`] for details.
+    pub async fn save_with_timestamps(&mut self) -> anyhow::Result<()> {
+        self.save_inner(true).await
+    }
+
+    /// Add a [`DateCreated`][components::DateCreated] component if the entity doesn't have one,
+    /// and replace the [`DateUpdated`][components::DateUpdated] component with the current time,
+    /// but only if the entity has changed since it was last saved.
+    ///
+    /// Timestamp components that have been added but not saved yet are left alone, so that they
+    /// can be set explicitly, when importing an entity for example.
+    async fn stamp_timestamps(&mut self) -> Result<()> {
+        let has_pending = |this: &Self, schema: Digest| {
+            this.pending_components
+                .iter()
+                .any(|x| x.unencrypted().map(|x| x.schema == schema).unwrap_or(false))
+        };
+
+        // Compare the snapshot that would be saved with the stored one, so that re-adding
+        // components with the values that they already have doesn't count as a change.
+        let (_, snapshot) = self.pending_snapshot()?;
+        let changed = self.store.get_entity(&self.link).await? != Some(snapshot.compute_digest());
+        if !changed {
+            return Ok(());
         }
 
-        struct PendingComponent {
-            schema: Option<Digest>,
-            data_hash: Digest,
-            data: Vec<u8>,
+        let date_created = components::DateCreated::schema_id();
+        let has_date_created = has_pending(self, date_created)
+            || self
+                .entity
+                .components
+                .iter()
+                .any(|x| x.schema_id == Some(date_created));
+        if !has_date_created {
+            self.add_component(components::DateCreated::now())?;
+        }
+        if !has_pending(self, components::DateUpdated::schema_id()) {
+            self.set_component(components::DateUpdated::now())?;
         }
-        let mut pending_components =
-            Vec::with_capacity(self.pending_components.len() + self.entity.components.len());
 
+        Ok(())
+    }
+
+    /// Serialize the pending components, and build the snapshot of the entity with them added.
+    fn pending_snapshot(&self) -> Result<(Vec<PendingComponent>, Entity)> {
+        let mut pending_components = Vec::with_capacity(self.pending_components.len());
         for component in &self.pending_components {
             let mut buf = Vec::new();
             component.serialize(&mut buf)?;
@@ -244,23 +382,185 @@ impl<S: LeafStore> LoadedEntity<S> {
             });
         }
 
-        let mut new_entity_snapshot = Entity::default();
-        new_entity_snapshot
+        let mut snapshot = Entity::default();
+        snapshot
             .components
             .extend(self.entity.components.iter().cloned());
-        new_entity_snapshot
+        snapshot
             .components
             .extend(pending_components.iter().map(|x| ComponentEntry {
                 schema_id: x.schema,
                 component_id: x.data_hash,
             }));
-        new_entity_snapshot.components.sort();
-        new_entity_snapshot.components.dedup();
+        snapshot.components.sort();
+        snapshot.components.dedup();
+        Ok((pending_components, snapshot))
+    }
+
+    async fn save_inner(&mut self, timestamps: bool) -> anyhow::Result<()> {
+        trash::check_not_trashed(&self.link)?;
+        if timestamps {
+            self.stamp_timestamps().await?;
+        }
+
+        // Hold the unique constraint lock for the rest of the save if we are adding any unique
+        // components, so that a concurrent save can't claim the same value after we check it.
+        let adds_unique_components = self.pending_components.iter().any(|comp| {
+            comp.unencrypted()
+                .map(|x| self.config.unique.scope(x.schema).is_some())
+                .unwrap_or(false)
+        });
+        let _unique_guard = if adds_unique_components {
+            Some(self.config.unique.lock().lock().await)
+        } else {
+            None
+        };
+
+        let (pending_components, new_entity_snapshot) = self.pending_snapshot()?;
         let mut new_entity_snapshot_buf = Vec::new();
         new_entity_snapshot.serialize(&mut new_entity_snapshot_buf)?;
 
         let new_entity_snapshot_id = Digest::new(&new_entity_snapshot_buf);
 
+        for comp in &pending_components {
+            let Some(schema) = comp.schema else {
+                continue;
+            };
+            let Some(scope) = self.config.unique.scope(schema) else {
+                continue;
+            };
+            let entry = ComponentEntry {
+                schema_id: Some(schema),
+                component_id: comp.data_hash,
+            };
+            if let Some(existing) = self
+                .config
+                .unique
+                .find_holder(&self.store, &self.link, scope, entry)
+                .await?
+            {
+                return Err(UniqueConstraintViolation {
+                    schema,
+                    scope,
+                    existing,
+                }
+                .into());
+            }
+        }
+
+        if let Some(quotas) = &self.config.quotas {
+            for comp in &pending_components {
+                quotas.check_component(&self.link, comp.data.len() as u64)?;
+            }
+        }
+
+        let old_snapshot_id = self.store.get_entity(&self.link).await?;
+        if old_snapshot_id == Some(new_entity_snapshot_id) {
+            // Nothing changed, and the blobs of the snapshot are already pinned to it.
+            self.pending_components.clear();
+            self.entity = new_entity_snapshot;
+            self.digest = new_entity_snapshot_id;
+            return Ok(());
+        }
+
+        // Pins belong to a snapshot, so the components that we are keeping from the previous
+        // version of the entity have to be pinned to the new snapshot too. Otherwise they would be
+        // left unpinned, and garbage collected, when the pins of the old snapshot are deleted
+        // below. The blobs are already in the store, but it can only pin a blob by storing it, so
+        // they are loaded first.
+        let mut kept_components = Vec::new();
+        for entry in &self.entity.components {
+            if pending_components
+                .iter()
+                .any(|x| x.data_hash == entry.component_id)
+            {
+                continue;
+            }
+            kept_components.push(self.store.get_blob(entry.component_id).await?);
+        }
+
+        // Reserve the space for the new version of the entity in its subspace's quota.
+        let previous_size = match &self.config.quotas {
+            Some(quotas) => {
+                if quotas
+                    .usage(self.link.namespace, self.link.subspace)
+                    .is_none()
+                {
+                    let sizes =
+                        quota::entity_sizes(&self.store, self.link.namespace, self.link.subspace)
+                            .await?;
+                    quotas
+                        .count_subspace(self.link.namespace, self.link.subspace, sizes)
+                        .await?;
+                }
+                let size = new_entity_snapshot_buf.len()
+                    + kept_components.iter().map(|x| x.len()).sum::<usize>()
+                    + pending_components
+                        .iter()
+                        .map(|x| x.data.len())
+                        .sum::<usize>();
+                Some(quotas.reserve_entity_size(&self.link, size as u64).await?)
+            }
+            None => None,
+        };
+
+        let written = self
+            .write_snapshot(
+                kept_components,
+                pending_components,
+                new_entity_snapshot_buf,
+                new_entity_snapshot_id,
+            )
+            .await;
+        if let Err(e) = written {
+            // Give back the space that we reserved, since the new version wasn't saved.
+            if let (Some(quotas), Some(previous_size)) = (&self.config.quotas, previous_size) {
+                quotas.set_entity_size(&self.link, previous_size).await?;
+            }
+            return Err(e);
+        }
+
+        // Clean up old blob pins if there was a previous version of this entity. This happens after
+        // the new pins are written so that blobs shared by both versions are never unpinned.
+        if let Some(old_snapshot_id) = old_snapshot_id {
+            if old_snapshot_id != new_entity_snapshot_id {
+                self.store.del_blobs(&self.link, old_snapshot_id).await?;
+            }
+        }
+
+        self.pending_components.clear();
+        self.entity = new_entity_snapshot;
+        self.digest = new_entity_snapshot_id;
+
+        self.config
+            .unique
+            .index_entity(&self.link, &self.entity.components);
+        if let Some(search) = &self.config.search {
+            let texts = search.entity_texts(self).await?;
+            search.index_entity(&self.link, &texts);
+        }
+        if let Some(backlinks) = &self.config.backlinks {
+            let targets = backlinks.entity_links(self).await?;
+            backlinks.index_entity(&self.link, targets).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the blobs and the snapshot of a new version of the entity.
+    async fn write_snapshot(
+        &self,
+        kept_components: Vec<Vec<u8>>,
+        pending_components: Vec<PendingComponent>,
+        new_entity_snapshot_buf: Vec<u8>,
+        new_entity_snapshot_id: Digest,
+    ) -> anyhow::Result<()> {
+        for data in kept_components {
+            self.store
+                .store_blob(&data, &self.link, new_entity_snapshot_id)
+                .await?;
+        }
+
         for comp in pending_components {
             // TODO: Make sure that snapshots and link snapshots are added to the blob store pins.
             //
@@ -285,34 +585,135 @@ impl<S: LeafStore> LoadedEntity<S> {
             verification_digest, new_entity_snapshot_id,
             "Entity snapshot digest incorrect"
         );
-        self.pending_components.clear();
-        self.entity = new_entity_snapshot;
-        self.digest = new_entity_snapshot_id;
-
         Ok(())
     }
 
     /// Delete the entity. Changes are immediately written to the store.
+    ///
+    /// If the trash is enabled with [`Leaf::with_trash()`], the entity is moved into the trash
+    /// instead. Entities that are in the trash can't be deleted, only purged with
+    /// [`Leaf::purge_trash()`].
     pub async fn delete(&mut self) -> anyhow::Result<()> {
+        trash::check_not_trashed(&self.link)?;
         if let Some(old_snapshot_id) = self.store.get_entity(&self.link).await? {
-            // Clean up old blob pins
-            self.store.del_blobs(&self.link, old_snapshot_id).await?;
-            // Delete the entity
-            self.store.del_entity(&self.link).await?;
+            if self.config.trash_retention.is_some() {
+                trash::move_to_trash(&self.store, &self.config, &self.link, old_snapshot_id)
+                    .await?;
+            } else {
+                // Clean up old blob pins
+                self.store.del_blobs(&self.link, old_snapshot_id).await?;
+                // Delete the entity
+                self.store.del_entity(&self.link).await?;
+
+                if let Some(quotas) = &self.config.quotas {
+                    quotas.set_entity_size(&self.link, None).await?;
+                }
+            }
 
             // Clear the components on this entity handle
             self.entity.components.clear();
+
+            self.config.unique.remove_entity(&self.link);
+            if let Some(search) = &self.config.search {
+                search.remove_entity(&self.link);
+            }
+            if let Some(backlinks) = &self.config.backlinks {
+                backlinks.remove_entity(&self.link).await?;
+            }
         }
         Ok(())
     }
 }
 
+/// A page of entities returned by [`Leaf::list_page()`].
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default)]
+pub struct EntityPage {
+    /// The entities in this page.
+    pub entities: Vec<ExactLink>,
+    /// The cursor for the next page, to be passed as the `after` argument when listing, or
+    /// [`None`] if this is the last page.
+    pub next: Option<EntityPath>,
+}
+
 // TODO: Store schema data in the network somehow, instead of just the schema hashes.
 
 impl<S: store::LeafStore + Clone> Leaf<S> {
     /// Create a new leaf store around the given backend store.
     pub fn new(store: S) -> Self {
-        Self { store }
+        Self {
+            store,
+            config: Default::default(),
+        }
+    }
+
+    /// Require components with the given schema to have unique values within `scope`.
+    ///
+    /// Saving an entity that would share a unique component value with another entity in the
+    /// same scope will fail with a [`UniqueConstraintViolation`]. Only saves made through this
+    /// process are checked against each other, so values synced from other nodes can still clash.
+    /// See the [`unique`] module for details.
+    pub fn with_unique_constraint(mut self, constraint: UniqueConstraint) -> Self {
+        Arc::make_mut(&mut self.config).unique.insert(constraint);
+        self
+    }
+
+    /// Enable full-text search using the given index.
+    ///
+    /// See [`search()`][Self::search].
+    pub fn with_search_index(mut self, index: SearchIndex) -> Self {
+        Arc::make_mut(&mut self.config).search = Some(index);
+        self
+    }
+
+    /// Automatically maintain the [`DateCreated`][components::DateCreated] and
+    /// [`DateUpdated`][components::DateUpdated] components when entities are saved.
+    ///
+    /// When an entity with changes is saved, it is given a `DateCreated` component if it doesn't
+    /// already have one, and its `DateUpdated` component is replaced with the current time. Saving
+    /// an entity without any changes leaves it untouched.
+    ///
+    /// Timestamps can also be enabled for a single save with
+    /// [`LoadedEntity::save_with_timestamps()`].
+    pub fn with_timestamps(mut self) -> Self {
+        Arc::make_mut(&mut self.config).timestamps = true;
+        self
+    }
+
+    /// Enable the index of links between entities.
+    ///
+    /// See [`backlinks()`][Self::backlinks].
+    pub fn with_backlink_index(mut self, index: BacklinkIndex) -> Self {
+        Arc::make_mut(&mut self.config).backlinks = Some(index);
+        self
+    }
+
+    /// Move deleted entities into the trash, where they are kept for `retention` before they are
+    /// purged.
+    ///
+    /// See the [`trash`] module for details.
+    pub fn with_trash(mut self, retention: Duration) -> Self {
+        Arc::make_mut(&mut self.config).trash_retention = Some(retention);
+        self
+    }
+
+    /// Limit how much may be stored in each subspace.
+    ///
+    /// Saving an entity that would exceed the quota of its subspace will fail with a
+    /// [`QuotaExceeded`][quota::QuotaExceeded] error. See
+    /// [`subspace_usage()`][Self::subspace_usage].
+    pub fn with_quotas(mut self, quotas: SubspaceQuotas) -> Self {
+        Arc::make_mut(&mut self.config).quotas = Some(quotas);
+        self
+    }
+
+    /// Require components of type `C` to have unique values within `scope`.
+    ///
+    /// See [`with_unique_constraint()`][Self::with_unique_constraint].
+    pub fn with_unique_component<C: Component>(self, scope: UniqueScope) -> Self {
+        self.with_unique_constraint(UniqueConstraint {
+            schema: C::schema_id(),
+            scope,
+        })
     }
 
     pub async fn create_subspace(&self) -> Result<SubspaceId> {
@@ -340,6 +741,24 @@ impl<S: store::LeafStore + Clone> Leaf<S> {
         self.store.get_namespace_secret(namespace).await
     }
 
+    /// Create a ticket that another node can use to join and sync a namespace with
+    /// [`join_namespace()`][Self::join_namespace].
+    ///
+    /// A [`ShareMode::Write`] ticket contains the namespace secret, so it should only be given to
+    /// nodes that are trusted to write to the namespace.
+    pub async fn share_namespace(&self, namespace: NamespaceId, mode: ShareMode) -> Result<String> {
+        self.store.share_namespace(namespace, mode).await
+    }
+    /// Join a namespace with a ticket from [`share_namespace()`][Self::share_namespace], and start
+    /// syncing it with the nodes in the ticket.
+    pub async fn join_namespace(&self, ticket: &str) -> Result<NamespaceId> {
+        self.store.join_namespace(ticket).await
+    }
+    /// List the nodes that a namespace has been synced with, and how we are connected to them.
+    pub async fn list_sync_peers(&self, namespace: NamespaceId) -> Result<Vec<SyncPeer>> {
+        self.store.list_sync_peers(namespace).await
+    }
+
     /// Load an entity entry
     pub async fn entity<L: Into<ExactLink>>(&self, link: L) -> Result<EntityEntry<S>> {
         let link = link.into();
@@ -347,6 +766,7 @@ impl<S: store::LeafStore + Clone> Leaf<S> {
             return Ok(EntityEntry::Empty {
                 link,
                 store: self.store.clone(),
+                config: self.config.clone(),
             });
         };
         let bytes = self.store.get_blob(digest).await?;
@@ -354,6 +774,7 @@ impl<S: store::LeafStore + Clone> Leaf<S> {
 
         Ok(EntityEntry::Entity(LoadedEntity {
             store: self.store.clone(),
+            config: self.config.clone(),
             link,
             entity,
             digest,
@@ -361,30 +782,447 @@ impl<S: store::LeafStore + Clone> Leaf<S> {
         }))
     }
 
+    /// Get a merged view of the entities at `path` in each of the given `subspaces`.
+    ///
+    /// The subspaces are given in merge order, so with the
+    /// [`FirstWins`][merge::MergePolicy::FirstWins] policy, components from earlier subspaces take
+    /// precedence. Subspaces that don't have an entity at the path are skipped, and [`None`] is
+    /// returned if none of them do.
+    pub async fn merged_entity<P: Into<EntityPath>>(
+        &self,
+        namespace: NamespaceId,
+        path: P,
+        subspaces: &[SubspaceId],
+        policies: &MergePolicies,
+    ) -> Result<Option<MergedEntity<S>>> {
+        let path = path.into();
+        let mut entities = Vec::new();
+        for &subspace in subspaces {
+            let link = ExactLink {
+                namespace,
+                subspace,
+                path: path.clone(),
+            };
+            let Some(digest) = self.store.get_entity(&link).await? else {
+                continue;
+            };
+            let bytes = self.store.get_blob(digest).await?;
+            let entity = Entity::deserialize(&mut &bytes[..])?;
+            entities.push((subspace, entity.components));
+        }
+        if entities.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(MergedEntity::merge(
+            self.store.clone(),
+            namespace,
+            path,
+            entities,
+            policies,
+        )))
+    }
+
+    /// Delete the entity at `link`.
+    ///
+    /// If the trash is enabled with [`with_trash()`][Self::with_trash], the entity is moved into
+    /// the trash instead. Entities that are in the trash can't be deleted, only purged with
+    /// [`purge_trash()`][Self::purge_trash].
     pub async fn del_entity<L: Into<ExactLink>>(&self, link: L) -> Result<()> {
         let link = link.into();
-        if let Some(digest) = self.store.get_entity(&link).await? {
-            self.store.del_blobs(&link, digest).await?;
+        trash::check_not_trashed(&link)?;
+        match self.store.get_entity(&link).await? {
+            Some(digest) if self.config.trash_retention.is_some() => {
+                trash::move_to_trash(&self.store, &self.config, &link, digest).await?;
+                self.unindex_entity(&link).await
+            }
+            _ => self.purge_entity(&link).await,
+        }
+    }
+
+    /// Permanently delete the entity at `link`, even if it is in the trash.
+    async fn purge_entity(&self, link: &ExactLink) -> Result<()> {
+        if let Some(digest) = self.store.get_entity(link).await? {
+            self.store.del_blobs(link, digest).await?;
+        }
+        self.store.del_entity(link).await?;
+        if let Some(quotas) = &self.config.quotas {
+            quotas.set_entity_size(link, None).await?;
+        }
+        self.unindex_entity(link).await
+    }
+
+    /// Remove a deleted entity from the indexes.
+    async fn unindex_entity(&self, link: &ExactLink) -> Result<()> {
+        self.config.unique.remove_entity(link);
+        if let Some(search) = &self.config.search {
+            search.remove_entity(link);
+        }
+        if let Some(backlinks) = &self.config.backlinks {
+            backlinks.remove_entity(link).await?;
         }
-        self.store.del_entity(&link).await?;
         Ok(())
     }
 
+    /// List the entities in the trash of a subspace, from the least to the most recently deleted.
+    pub async fn list_trash(
+        &self,
+        namespace: NamespaceId,
+        subspace: SubspaceId,
+    ) -> Result<Vec<TrashEntry>> {
+        let root = trash::trash_root(namespace, subspace);
+        let stream = self.store.list(root, None, None, None).await?;
+        pin_mut!(stream);
+        let mut entries = Vec::new();
+        while let Some(link) = stream.try_next().await? {
+            entries.extend(TrashEntry::from_link(&link));
+        }
+        entries.sort_by_key(|x| x.deleted_at);
+        Ok(entries)
+    }
+
+    /// Restore an entity from the trash to the link that it was deleted from, returning that link.
+    ///
+    /// Returns an error if there is already an entity at the original link, and a
+    /// [`UniqueConstraintViolation`] error if another entity has since been given one of the
+    /// entity's unique component values.
+    pub async fn restore_trash<L: Into<ExactLink>>(&self, link: L) -> Result<ExactLink> {
+        let link = link.into();
+        let Some(entry) = TrashEntry::from_link(&link) else {
+            anyhow::bail!("{link:?} is not in the trash.");
+        };
+        let Some(digest) = self.store.get_entity(&link).await? else {
+            anyhow::bail!("Entity does not exist at: {link:?}");
+        };
+        if self.store.get_entity(&entry.original).await?.is_some() {
+            anyhow::bail!(
+                "Can't restore {link:?} because there is already an entity at {:?}",
+                entry.original
+            );
+        }
+
+        {
+            // Hold the unique constraint lock until the entity is restored, like when saving it.
+            let _unique_guard = self.config.unique.lock().lock().await;
+            let bytes = self.store.get_blob(digest).await?;
+            let entity = Entity::deserialize(&mut &bytes[..])?;
+            for component in entity.components {
+                let Some(schema) = component.schema_id else {
+                    continue;
+                };
+                let Some(scope) = self.config.unique.scope(schema) else {
+                    continue;
+                };
+                if let Some(existing) = self
+                    .config
+                    .unique
+                    .find_holder(&self.store, &entry.original, scope, component)
+                    .await?
+                {
+                    return Err(UniqueConstraintViolation {
+                        schema,
+                        scope,
+                        existing,
+                    }
+                    .into());
+                }
+            }
+
+            trash::move_entity(&self.store, &self.config, &link, &entry.original, digest).await?;
+        }
+
+        let search = self.config.search.as_ref();
+        let backlinks = self.config.backlinks.as_ref();
+        if search.is_some() || backlinks.is_some() {
+            let entity = self.entity(entry.original.clone()).await?.entity()?;
+            if let Some(search) = search {
+                let texts = search.entity_texts(&entity).await?;
+                search.index_entity(&entity.link, &texts);
+            }
+            if let Some(backlinks) = backlinks {
+                let targets = backlinks.entity_links(&entity).await?;
+                backlinks.index_entity(&entity.link, targets).await?;
+            }
+        }
+
+        Ok(entry.original)
+    }
+
+    /// Permanently delete an entity from the trash.
+    pub async fn purge_trash<L: Into<ExactLink>>(&self, link: L) -> Result<()> {
+        let link = link.into();
+        if !trash::is_trashed(&link.path) {
+            anyhow::bail!("{link:?} is not in the trash.");
+        }
+        self.purge_entity(&link).await
+    }
+
+    /// Purge the entities that have been in the trash of a subspace for longer than the retention
+    /// of the trash, returning how many were purged.
+    ///
+    /// The trash must be enabled with [`with_trash()`][Self::with_trash].
+    pub async fn purge_expired_trash(
+        &self,
+        namespace: NamespaceId,
+        subspace: SubspaceId,
+    ) -> Result<u64> {
+        let Some(retention) = self.config.trash_retention else {
+            anyhow::bail!("The trash is not enabled on this Leaf store.");
+        };
+        let retention = retention.as_millis().try_into().unwrap_or(u64::MAX);
+        let cutoff = trash::now_millis().saturating_sub(retention);
+        let mut purged = 0;
+        for entry in self.list_trash(namespace, subspace).await? {
+            if entry.deleted_at >= cutoff {
+                break;
+            }
+            self.purge_trash(entry.link).await?;
+            purged += 1;
+        }
+        Ok(purged)
+    }
+
+    /// Search the textual components of the entities in a namespace, returning up to `limit`
+    /// results ordered from most to least relevant.
+    ///
+    /// The namespace will be indexed the first time it is searched. Search must be enabled with
+    /// [`with_search_index()`][Self::with_search_index].
+    pub async fn search(
+        &self,
+        namespace: NamespaceId,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let Some(search) = &self.config.search else {
+            anyhow::bail!("Search is not enabled on this Leaf store.");
+        };
+        if !search.is_indexed(namespace) {
+            self.reindex(namespace).await?;
+        }
+        Ok(search.search(namespace, query, limit))
+    }
+
+    /// Get the entities that link to `link`, optionally only through components with the given
+    /// schema.
+    ///
+    /// The namespace of `link` will be indexed the first time that backlinks into it are requested.
+    /// Links from entities in other namespaces are only found once those namespaces have been
+    /// indexed too. Backlinks must be enabled with
+    /// [`with_backlink_index()`][Self::with_backlink_index].
+    pub async fn backlinks<L: Into<ExactLink>>(
+        &self,
+        link: L,
+        schema: Option<Digest>,
+    ) -> Result<Vec<Backlink>> {
+        let link = link.into();
+        let Some(backlinks) = &self.config.backlinks else {
+            anyhow::bail!("Backlinks are not enabled on this Leaf store.");
+        };
+        if !backlinks.is_indexed(link.namespace) {
+            self.reindex(link.namespace).await?;
+        }
+        Ok(backlinks.backlinks(&link, schema))
+    }
+
+    /// Get how much is stored in a subspace.
+    ///
+    /// If quotas are enabled with [`with_quotas()`][Self::with_quotas], the subspace is counted the
+    /// first time that its usage is requested, and the count is kept up to date after that.
+    /// Otherwise the subspace is counted every time.
+    pub async fn subspace_usage(
+        &self,
+        namespace: NamespaceId,
+        subspace: SubspaceId,
+    ) -> Result<SubspaceUsage> {
+        let Some(quotas) = &self.config.quotas else {
+            let sizes = quota::entity_sizes(&self.store, namespace, subspace).await?;
+            return Ok(SubspaceUsage {
+                bytes: sizes.iter().map(|(_, size)| size).sum(),
+                entities: sizes.len() as u64,
+            });
+        };
+        if let Some(usage) = quotas.usage(namespace, subspace) {
+            return Ok(usage);
+        }
+        self.recount_usage(namespace, subspace).await?;
+        Ok(quotas.usage(namespace, subspace).unwrap_or_default())
+    }
+
+    /// Count the usage of a subspace again from the entities in the store.
+    ///
+    /// This can be used to pick up changes that were synced from other nodes. Quotas must be
+    /// enabled with [`with_quotas()`][Self::with_quotas].
+    pub async fn recount_usage(&self, namespace: NamespaceId, subspace: SubspaceId) -> Result<()> {
+        let Some(quotas) = &self.config.quotas else {
+            anyhow::bail!("Quotas are not enabled on this Leaf store.");
+        };
+        let sizes = quota::entity_sizes(&self.store, namespace, subspace).await?;
+        quotas.count_subspace(namespace, subspace, sizes).await
+    }
+
+    /// Rebuild the search, backlink and unique value indexes, whichever are enabled, for a
+    /// namespace from the entities in the store.
+    ///
+    /// This can be used to pick up changes that were synced from other nodes.
+    pub async fn reindex(&self, namespace: NamespaceId) -> Result<()> {
+        let search = self.config.search.as_ref();
+        let backlinks = self.config.backlinks.as_ref();
+        let unique = &self.config.unique;
+        if search.is_none() && backlinks.is_none() && unique.is_empty() {
+            anyhow::bail!(
+                "Neither search, backlinks nor unique constraints are enabled on this Leaf store."
+            );
+        }
+        if !unique.is_empty() {
+            unique.index_namespace(&self.store, namespace).await?;
+        }
+        if search.is_none() && backlinks.is_none() {
+            return Ok(());
+        }
+
+        let mut texts = Vec::new();
+        let mut links = Vec::new();
+        let subspaces = self.store.list_namespace_subspaces(namespace).await?;
+        pin_mut!(subspaces);
+        while let Some(subspace) = subspaces.try_next().await? {
+            let entity_links = self.list((namespace, subspace, ()), None, None).await?;
+            pin_mut!(entity_links);
+            while let Some(link) = entity_links.try_next().await? {
+                let EntityEntry::Entity(entity) = self.entity(link).await? else {
+                    continue;
+                };
+                if let Some(search) = search {
+                    texts.push((entity.link.clone(), search.entity_texts(&entity).await?));
+                }
+                if let Some(backlinks) = backlinks {
+                    links.push((entity.link.clone(), backlinks.entity_links(&entity).await?));
+                }
+            }
+        }
+        if let Some(search) = search {
+            search.index_namespace(namespace, texts);
+        }
+        if let Some(backlinks) = backlinks {
+            backlinks.index_namespace(namespace, links).await?;
+        }
+
+        Ok(())
+    }
+
+    /// List the entities at or below the path of `link`.
+    ///
+    /// If `depth` is set, only entities at most `depth` path segments below `link` are listed, so
+    /// a depth of `1` lists the direct children of `link`. The entity at `link` itself is included
+    /// if it exists.
+    ///
+    /// If `after` is set, listing resumes after the entity with that path. See
+    /// [`LeafStore::list()`] for details about the listing order.
     pub async fn list<L: Into<ExactLink>>(
         &self,
         link: L,
-        // TODO: add a recursive option to allow only listing the entities that are direct children
-        // of the link.
+        depth: Option<u32>,
+        after: Option<EntityPath>,
     ) -> Result<impl Stream<Item = Result<ExactLink>> + '_> {
         let link = link.into();
-        let s = self.store.list(link, None, None).await?;
+        let s = self.list_live(link, depth, after, None).await?;
         Ok(s)
     }
 
+    /// List a page of up to `limit` entities at or below the path of `link`.
+    ///
+    /// This works like [`list()`][Self::list], but the returned page includes the cursor to pass
+    /// as `after` to get the next page, if there are more entities. Returns an error if `limit` is
+    /// `0`, because an empty page has no cursor to continue from.
+    pub async fn list_page<L: Into<ExactLink>>(
+        &self,
+        link: L,
+        depth: Option<u32>,
+        after: Option<EntityPath>,
+        limit: u64,
+    ) -> Result<EntityPage> {
+        anyhow::ensure!(
+            limit > 0,
+            "The limit of a page of entities must be at least 1."
+        );
+        let link = link.into();
+        // Fetch one extra entity so that we know whether there is another page.
+        let stream = self
+            .list_live(link, depth, after, Some(limit.saturating_add(1)))
+            .await?;
+        pin_mut!(stream);
+        let mut entities = Vec::new();
+        let mut next = None;
+        while let Some(link) = stream.try_next().await? {
+            if entities.len() as u64 == limit {
+                next = entities.last().map(|x: &ExactLink| x.path.clone());
+                break;
+            }
+            entities.push(link);
+        }
+        Ok(EntityPage { entities, next })
+    }
+
+    /// List entities from the store, leaving out the entities in the trash.
+    async fn list_live(
+        &self,
+        link: ExactLink,
+        depth: Option<u32>,
+        after: Option<EntityPath>,
+        limit: Option<u64>,
+    ) -> Result<impl Stream<Item = Result<ExactLink>> + '_> {
+        // The trash is always at the root of a subspace, so other listings can't include it.
+        let in_root = link.path.0.is_empty();
+        let Some(limit) = limit.filter(|_| in_root) else {
+            let stream = self.store.list(link, depth, after, limit).await?;
+            return Ok(stream
+                .try_filter(move |x| std::future::ready(!(in_root && trash::is_trashed(&x.path))))
+                .left_stream());
+        };
+
+        // The trashed entities are only skipped after the store has listed them, so they would
+        // count against the limit. Instead, we list a page at a time and keep listing from the end
+        // of the last page until we have found enough entities that aren't in the trash. Each page
+        // is larger by the number of trashed entities that we have skipped, so a large trash is
+        // skipped in a few pages.
+        let pages = futures::stream::try_unfold(
+            (after, limit, 0, false),
+            move |(after, remaining, trashed, done)| {
+                let link = link.clone();
+                async move {
+                    if done || remaining == 0 {
+                        return Ok(None);
+                    }
+                    let size = remaining.saturating_add(trashed);
+                    let page = self
+                        .store
+                        .list(link, depth, after, Some(size))
+                        .await?
+                        .try_collect::<Vec<_>>()
+                        .await?;
+                    let last = page.last().map(|x| x.path.clone());
+                    let listed = page.len() as u64;
+                    let mut live = page
+                        .into_iter()
+                        .filter(|x| !trash::is_trashed(&x.path))
+                        .collect::<Vec<_>>();
+                    let trashed = trashed + listed - live.len() as u64;
+                    live.truncate(remaining.try_into().unwrap_or(usize::MAX));
+                    let remaining = remaining - live.len() as u64;
+                    anyhow::Ok(Some((live, (last, remaining, trashed, listed < size))))
+                }
+            },
+        );
+        Ok(pages
+            .map_ok(|page| futures::stream::iter(page.into_iter().map(Ok)))
+            .try_flatten()
+            .right_stream())
+    }
+
+    /// List the namespaces in the store, and whether we can write to them.
     pub async fn list_namespaces(
         &self,
-    ) -> anyhow::Result<impl Stream<Item = std::result::Result<NamespaceId, anyhow::Error>> + '_>
-    {
+    ) -> anyhow::Result<
+        impl Stream<Item = std::result::Result<(NamespaceId, NamespaceCapability), anyhow::Error>> + '_,
+    > {
         self.store.list_namespaces().await
     }
     pub async fn list_subspaces(
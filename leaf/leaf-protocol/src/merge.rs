@@ -0,0 +1,184 @@
+//! Merged views of the same entity path across multiple subspaces.
+//!
+//! Every subspace in a namespace has its own copy of each path. A [`MergedEntity`] combines the
+//! components of the entities at one path in an ordered list of subspaces, for example an instance
+//! subspace followed by a user's own subspace, following a [`MergePolicy`] for each schema.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use borsh::BorshDeserialize;
+
+use crate::{
+    store::LeafStore,
+    types::{ComponentEntry, ComponentKind, EntityPath, NamespaceId, SubspaceId},
+    Component, Digest,
+};
+
+/// How to merge the components with the same schema from multiple subspaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergePolicy {
+    /// Only use the components from the first subspace, in the merge order, that has any
+    /// components with the schema.
+    #[default]
+    FirstWins,
+    /// Use the components from all of the subspaces. Identical components in multiple subspaces are
+    /// only included once, from the first subspace that has them.
+    Union,
+}
+
+/// The [`MergePolicy`] to use for each schema when merging entities.
+#[derive(Debug, Clone, Default)]
+pub struct MergePolicies {
+    /// The policy for schemas that don't have a specific policy.
+    pub default: MergePolicy,
+    schemas: HashMap<Digest, MergePolicy>,
+}
+
+impl MergePolicies {
+    /// Create merge policies that use `default` for every schema.
+    pub fn new(default: MergePolicy) -> Self {
+        Self {
+            default,
+            schemas: Default::default(),
+        }
+    }
+
+    /// Use `policy` for components with the given schema.
+    pub fn with_schema(mut self, schema: Digest, policy: MergePolicy) -> Self {
+        self.schemas.insert(schema, policy);
+        self
+    }
+
+    /// Use `policy` for components of type `C`.
+    pub fn with_component<C: Component>(self, policy: MergePolicy) -> Self {
+        self.with_schema(C::schema_id(), policy)
+    }
+
+    /// Get the policy for components with the given schema.
+    ///
+    /// Components without a schema, such as encrypted components, use the default policy.
+    pub fn policy(&self, schema: Option<Digest>) -> MergePolicy {
+        schema
+            .and_then(|schema| self.schemas.get(&schema).copied())
+            .unwrap_or(self.default)
+    }
+}
+
+/// A component in a [`MergedEntity`], along with the subspace that it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergedComponent {
+    /// The subspace that has the component.
+    pub subspace: SubspaceId,
+    pub schema_id: Option<Digest>,
+    pub component_id: Digest,
+}
+
+/// A value along with the subspace that it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FromSubspace<T> {
+    pub subspace: SubspaceId,
+    pub value: T,
+}
+
+/// A read-only view of the entities at the same path in multiple subspaces, merged into one.
+///
+/// Created with [`Leaf::merged_entity()`][crate::Leaf::merged_entity].
+#[derive(Debug, Clone)]
+pub struct MergedEntity<S: LeafStore> {
+    pub store: S,
+    pub namespace: NamespaceId,
+    pub path: EntityPath,
+    /// The subspaces that have an entity at the path, in the merge order.
+    pub subspaces: Vec<SubspaceId>,
+    /// The merged components, in the merge order of their subspaces.
+    pub components: Vec<MergedComponent>,
+}
+
+impl<S: LeafStore> MergedEntity<S> {
+    /// Merge the component entries of the entities in each subspace, which must be given in the
+    /// merge order.
+    pub(crate) fn merge(
+        store: S,
+        namespace: NamespaceId,
+        path: EntityPath,
+        entities: Vec<(SubspaceId, Vec<ComponentEntry>)>,
+        policies: &MergePolicies,
+    ) -> Self {
+        // The subspace that each schema is taken from, for schemas with the first-wins policy.
+        let mut winners = HashMap::<Option<Digest>, SubspaceId>::new();
+        let mut seen = HashSet::<Digest>::new();
+        let mut components = Vec::new();
+        for (subspace, entries) in &entities {
+            for &ComponentEntry {
+                schema_id,
+                component_id,
+            } in entries
+            {
+                if policies.policy(schema_id) == MergePolicy::FirstWins
+                    && *winners.entry(schema_id).or_insert(*subspace) != *subspace
+                {
+                    continue;
+                }
+                if seen.insert(component_id) {
+                    components.push(MergedComponent {
+                        subspace: *subspace,
+                        schema_id,
+                        component_id,
+                    });
+                }
+            }
+        }
+        Self {
+            store,
+            namespace,
+            path,
+            subspaces: entities.into_iter().map(|(subspace, _)| subspace).collect(),
+            components,
+        }
+    }
+
+    /// Get the data of the merged components with the given schema, along with the subspace each
+    /// one came from.
+    pub async fn get_components_by_schema(
+        &self,
+        schema: Digest,
+    ) -> Result<Vec<FromSubspace<Vec<u8>>>> {
+        let mut res = Vec::new();
+        for component in &self.components {
+            if component.schema_id != Some(schema) {
+                continue;
+            }
+            let data = self.store.get_blob(component.component_id).await?;
+            if let ComponentKind::Unencrypted(data) = ComponentKind::deserialize(&mut &data[..])? {
+                if data.schema == schema {
+                    res.push(FromSubspace {
+                        subspace: component.subspace,
+                        value: data.data,
+                    });
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    /// Get the first merged component of the given type, or [`None`] if there is no component of
+    /// that type.
+    pub async fn get_component<C: Component>(&self) -> Result<Option<FromSubspace<C>>> {
+        Ok(self.get_components::<C>().await?.into_iter().next())
+    }
+
+    /// Get all merged components of the given type.
+    pub async fn get_components<C: Component>(&self) -> Result<Vec<FromSubspace<C>>> {
+        self.get_components_by_schema(C::schema_id())
+            .await?
+            .into_iter()
+            .map(|data| {
+                Ok(FromSubspace {
+                    subspace: data.subspace,
+                    value: C::deserialize(&mut &data.value[..])?,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,39 @@
+//! Local petnames and descriptions for namespaces and subspaces.
+//!
+//! Namespace and subspace IDs are opaque 32 byte keys, which makes it hard for people to keep track
+//! of which is which. `LeafMetadataStore`, which is enabled by the `metadata` feature, attaches a
+//! human-readable petname, a description, and the time that we first saw the key, to each of them.
+//!
+//! Metadata is only stored locally, in a `redb` database, and is never synced to other nodes. It
+//! may be kept in its own database file or share the database of a `LeafRedbStore`.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::types::{NamespaceId, SubspaceId};
+
+#[cfg(feature = "metadata")]
+pub mod redb;
+#[cfg(feature = "metadata")]
+pub use redb::LeafMetadataStore;
+
+/// A namespace or subspace that metadata can be attached to.
+///
+/// Namespaces and subspaces with the same secret have the same ID, so the kind of key is part of
+/// the metadata key.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MetadataKey {
+    Namespace(NamespaceId),
+    Subspace(SubspaceId),
+}
+
+/// Local metadata about a namespace or subspace.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyMetadata {
+    /// A short, human-readable name for the key. Petnames are only meaningful locally, and don't
+    /// need to be unique.
+    pub petname: Option<String>,
+    /// A longer description of what the key is used for.
+    pub description: Option<String>,
+    /// When the metadata for the key was first recorded, in seconds since the Unix epoch.
+    pub created_at: u64,
+}
@@ -0,0 +1,150 @@
+//! A [`redb`] database for [`KeyMetadata`].
+
+use std::{path::Path, sync::Arc};
+
+use borsh::BorshDeserialize;
+use redb::{Database, ReadTransaction, ReadableTable, TableDefinition, WriteTransaction};
+
+use super::{KeyMetadata, MetadataKey};
+
+/// The metadata for each key, keyed by the encoded [`MetadataKey`].
+const KEY_METADATA: TableDefinition<&[u8], &[u8]> = TableDefinition::new("leaf_key_metadata");
+
+/// A local store for [`KeyMetadata`].
+///
+/// Cloning the store is cheap and the clones share the same database.
+#[derive(Debug, Clone)]
+pub struct LeafMetadataStore {
+    db: Arc<Database>,
+}
+
+impl LeafMetadataStore {
+    /// Open or create a metadata database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::new(Arc::new(Database::create(path)?))
+    }
+
+    /// Create a metadata store using an existing database.
+    ///
+    /// The store only uses tables with names starting with `leaf_`, so the database may be shared
+    /// with a [`LeafRedbStore`][crate::store::redb::LeafRedbStore] or other tables.
+    pub fn new(db: Arc<Database>) -> anyhow::Result<Self> {
+        let tx = db.begin_write()?;
+        {
+            // Make sure that the table exists so that reads don't fail on a new database.
+            tx.open_table(KEY_METADATA)?;
+        }
+        tx.commit()?;
+        Ok(Self { db })
+    }
+
+    /// Get the metadata for a key, if any has been recorded.
+    pub async fn get(&self, key: MetadataKey) -> anyhow::Result<Option<KeyMetadata>> {
+        self.read(move |tx| {
+            let key = borsh::to_vec(&key)?;
+            let table = tx.open_table(KEY_METADATA)?;
+            let Some(value) = table.get(&key[..])? else {
+                return Ok(None);
+            };
+            Ok(Some(KeyMetadata::try_from_slice(value.value())?))
+        })
+        .await
+    }
+
+    /// Set the petname and description of a key, returning the updated metadata.
+    ///
+    /// The creation time is recorded the first time that metadata is set for the key, and is kept
+    /// after that.
+    pub async fn set(
+        &self,
+        key: MetadataKey,
+        petname: Option<String>,
+        description: Option<String>,
+    ) -> anyhow::Result<KeyMetadata> {
+        self.write(move |tx| {
+            let mut metadata = get_or_default(tx, key)?;
+            metadata.petname = petname;
+            metadata.description = description;
+            put(tx, key, &metadata)?;
+            Ok(metadata)
+        })
+        .await
+    }
+
+    /// Record that we have seen a key, which sets its creation time if it doesn't have any
+    /// metadata yet.
+    pub async fn record(&self, key: MetadataKey) -> anyhow::Result<()> {
+        self.write(move |tx| {
+            let metadata = get_or_default(tx, key)?;
+            put(tx, key, &metadata)
+        })
+        .await
+    }
+
+    /// List all of the keys that have metadata.
+    pub async fn list(&self) -> anyhow::Result<Vec<(MetadataKey, KeyMetadata)>> {
+        self.read(|tx| {
+            tx.open_table(KEY_METADATA)?
+                .iter()?
+                .map(|x| {
+                    let (key, value) = x?;
+                    Ok((
+                        MetadataKey::try_from_slice(key.value())?,
+                        KeyMetadata::try_from_slice(value.value())?,
+                    ))
+                })
+                .collect()
+        })
+        .await
+    }
+
+    /// Run a read transaction on a blocking thread.
+    async fn read<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&ReadTransaction) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || f(&db.begin_read()?))
+            .await
+            .map_err(|_| anyhow::format_err!("Error executing database operation"))?
+    }
+
+    /// Run a write transaction on a blocking thread, committing it if `f` succeeds.
+    async fn write<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&WriteTransaction) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || {
+            let tx = db.begin_write()?;
+            let value = f(&tx)?;
+            tx.commit()?;
+            Ok(value)
+        })
+        .await
+        .map_err(|_| anyhow::format_err!("Error executing database operation"))?
+    }
+}
+
+/// Get the metadata for a key, or new metadata created now if there isn't any yet.
+fn get_or_default(tx: &WriteTransaction, key: MetadataKey) -> anyhow::Result<KeyMetadata> {
+    let key = borsh::to_vec(&key)?;
+    let table = tx.open_table(KEY_METADATA)?;
+    let metadata = match table.get(&key[..])? {
+        Some(value) => KeyMetadata::try_from_slice(value.value())?,
+        None => KeyMetadata {
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs(),
+            ..Default::default()
+        },
+    };
+    Ok(metadata)
+}
+
+fn put(tx: &WriteTransaction, key: MetadataKey, metadata: &KeyMetadata) -> anyhow::Result<()> {
+    let key = borsh::to_vec(&key)?;
+    let value = borsh::to_vec(metadata)?;
+    tx.open_table(KEY_METADATA)?.insert(&key[..], &value[..])?;
+    Ok(())
+}
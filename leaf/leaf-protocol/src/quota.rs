@@ -0,0 +1,356 @@
+//! Per-subspace storage quotas.
+//!
+//! [`SubspaceQuotas`] limits the total size of the entities in each subspace, the number of
+//! entities, and the size of a single component. The size of an entity is the size of its
+//! snapshot plus the size of each of its components, so components that are shared between
+//! entities are counted once for every entity that has them.
+//!
+//! The usage of a subspace is counted from the store the first time an entity is saved to it or
+//! its usage is requested, and after that it is kept up to date as entities are saved or deleted
+//! through the [`Leaf`][crate::Leaf] the quotas are attached to. Changes that come from somewhere
+//! else, such as a sync from another node, will not be noticed until the usage is recounted with
+//! [`Leaf::recount_usage()`][crate::Leaf::recount_usage].
+//!
+//! With the `metadata` feature, the usage can be persisted in a `redb` database with
+//! [`SubspaceQuotas::with_database()`], so that subspaces are only counted once, instead of every
+//! time that the process is started.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Result;
+use borsh::{BorshDeserialize, BorshSerialize};
+use futures::{pin_mut, TryStreamExt};
+
+#[cfg(feature = "metadata")]
+mod redb;
+
+use crate::{
+    store::LeafStore,
+    types::{Entity, EntityPath, ExactLink, NamespaceId, SubspaceId},
+    Digest,
+};
+
+/// Limits on what may be stored in a subspace. A limit of [`None`] means that it is unlimited.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    /// The total size of the entities in the subspace, in bytes.
+    pub max_bytes: Option<u64>,
+    /// The number of entities in the subspace.
+    pub max_entities: Option<u64>,
+    /// The size of a single component, in bytes.
+    pub max_component_bytes: Option<u64>,
+}
+
+/// How much is stored in a subspace.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubspaceUsage {
+    /// The total size of the entities in the subspace, in bytes.
+    pub bytes: u64,
+    /// The number of entities in the subspace.
+    pub entities: u64,
+}
+
+/// A limit of a [`Quota`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaLimit {
+    Bytes,
+    Entities,
+    ComponentBytes,
+}
+
+impl std::fmt::Display for QuotaLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuotaLimit::Bytes => write!(f, "total size"),
+            QuotaLimit::Entities => write!(f, "entity count"),
+            QuotaLimit::ComponentBytes => write!(f, "component size"),
+        }
+    }
+}
+
+/// Error returned when saving an entity would exceed the quota of its subspace.
+#[derive(Debug, Clone)]
+pub struct QuotaExceeded {
+    /// The entity that was being saved.
+    pub link: ExactLink,
+    /// The limit that would be exceeded.
+    pub limit: QuotaLimit,
+    /// The value of the limit.
+    pub max: u64,
+    /// The value that saving the entity would have resulted in.
+    pub requested: u64,
+}
+
+impl std::fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Saving the entity at {:?} would exceed the {} quota of its subspace: {} is more \
+            than the limit of {}",
+            self.link, self.limit, self.requested, self.max
+        )
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+#[derive(Debug, Default)]
+struct SubspaceState {
+    usage: SubspaceUsage,
+    /// The size of each entity in the subspace.
+    entities: HashMap<EntityPath, u64>,
+}
+
+impl SubspaceState {
+    /// Add an entity that isn't counted yet.
+    fn insert(&mut self, path: EntityPath, size: u64) {
+        self.usage.bytes += size;
+        self.usage.entities += 1;
+        self.entities.insert(path, size);
+    }
+}
+
+/// The usage of each subspace that has been counted.
+type Subspaces = Arc<RwLock<HashMap<(NamespaceId, SubspaceId), SubspaceState>>>;
+
+/// The [`Quota`]s of each subspace, and how much of them is used.
+///
+/// Cloning the quotas is cheap and the clones share the same usage counts.
+#[derive(Debug, Clone, Default)]
+pub struct SubspaceQuotas {
+    default: Quota,
+    overrides: HashMap<SubspaceId, Quota>,
+    subspaces: Subspaces,
+    #[cfg(feature = "metadata")]
+    db: Option<redb::QuotaDb>,
+}
+
+impl SubspaceQuotas {
+    /// Apply the `default` quota to every subspace.
+    pub fn new(default: Quota) -> Self {
+        Self {
+            default,
+            ..Default::default()
+        }
+    }
+
+    /// Use a different quota for the given subspace, in every namespace.
+    pub fn with_subspace_quota(mut self, subspace: SubspaceId, quota: Quota) -> Self {
+        self.overrides.insert(subspace, quota);
+        self
+    }
+
+    /// Persist the usage counts in `db`, loading the subspaces that have already been counted in
+    /// it.
+    ///
+    /// The quotas only use tables with names starting with `leaf_`, so the database may be shared
+    /// with a [`LeafMetadataStore`][crate::metadata::LeafMetadataStore] or a
+    /// [`LeafRedbStore`][crate::store::redb::LeafRedbStore].
+    ///
+    /// Changes that were made to the store while the quotas weren't in use are only picked up by
+    /// recounting the usage with [`Leaf::recount_usage()`][crate::Leaf::recount_usage].
+    #[cfg(feature = "metadata")]
+    pub fn with_database(mut self, db: Arc<::redb::Database>) -> Result<Self> {
+        let db = redb::QuotaDb::new(db)?;
+        self.subspaces = Arc::new(RwLock::new(db.load()?));
+        self.db = Some(db);
+        Ok(self)
+    }
+
+    /// Write the usage of a subspace to the database, if the usage is persisted.
+    async fn persist_subspace(&self, namespace: NamespaceId, subspace: SubspaceId) -> Result<()> {
+        #[cfg(feature = "metadata")]
+        if let Some(db) = &self.db {
+            db.put_subspace(self.subspaces.clone(), namespace, subspace)
+                .await?;
+        }
+        #[cfg(not(feature = "metadata"))]
+        let _ = (namespace, subspace);
+        Ok(())
+    }
+
+    /// Write the sizes of the entities at `links` to the database, if the usage is persisted.
+    async fn persist_entities(&self, links: &[&ExactLink]) -> Result<()> {
+        #[cfg(feature = "metadata")]
+        if let Some(db) = &self.db {
+            let links = links.iter().map(|&x| x.clone()).collect();
+            db.put_entities(self.subspaces.clone(), links).await?;
+        }
+        #[cfg(not(feature = "metadata"))]
+        let _ = links;
+        Ok(())
+    }
+
+    /// Get the quota of a subspace.
+    pub fn quota(&self, subspace: SubspaceId) -> Quota {
+        self.overrides
+            .get(&subspace)
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    /// Get the usage of a subspace, or [`None`] if it hasn't been counted yet.
+    pub fn usage(&self, namespace: NamespaceId, subspace: SubspaceId) -> Option<SubspaceUsage> {
+        let subspaces = self.subspaces.read().unwrap();
+        subspaces.get(&(namespace, subspace)).map(|x| x.usage)
+    }
+
+    /// Replace the usage of a subspace with the given entities and their sizes.
+    pub async fn count_subspace(
+        &self,
+        namespace: NamespaceId,
+        subspace: SubspaceId,
+        entities: Vec<(EntityPath, u64)>,
+    ) -> Result<()> {
+        let mut state = SubspaceState::default();
+        for (path, size) in entities {
+            state.insert(path, size);
+        }
+        self.subspaces
+            .write()
+            .unwrap()
+            .insert((namespace, subspace), state);
+        self.persist_subspace(namespace, subspace).await
+    }
+
+    /// Check that a component of `size` bytes may be added to the entity at `link`.
+    pub fn check_component(&self, link: &ExactLink, size: u64) -> Result<(), QuotaExceeded> {
+        let quota = self.quota(link.subspace);
+        match quota.max_component_bytes {
+            Some(max) if size > max => Err(QuotaExceeded {
+                link: link.clone(),
+                limit: QuotaLimit::ComponentBytes,
+                max,
+                requested: size,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Replace the size of the entity at `link` with `size`, if that keeps its subspace within its
+    /// quota, returning the previous size of the entity.
+    ///
+    /// Changes that don't increase the usage of a subspace are always allowed, even if the
+    /// subspace is already over its quota. The subspace must have been counted first, otherwise
+    /// nothing is checked or changed.
+    ///
+    /// Returns a [`QuotaExceeded`] error if the change isn't allowed.
+    pub async fn reserve_entity_size(&self, link: &ExactLink, size: u64) -> Result<Option<u64>> {
+        let previous = self.try_reserve_entity_size(link, size)?;
+        self.persist_entities(&[link]).await?;
+        Ok(previous)
+    }
+
+    fn try_reserve_entity_size(
+        &self,
+        link: &ExactLink,
+        size: u64,
+    ) -> Result<Option<u64>, QuotaExceeded> {
+        let quota = self.quota(link.subspace);
+        let mut subspaces = self.subspaces.write().unwrap();
+        let Some(state) = subspaces.get_mut(&(link.namespace, link.subspace)) else {
+            return Ok(None);
+        };
+        let previous = state.entities.get(&link.path).copied();
+        let bytes = state.usage.bytes - previous.unwrap_or(0) + size;
+        let entities = state.usage.entities + previous.is_none() as u64;
+        if let Some(max) = quota.max_bytes {
+            if bytes > max && bytes > state.usage.bytes {
+                return Err(QuotaExceeded {
+                    link: link.clone(),
+                    limit: QuotaLimit::Bytes,
+                    max,
+                    requested: bytes,
+                });
+            }
+        }
+        if let Some(max) = quota.max_entities {
+            if entities > max && entities > state.usage.entities {
+                return Err(QuotaExceeded {
+                    link: link.clone(),
+                    limit: QuotaLimit::Entities,
+                    max,
+                    requested: entities,
+                });
+            }
+        }
+        state.usage = SubspaceUsage { bytes, entities };
+        state.entities.insert(link.path.clone(), size);
+        Ok(previous)
+    }
+
+    /// Set the size of the entity at `link` without checking the quota, or remove the entity if
+    /// the size is [`None`].
+    pub async fn set_entity_size(&self, link: &ExactLink, size: Option<u64>) -> Result<()> {
+        {
+            let mut subspaces = self.subspaces.write().unwrap();
+            let Some(state) = subspaces.get_mut(&(link.namespace, link.subspace)) else {
+                return Ok(());
+            };
+            let previous = match size {
+                Some(size) => state.entities.insert(link.path.clone(), size),
+                None => state.entities.remove(&link.path),
+            };
+            state.usage.bytes = state.usage.bytes - previous.unwrap_or(0) + size.unwrap_or(0);
+            state.usage.entities =
+                state.usage.entities - previous.is_some() as u64 + size.is_some() as u64;
+        }
+        self.persist_entities(&[link]).await
+    }
+
+    /// Move the size of the entity at `from` to the entity at `to`, in the same subspace, without
+    /// checking the quota.
+    pub async fn move_entity_size(&self, from: &ExactLink, to: &ExactLink) -> Result<()> {
+        {
+            let mut subspaces = self.subspaces.write().unwrap();
+            let Some(state) = subspaces.get_mut(&(from.namespace, from.subspace)) else {
+                return Ok(());
+            };
+            let Some(size) = state.entities.remove(&from.path) else {
+                return Ok(());
+            };
+            if let Some(previous) = state.entities.insert(to.path.clone(), size) {
+                state.usage.bytes -= previous;
+                state.usage.entities -= 1;
+            }
+        }
+        self.persist_entities(&[from, to]).await
+    }
+}
+
+/// Get the size of every entity in a subspace.
+pub(crate) async fn entity_sizes<S: LeafStore>(
+    store: &S,
+    namespace: NamespaceId,
+    subspace: SubspaceId,
+) -> Result<Vec<(EntityPath, u64)>> {
+    let root = ExactLink {
+        namespace,
+        subspace,
+        path: EntityPath::default(),
+    };
+    let stream = store.list(root, None, None, None).await?;
+    pin_mut!(stream);
+    let mut sizes = Vec::new();
+    while let Some(link) = stream.try_next().await? {
+        let Some(digest) = store.get_entity(&link).await? else {
+            continue;
+        };
+        sizes.push((link.path, entity_size(store, digest).await?));
+    }
+    Ok(sizes)
+}
+
+/// Get the size of the entity snapshot with the given digest, including its components.
+async fn entity_size<S: LeafStore>(store: &S, digest: Digest) -> Result<u64> {
+    let bytes = store.get_blob(digest).await?;
+    let entity = Entity::deserialize(&mut &bytes[..])?;
+    let mut size = bytes.len() as u64;
+    for entry in &entity.components {
+        size += store.get_blob(entry.component_id).await?.len() as u64;
+    }
+    Ok(size)
+}
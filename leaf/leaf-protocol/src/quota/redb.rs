@@ -0,0 +1,152 @@
+//! A [`redb`] database that persists the usage counts of [`SubspaceQuotas`][super::SubspaceQuotas].
+
+use std::{collections::HashMap, sync::Arc};
+
+use borsh::BorshDeserialize;
+use redb::{Database, ReadableTable, TableDefinition, WriteTransaction};
+
+use super::{SubspaceState, Subspaces};
+use crate::types::{ExactLink, NamespaceId, SubspaceId};
+
+/// The subspaces that have been counted, keyed by their namespace followed by their subspace.
+const QUOTA_SUBSPACES: TableDefinition<&[u8], ()> = TableDefinition::new("leaf_quota_subspaces");
+/// The size of each entity in the counted subspaces, keyed by the encoded [`ExactLink`] of the
+/// entity, which starts with its namespace and subspace.
+const QUOTA_ENTITIES: TableDefinition<&[u8], u64> = TableDefinition::new("leaf_quota_entities");
+
+/// The tables that the usage counts of quotas are persisted in.
+///
+/// Cloning the database is cheap and the clones share the same database.
+#[derive(Debug, Clone)]
+pub(super) struct QuotaDb {
+    db: Arc<Database>,
+}
+
+/// The key of a subspace, which is also the prefix of the keys of the entities in it.
+fn subspace_key(namespace: &NamespaceId, subspace: &SubspaceId) -> Vec<u8> {
+    [&namespace[..], &subspace[..]].concat()
+}
+
+impl QuotaDb {
+    /// Use the quota tables in `db`, creating them if they don't exist yet.
+    pub fn new(db: Arc<Database>) -> anyhow::Result<Self> {
+        let tx = db.begin_write()?;
+        {
+            // Make sure that the tables exist so that reads don't fail on a new database.
+            tx.open_table(QUOTA_SUBSPACES)?;
+            tx.open_table(QUOTA_ENTITIES)?;
+        }
+        tx.commit()?;
+        Ok(Self { db })
+    }
+
+    /// Load the usage of the subspaces that have been counted.
+    pub fn load(&self) -> anyhow::Result<HashMap<(NamespaceId, SubspaceId), SubspaceState>> {
+        let tx = self.db.begin_read()?;
+        let mut subspaces = HashMap::new();
+        for entry in tx.open_table(QUOTA_SUBSPACES)?.iter()? {
+            let (key, _) = entry?;
+            let key = <(NamespaceId, SubspaceId)>::try_from_slice(key.value())?;
+            subspaces.insert(key, SubspaceState::default());
+        }
+        for entry in tx.open_table(QUOTA_ENTITIES)?.iter()? {
+            let (link, size) = entry?;
+            let link = ExactLink::try_from_slice(link.value())?;
+            if let Some(state) = subspaces.get_mut(&(link.namespace, link.subspace)) {
+                state.insert(link.path, size.value());
+            }
+        }
+        Ok(subspaces)
+    }
+
+    /// Replace the sizes of the entities in a subspace with the ones in `subspaces`, removing the
+    /// subspace if it isn't in there.
+    ///
+    /// The sizes are read inside of the write transaction, so when the usage of a subspace is
+    /// changed by multiple tasks at once, the last transaction to commit writes the latest sizes.
+    pub async fn put_subspace(
+        &self,
+        subspaces: Subspaces,
+        namespace: NamespaceId,
+        subspace: SubspaceId,
+    ) -> anyhow::Result<()> {
+        self.write(move |tx| {
+            let prefix = subspace_key(&namespace, &subspace);
+            let mut table = tx.open_table(QUOTA_ENTITIES)?;
+            let stale = table
+                .range::<&[u8]>(&prefix[..]..)?
+                .map(|entry| entry.map(|(key, _)| key.value().to_vec()))
+                .take_while(|key| {
+                    key.as_ref()
+                        .map(|key| key.starts_with(&prefix))
+                        .unwrap_or(true)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            for key in stale {
+                table.remove(&key[..])?;
+            }
+
+            let subspaces = subspaces.read().unwrap();
+            let mut counted = tx.open_table(QUOTA_SUBSPACES)?;
+            let Some(state) = subspaces.get(&(namespace, subspace)) else {
+                counted.remove(&prefix[..])?;
+                return Ok(());
+            };
+            for (path, &size) in &state.entities {
+                let link = ExactLink {
+                    namespace,
+                    subspace,
+                    path: path.clone(),
+                };
+                table.insert(&borsh::to_vec(&link)?[..], size)?;
+            }
+            counted.insert(&prefix[..], ())?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Write the sizes of the entities at `links` from `subspaces`, removing the entities that
+    /// aren't in there.
+    ///
+    /// Like [`put_subspace()`][Self::put_subspace], the sizes are read inside of the write
+    /// transaction. Entities in subspaces that haven't been counted are skipped.
+    pub async fn put_entities(
+        &self,
+        subspaces: Subspaces,
+        links: Vec<ExactLink>,
+    ) -> anyhow::Result<()> {
+        self.write(move |tx| {
+            let subspaces = subspaces.read().unwrap();
+            let mut table = tx.open_table(QUOTA_ENTITIES)?;
+            for link in links {
+                let Some(state) = subspaces.get(&(link.namespace, link.subspace)) else {
+                    continue;
+                };
+                let key = borsh::to_vec(&link)?;
+                match state.entities.get(&link.path) {
+                    Some(&size) => table.insert(&key[..], size)?,
+                    None => table.remove(&key[..])?,
+                };
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Run a write transaction on a blocking thread, committing it if `f` succeeds.
+    async fn write<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&WriteTransaction) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || {
+            let tx = db.begin_write()?;
+            let value = f(&tx)?;
+            tx.commit()?;
+            Ok(value)
+        })
+        .await
+        .map_err(|_| anyhow::format_err!("Error executing database operation"))?
+    }
+}
@@ -0,0 +1,276 @@
+//! Full-text search over textual components.
+//!
+//! The [`SearchIndex`] is an in-memory inverted index over the text of the components on the
+//! entities in a namespace. A namespace is indexed the first time it is searched, and after that
+//! the index is kept up to date as entities are saved or deleted through the [`Leaf`][crate::Leaf]
+//! it is attached to.
+//!
+//! Changes that come from somewhere else, such as a sync from another node, will not be noticed
+//! until the namespace is re-indexed with [`Leaf::reindex()`][crate::Leaf::reindex].
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Result;
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::{
+    components::{CommonMark, Description, Name, Utf8},
+    store::LeafStore,
+    types::{ExactLink, NamespaceId},
+    Component, Digest, LoadedEntity,
+};
+
+/// BM25 term frequency saturation parameter.
+const K1: f32 = 1.2;
+/// BM25 document length normalization parameter.
+const B: f32 = 0.75;
+/// The amount that a match on a prefix of a term counts for, compared to an exact match.
+const PREFIX_MATCH_WEIGHT: f32 = 0.5;
+
+/// A ranked search result.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    /// The entity that matched.
+    pub link: ExactLink,
+    /// The relevance score of the match. Higher is better.
+    pub score: f32,
+}
+
+/// An in-memory full-text index over the textual components of entities.
+///
+/// Cloning the index is cheap and the clones share the same underlying index.
+#[derive(Debug, Clone)]
+pub struct SearchIndex {
+    /// The schemas of the components to index, and the weight of a term match in each of them.
+    ///
+    /// The data of each component must be a single borsh [`String`].
+    text_schemas: HashMap<Digest, f32>,
+    namespaces: Arc<RwLock<HashMap<NamespaceId, NamespaceIndex>>>,
+}
+
+impl Default for SearchIndex {
+    /// Create a search index over the [`Name`], [`Description`], [`Utf8`], and [`CommonMark`]
+    /// components.
+    fn default() -> Self {
+        Self::empty()
+            .with_text_component::<Name>(3.0)
+            .with_text_component::<Description>(1.5)
+            .with_text_component::<Utf8>(1.0)
+            .with_text_component::<CommonMark>(1.0)
+    }
+}
+
+impl SearchIndex {
+    /// Create a search index that doesn't index any components.
+    pub fn empty() -> Self {
+        Self {
+            text_schemas: Default::default(),
+            namespaces: Default::default(),
+        }
+    }
+
+    /// Index components with the given schema, multiplying the score of matches in it by `weight`.
+    ///
+    /// The data of the component must be a single borsh [`String`].
+    pub fn with_text_schema(mut self, schema: Digest, weight: f32) -> Self {
+        self.text_schemas.insert(schema, weight);
+        self
+    }
+
+    /// Index the given component type, multiplying the score of matches in it by `weight`.
+    pub fn with_text_component<C: Component>(self, weight: f32) -> Self {
+        self.with_text_schema(C::schema_id(), weight)
+    }
+
+    /// The schemas of the components that are indexed.
+    pub fn text_schemas(&self) -> impl Iterator<Item = Digest> + '_ {
+        self.text_schemas.keys().copied()
+    }
+
+    /// Whether the given namespace has been indexed yet.
+    pub fn is_indexed(&self, namespace: NamespaceId) -> bool {
+        self.namespaces.read().unwrap().contains_key(&namespace)
+    }
+
+    /// Replace the index for a namespace with the given entities and their texts.
+    pub fn index_namespace(
+        &self,
+        namespace: NamespaceId,
+        entities: impl IntoIterator<Item = (ExactLink, Vec<(Digest, String)>)>,
+    ) {
+        let mut index = NamespaceIndex::default();
+        for (link, texts) in entities {
+            index.insert(link, self.document(&texts));
+        }
+        self.namespaces.write().unwrap().insert(namespace, index);
+    }
+
+    /// Update the indexed texts of an entity.
+    ///
+    /// This does nothing if the entity's namespace hasn't been indexed yet, because the whole
+    /// namespace will be indexed the first time that it is searched.
+    pub fn index_entity(&self, link: &ExactLink, texts: &[(Digest, String)]) {
+        let document = self.document(texts);
+        let mut namespaces = self.namespaces.write().unwrap();
+        if let Some(index) = namespaces.get_mut(&link.namespace) {
+            index.remove(link);
+            index.insert(link.clone(), document);
+        }
+    }
+
+    /// Remove an entity from the index.
+    pub fn remove_entity(&self, link: &ExactLink) {
+        let mut namespaces = self.namespaces.write().unwrap();
+        if let Some(index) = namespaces.get_mut(&link.namespace) {
+            index.remove(link);
+        }
+    }
+
+    /// Search a namespace, returning up to `limit` results ordered from most to least relevant.
+    ///
+    /// Returns no results if the namespace hasn't been indexed.
+    pub fn search(&self, namespace: NamespaceId, query: &str, limit: usize) -> Vec<SearchResult> {
+        let namespaces = self.namespaces.read().unwrap();
+        let Some(index) = namespaces.get(&namespace) else {
+            return Vec::new();
+        };
+        index.search(query, limit)
+    }
+
+    /// Get the texts of the indexed components on an entity.
+    pub(crate) async fn entity_texts<S: LeafStore>(
+        &self,
+        entity: &LoadedEntity<S>,
+    ) -> Result<Vec<(Digest, String)>> {
+        let mut texts = Vec::new();
+        for &schema in self.text_schemas.keys() {
+            for data in entity.get_components_by_schema(schema).await? {
+                if let Ok(text) = String::deserialize(&mut &data[..]) {
+                    texts.push((schema, text));
+                }
+            }
+        }
+        Ok(texts)
+    }
+
+    fn document(&self, texts: &[(Digest, String)]) -> Document {
+        let mut document = Document::default();
+        for (schema, text) in texts {
+            let weight = self.text_schemas.get(schema).copied().unwrap_or(1.0);
+            for token in tokenize(text) {
+                *document.terms.entry(token).or_default() += weight;
+                document.len += weight;
+            }
+        }
+        document
+    }
+}
+
+/// The indexed text of a single entity.
+#[derive(Debug, Default)]
+struct Document {
+    /// The weighted number of times each term appears in the entity.
+    terms: HashMap<String, f32>,
+    /// The weighted number of terms in the entity.
+    len: f32,
+}
+
+#[derive(Debug, Default)]
+struct NamespaceIndex {
+    documents: HashMap<ExactLink, Document>,
+    /// The entities that contain each term.
+    postings: BTreeMap<String, HashSet<ExactLink>>,
+    total_len: f32,
+}
+
+impl NamespaceIndex {
+    fn insert(&mut self, link: ExactLink, document: Document) {
+        if document.terms.is_empty() {
+            return;
+        }
+        for term in document.terms.keys() {
+            self.postings
+                .entry(term.clone())
+                .or_default()
+                .insert(link.clone());
+        }
+        self.total_len += document.len;
+        self.documents.insert(link, document);
+    }
+
+    fn remove(&mut self, link: &ExactLink) {
+        let Some(document) = self.documents.remove(link) else {
+            return;
+        };
+        for term in document.terms.keys() {
+            if let Some(links) = self.postings.get_mut(term) {
+                links.remove(link);
+                if links.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+        self.total_len -= document.len;
+    }
+
+    fn search(&self, query: &str, limit: usize) -> Vec<SearchResult> {
+        if self.documents.is_empty() {
+            return Vec::new();
+        }
+        let document_count = self.documents.len() as f32;
+        let avg_len = self.total_len / document_count;
+
+        let tokens = tokenize(query).collect::<Vec<_>>();
+        let mut scores = HashMap::<&ExactLink, f32>::new();
+        for (i, token) in tokens.iter().enumerate() {
+            // Allow the last token to match as a prefix so that results show up while the user is
+            // still typing.
+            let is_last = i == tokens.len() - 1;
+            let matches = self
+                .postings
+                .range(token.clone()..)
+                .take_while(|(term, _)| *term == token || (is_last && term.starts_with(token)));
+            for (term, links) in matches {
+                let match_weight = if term == token {
+                    1.0
+                } else {
+                    PREFIX_MATCH_WEIGHT
+                };
+                let n = links.len() as f32;
+                let idf = (1.0 + (document_count - n + 0.5) / (n + 0.5)).ln();
+                for link in links {
+                    let document = &self.documents[link];
+                    let tf = document.terms[term];
+                    let norm = K1 * (1.0 - B + B * document.len / avg_len);
+                    *scores.entry(link).or_default() +=
+                        match_weight * idf * tf * (K1 + 1.0) / (tf + norm);
+                }
+            }
+        }
+
+        let mut results = scores
+            .into_iter()
+            .map(|(link, score)| SearchResult {
+                link: link.clone(),
+                score,
+            })
+            .collect::<Vec<_>>();
+        results.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.link.cmp(&b.link))
+        });
+        results.truncate(limit);
+        results
+    }
+}
+
+/// Split text into lowercase alphanumeric terms.
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+}
@@ -3,15 +3,30 @@
 use std::{fmt::Debug, future::Future};
 
 use anyhow::Result;
+use borsh::{BorshDeserialize, BorshSerialize};
 use futures::Stream;
 
 use crate::{
-    types::{ExactLink, NamespaceId, NamespaceSecretKey, SubspaceId, SubspaceSecretKey},
+    types::{
+        EntityPath, ExactLink, NamespaceId, NamespaceSecretKey, SubspaceId, SubspaceSecretKey,
+    },
     Digest,
 };
 
+pub mod boxed;
+#[cfg(feature = "cache")]
+pub mod cache;
+pub mod conformance;
+#[cfg(feature = "instrument")]
+pub mod instrument;
 #[cfg(feature = "backend_iroh")]
 pub mod iroh;
+#[cfg(feature = "backend_memory")]
+pub mod memory;
+#[cfg(feature = "backend_redb")]
+pub mod redb;
+
+pub use boxed::DynLeafStore;
 
 pub trait KeyResolverImpl<KeyId> {
     /// Returns the `EncryptionAlgorithmId` that this implements.
@@ -29,11 +44,11 @@ pub trait EncryptionAlgorithmImpl<Digest> {
     fn decrypt(&self, key_id: [u8; 32], data: &[u8]) -> Vec<u8>;
 }
 
-// TODO: Find way to avoid leaking blobs in the garbage collector.
+// NOTE: Blob pins may be leaked by the garbage collector.
 //
-// Right now the garbage collector cleans up data by the fact that every time you overwrite an
-// entity, it will look at the previous components of the entity, and remove all of the GC pins for
-// those components.
+// The garbage collector cleans up data by the fact that every time you overwrite an entity, it will
+// look at the previous components of the entity, and remove all of the GC pins for those
+// components.
 //
 // The problem is that if a sync comes and inserts an update in between the time that I read the
 // previous version of the entity, and the time that I overwrite the previous version, I will have
@@ -41,9 +56,278 @@ pub trait EncryptionAlgorithmImpl<Digest> {
 // collector pins.
 //
 // This situation means that live data should never have a problem getting deleted, but some dead
-// data might get left in the `_leaf_gc_` table forever.
+// data might get left in the `_leaf_gc_` table. Stores where this can happen, like the Iroh store,
+// provide a mark-and-sweep pass, such as `LeafIrohStore::collect_garbage()`, to clean it up.
+
+/// The result of a garbage collection pass over a store.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// The number of leaked blob pins that were removed.
+    pub pins: u64,
+    /// The number of blobs that are no longer pinned by any entity snapshot.
+    pub blobs: u64,
+    /// The total size of the blobs that are no longer pinned, in bytes.
+    pub bytes: u64,
+}
+
+/// What we are allowed to do in a namespace.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NamespaceCapability {
+    /// We can read and sync the namespace, but not write to it.
+    Read,
+    /// We have the namespace secret and can write to the namespace.
+    Write,
+}
+
+/// Error returned when writing to a namespace that we don't have the secret for.
+///
+/// A read-only namespace can be made writable by importing its secret with
+/// [`LeafStore::import_namespace_secret()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadOnlyNamespace {
+    pub namespace: NamespaceId,
+}
+
+impl std::fmt::Display for ReadOnlyNamespace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Digests are displayed in the same base32 format that we use for namespace IDs.
+        write!(
+            f,
+            "Cannot write to read-only namespace {}. Import the namespace secret to write to it.",
+            Digest::from_bytes(self.namespace)
+        )
+    }
+}
+
+impl std::error::Error for ReadOnlyNamespace {}
+
+/// Prefixed to the message signed by an [`EntitySignature`], so that the signature can't be
+/// mistaken for a signature over something else made with the same key.
+const ENTITY_SIGNATURE_CONTEXT: &[u8] = b"leaf-entity-signature-v1";
+
+/// A signature by the author of a subspace over an entity snapshot.
+///
+/// Subspace IDs are ed25519 public keys, so the signature proves that the holder of the subspace
+/// secret wrote the snapshot with the given digest at the namespace, subspace, and path of the
+/// entity. Signatures are deterministic, so signing the same snapshot again gives the same
+/// signature.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntitySignature(pub [u8; 64]);
+
+impl EntitySignature {
+    fn message(link: &ExactLink, digest: Digest) -> Vec<u8> {
+        let mut message = ENTITY_SIGNATURE_CONTEXT.to_vec();
+        borsh::to_writer(&mut message, &(link, digest)).unwrap();
+        message
+    }
+
+    /// Sign the entity snapshot with `digest` at `link` with the secret of its subspace.
+    pub fn sign(subspace_secret: &SubspaceSecretKey, link: &ExactLink, digest: Digest) -> Self {
+        let secret = iroh_base::key::SecretKey::from_bytes(subspace_secret);
+        Self(secret.sign(&Self::message(link, digest)).to_bytes())
+    }
+
+    /// Check that the signature was made by the author of the subspace of `link` for the entity
+    /// snapshot with `digest`, returning an [`InvalidEntitySignature`] error if it wasn't.
+    pub fn verify(&self, link: &ExactLink, digest: Digest) -> Result<(), InvalidEntitySignature> {
+        let error = || InvalidEntitySignature {
+            link: link.clone(),
+            digest,
+        };
+        let author = iroh_base::key::PublicKey::from_bytes(&link.subspace).map_err(|_| error())?;
+        let signature = iroh_base::key::Signature::from_bytes(&self.0);
+        author
+            .verify(&Self::message(link, digest), &signature)
+            .map_err(|_| error())
+    }
+}
 
-pub trait LeafStore: Debug {
+/// Error returned when an [`EntitySignature`] was not made by the author of the entity's subspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidEntitySignature {
+    pub link: ExactLink,
+    pub digest: Digest,
+}
+
+impl std::fmt::Display for InvalidEntitySignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The signature of the entity snapshot {} at {:?} was not made by the author of its \
+            subspace.",
+            self.digest, self.link
+        )
+    }
+}
+
+impl std::error::Error for InvalidEntitySignature {}
+
+/// Proof that the author of a subspace wrote an entity snapshot, which lets the snapshot be
+/// stored with [`LeafStore::store_signed_entity()`] by stores that don't have the subspace secret.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub enum EntityAuthorship {
+    /// An [`EntitySignature`], made by the stores that don't keep signatures of their own.
+    Signature(EntitySignature),
+    /// The signed Iroh document entry that the entity was written with, encoded with postcard.
+    ///
+    /// The entry carries the signatures of the subspace author and the namespace, and the
+    /// timestamp of the entry, so storing it in an Iroh document restores the entry exactly as
+    /// the author wrote it.
+    IrohEntry(Vec<u8>),
+}
+
+impl EntityAuthorship {
+    /// Check that the entity snapshot with `digest` at `link` was written by the author of its
+    /// subspace, returning an [`InvalidEntitySignature`] error if it wasn't.
+    ///
+    /// Iroh entries can only be checked with the `backend_iroh` feature.
+    pub fn verify(&self, link: &ExactLink, digest: Digest) -> Result<()> {
+        match self {
+            EntityAuthorship::Signature(signature) => Ok(signature.verify(link, digest)?),
+            #[cfg(feature = "backend_iroh")]
+            EntityAuthorship::IrohEntry(entry) => {
+                self::iroh::verify_signed_entry(entry, link, digest)?;
+                Ok(())
+            }
+            #[cfg(not(feature = "backend_iroh"))]
+            EntityAuthorship::IrohEntry(_) => {
+                anyhow::bail!("Checking signed Iroh entries needs the `backend_iroh` feature.")
+            }
+        }
+    }
+}
+
+/// The access that a namespace share ticket grants to the node that joins with it.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareMode {
+    /// The node can read and sync the namespace, but not write to it.
+    Read,
+    /// The node gets the namespace secret and can write to the namespace.
+    Write,
+}
+
+/// A node that a namespace is synced with.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SyncPeer {
+    /// The public key of the peer node.
+    pub node_id: [u8; 32],
+    /// How we are currently connected to the peer.
+    pub connection: PeerConnection,
+    /// The latency of the connection, in milliseconds, if it is known.
+    pub latency_ms: Option<u64>,
+}
+
+/// How we are connected to a [`SyncPeer`].
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerConnection {
+    /// We don't have a working connection to the peer.
+    None,
+    /// We are connected directly to the peer.
+    Direct,
+    /// We are connected to the peer through a relay server.
+    Relay,
+    /// We are connected both through a relay and a direct address that hasn't been confirmed to
+    /// work yet.
+    Mixed,
+}
+
+/// A [`LeafStore`] method, used to label [`StoreMetrics`].
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StoreOperation {
+    CreateSubspace,
+    GetSubspaceSecret,
+    ListSubspaces,
+    ImportSubspaceSecret,
+    CreateNamespace,
+    ListNamespaces,
+    GetNamespaceSecret,
+    ImportNamespaceSecret,
+    ListNamespaceSubspaces,
+    StoreBlob,
+    DelBlobs,
+    GetBlob,
+    StoreEntity,
+    DelEntity,
+    GetEntity,
+    List,
+    ShareNamespace,
+    JoinNamespace,
+    ListSyncPeers,
+    StoreSignedEntity,
+    GetEntitySignature,
+}
+
+impl StoreOperation {
+    /// Every operation, in declaration order.
+    pub const ALL: [StoreOperation; 21] = [
+        StoreOperation::CreateSubspace,
+        StoreOperation::GetSubspaceSecret,
+        StoreOperation::ListSubspaces,
+        StoreOperation::ImportSubspaceSecret,
+        StoreOperation::CreateNamespace,
+        StoreOperation::ListNamespaces,
+        StoreOperation::GetNamespaceSecret,
+        StoreOperation::ImportNamespaceSecret,
+        StoreOperation::ListNamespaceSubspaces,
+        StoreOperation::StoreBlob,
+        StoreOperation::DelBlobs,
+        StoreOperation::GetBlob,
+        StoreOperation::StoreEntity,
+        StoreOperation::DelEntity,
+        StoreOperation::GetEntity,
+        StoreOperation::List,
+        StoreOperation::ShareNamespace,
+        StoreOperation::JoinNamespace,
+        StoreOperation::ListSyncPeers,
+        StoreOperation::StoreSignedEntity,
+        StoreOperation::GetEntitySignature,
+    ];
+}
+
+/// The upper bounds of the buckets in an [`OperationMetrics`] latency histogram, in microseconds.
+///
+/// Calls that take longer than the last bound are counted in one extra bucket at the end.
+pub const LATENCY_BUCKETS_US: [u64; 12] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 1_000_000,
+];
+
+/// The number of calls to a [`StoreOperation`] and how long they took.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OperationMetrics {
+    pub operation: StoreOperation,
+    /// The number of calls, including the ones that failed.
+    pub calls: u64,
+    /// The number of calls that returned an error.
+    pub errors: u64,
+    /// The total time spent in the operation, in microseconds.
+    pub total_us: u64,
+    /// The number of calls in each [`LATENCY_BUCKETS_US`] bucket, followed by the calls that took
+    /// longer than the last bound.
+    ///
+    /// The buckets are not cumulative, so each call is only counted in one of them. Operations
+    /// that return a stream are timed until the stream is returned, not until it is consumed.
+    pub latency_buckets: Vec<u64>,
+}
+
+/// A snapshot of the metrics recorded by a
+/// [`LeafInstrumentedStore`][crate::store::instrument::LeafInstrumentedStore].
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct StoreMetrics {
+    /// The metrics of each operation that has been called at least once.
+    pub operations: Vec<OperationMetrics>,
+    /// The number of bytes returned by `get_blob()`.
+    pub blob_bytes_read: u64,
+    /// The number of bytes passed to `store_blob()`.
+    pub blob_bytes_written: u64,
+    /// The number of bytes of entity data passed to `store_entity()`.
+    pub entity_bytes_written: u64,
+}
+
+/// A backend that stores the entities and blobs used by [`Leaf`][crate::Leaf].
+///
+/// The futures and streams returned by a store must be [`Send`], so that code that is generic over
+/// the store can run them on multi-threaded runtimes.
+pub trait LeafStore: Debug + Send + Sync {
     /// Get an iterator over key resolver algorithms implemented by this backend.
     // TODO: try avoid allocating while still being object safe.
     fn key_resolvers(&self) -> Box<dyn Iterator<Item = &dyn KeyResolverImpl<Digest>> + '_>;
@@ -52,34 +336,50 @@ pub trait LeafStore: Debug {
         &self,
     ) -> Box<dyn Iterator<Item = &dyn EncryptionAlgorithmImpl<Digest>> + '_>;
 
-    fn create_subspace(&self) -> impl Future<Output = Result<SubspaceId>>;
+    fn create_subspace(&self) -> impl Future<Output = Result<SubspaceId>> + Send;
     fn get_subspace_secret(
         &self,
         subspace: SubspaceId,
-    ) -> impl Future<Output = Result<Option<SubspaceSecretKey>>>;
+    ) -> impl Future<Output = Result<Option<SubspaceSecretKey>>> + Send;
     fn list_subspaces(
         &self,
-    ) -> impl Future<Output = Result<impl Stream<Item = anyhow::Result<SubspaceId>>>>;
+    ) -> impl Future<Output = Result<impl Stream<Item = Result<SubspaceId>> + Send>> + Send;
     fn import_subspace_secret(
         &self,
         subspace_secret: SubspaceSecretKey,
-    ) -> impl Future<Output = Result<SubspaceId>>;
+    ) -> impl Future<Output = Result<SubspaceId>> + Send;
 
-    fn create_namespace(&self) -> impl Future<Output = Result<NamespaceId>>;
+    fn create_namespace(&self) -> impl Future<Output = Result<NamespaceId>> + Send;
+    /// List the namespaces in the store, and whether we can write to them.
     fn list_namespaces(
         &self,
-    ) -> impl Future<Output = Result<impl Stream<Item = anyhow::Result<NamespaceId>>>>;
+    ) -> impl Future<
+        Output = Result<impl Stream<Item = Result<(NamespaceId, NamespaceCapability)>> + Send>,
+    > + Send;
     fn get_namespace_secret(
         &self,
         namespace: NamespaceId,
-    ) -> impl Future<Output = Result<Option<NamespaceSecretKey>>>;
+    ) -> impl Future<Output = Result<Option<NamespaceSecretKey>>> + Send;
+    /// Import a namespace secret, which also makes the namespace writable if we could only read it
+    /// before.
     fn import_namespace_secret(
         &self,
         secret: [u8; 32],
-    ) -> impl Future<Output = Result<NamespaceId>>;
+    ) -> impl Future<Output = Result<NamespaceId>> + Send;
+    /// List the subspaces that have entities in the given namespace.
+    ///
+    /// Unlike [`LeafStore::list_subspaces()`] this is not limited to the subspaces that we have
+    /// secrets for.
+    fn list_namespace_subspaces(
+        &self,
+        namespace: NamespaceId,
+    ) -> impl Future<Output = Result<impl Stream<Item = Result<SubspaceId>> + Send>> + Send;
 
     /// Store a blob for an entity snapshot.
     ///
+    /// This, and the other methods that write to a namespace, return a [`ReadOnlyNamespace`] error
+    /// if we don't have the namespace secret.
+    ///
     /// You must specify a namespace to associate the blob to, an entity path, and an entity
     /// snapshot.
     ///
@@ -90,7 +390,7 @@ pub trait LeafStore: Debug {
         data: &[u8],
         link: &ExactLink,
         entity_snapshot_id: Digest,
-    ) -> impl Future<Output = Result<Digest>>;
+    ) -> impl Future<Output = Result<Digest>> + Send;
     /// Delete a blob. This doesn't necessarily delete the blob immediately, but it removes the
     /// garbage collector pin for the entity snapshot.
     ///
@@ -99,19 +399,84 @@ pub trait LeafStore: Debug {
         &self,
         link: &ExactLink,
         entity_snapshot_id: Digest,
-    ) -> impl Future<Output = Result<usize>>;
+    ) -> impl Future<Output = Result<usize>> + Send;
     /// Get's a blob from the local store.
-    fn get_blob(&self, digest: Digest) -> impl Future<Output = Result<Vec<u8>>>;
+    fn get_blob(&self, digest: Digest) -> impl Future<Output = Result<Vec<u8>>> + Send;
 
-    fn store_entity(&self, link: &ExactLink, data: Vec<u8>)
-        -> impl Future<Output = Result<Digest>>;
-    fn del_entity(&self, link: &ExactLink) -> impl Future<Output = Result<()>>;
-    fn get_entity(&self, link: &ExactLink) -> impl Future<Output = Result<Option<Digest>>>;
+    fn store_entity(
+        &self,
+        link: &ExactLink,
+        data: Vec<u8>,
+    ) -> impl Future<Output = Result<Digest>> + Send;
+    fn del_entity(&self, link: &ExactLink) -> impl Future<Output = Result<()>> + Send;
+    fn get_entity(&self, link: &ExactLink) -> impl Future<Output = Result<Option<Digest>>> + Send;
+    /// Store an entity that was written by the author of its subspace somewhere else, such as in
+    /// an archive, after checking that `authorship` proves that the author wrote `data`.
+    ///
+    /// Unlike [`LeafStore::store_entity()`] this doesn't need the subspace secret, but it still
+    /// needs the namespace secret. Returns an [`InvalidEntitySignature`] error if the authorship
+    /// doesn't match. The Iroh store can only store [`EntityAuthorship::Signature`]s for subspaces
+    /// that it has the secret of, and fails if it already has a newer entry from the author.
+    fn store_signed_entity(
+        &self,
+        link: &ExactLink,
+        data: Vec<u8>,
+        authorship: EntityAuthorship,
+    ) -> impl Future<Output = Result<Digest>> + Send;
+    /// Get the proof that the subspace author wrote the current snapshot of the entity at
+    /// `link`.
+    ///
+    /// Returns [`None`] if there is no entity at `link`, or if we don't have the subspace secret
+    /// and the store doesn't have the authorship that the entity was stored with.
+    fn get_entity_signature(
+        &self,
+        link: &ExactLink,
+    ) -> impl Future<Output = Result<Option<EntityAuthorship>>> + Send;
 
+    /// List the entities with paths that start with the path of `link`, including the entity at
+    /// `link` itself if there is one.
+    ///
+    /// If `depth` is set, only entities at most `depth` path segments below `link` are listed, so
+    /// a depth of `1` lists the direct children of `link`. Stores should skip over the entities
+    /// below a path that is too deep instead of reading each of them, so that listing the direct
+    /// children of an entity with many descendants doesn't cost as much as listing all of them.
+    ///
+    /// Entities are always listed in the same, store-defined, order, which is not necessarily the
+    /// [`Ord`] order of their paths. If `after` is set, listing resumes after the entity with that
+    /// path, which allows paginating by passing the path of the last entity from the previous
+    /// page. The entity at `after` doesn't need to exist anymore.
     fn list(
         &self,
         link: ExactLink,
+        depth: Option<u32>,
+        after: Option<EntityPath>,
         limit: Option<u64>,
-        offset: Option<u64>,
-    ) -> impl Future<Output = Result<impl Stream<Item = anyhow::Result<ExactLink>>>>;
+    ) -> impl Future<Output = Result<impl Stream<Item = Result<ExactLink>> + Send>> + Send;
+
+    /// Create a ticket that another node can use to join and sync the namespace with
+    /// [`LeafStore::join_namespace()`].
+    ///
+    /// Stores that can't sync with other nodes return an error.
+    fn share_namespace(
+        &self,
+        namespace: NamespaceId,
+        mode: ShareMode,
+    ) -> impl Future<Output = Result<String>> + Send {
+        let _ = (namespace, mode);
+        async { anyhow::bail!("Namespace sharing is not supported by this Leaf store.") }
+    }
+    /// Join a namespace with a ticket from [`LeafStore::share_namespace()`], and start syncing it
+    /// with the nodes in the ticket.
+    fn join_namespace(&self, ticket: &str) -> impl Future<Output = Result<NamespaceId>> + Send {
+        let _ = ticket;
+        async { anyhow::bail!("Namespace sharing is not supported by this Leaf store.") }
+    }
+    /// List the nodes that the namespace has been synced with.
+    fn list_sync_peers(
+        &self,
+        namespace: NamespaceId,
+    ) -> impl Future<Output = Result<Vec<SyncPeer>>> + Send {
+        let _ = namespace;
+        async { anyhow::bail!("Namespace sharing is not supported by this Leaf store.") }
+    }
 }
@@ -0,0 +1,400 @@
+//! An object-safe version of [`LeafStore`].
+//!
+//! [`LeafStore`] returns `impl Future`s and `impl Stream`s, so it can't be used as a trait object.
+//! [`DynLeafStore`] is implemented for every [`LeafStore`] and returns boxed futures and streams
+//! instead, and `Box<dyn DynLeafStore>` implements [`LeafStore`] again. This allows picking a
+//! backend at runtime, or writing stores that wrap any other store, with `Leaf<Box<dyn
+//! DynLeafStore>>`.
+//!
+//! [`DynLeafStore`] has the same method names as [`LeafStore`], so the two traits shouldn't be
+//! imported into the same scope.
+
+use std::fmt::Debug;
+
+use anyhow::Result;
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
+
+use crate::{
+    store::{
+        EncryptionAlgorithmImpl, EntityAuthorship, KeyResolverImpl, LeafStore, NamespaceCapability,
+        ShareMode, SyncPeer,
+    },
+    types::{
+        EntityPath, ExactLink, NamespaceId, NamespaceSecretKey, SubspaceId, SubspaceSecretKey,
+    },
+    Digest,
+};
+
+/// The namespaces listed by [`DynLeafStore::list_namespaces()`], with our capability for each.
+pub type NamespaceStream<'a> = BoxStream<'a, Result<(NamespaceId, NamespaceCapability)>>;
+
+/// Object-safe version of [`LeafStore`], which is implemented for all [`LeafStore`]s.
+///
+/// See the [module documentation][self] for more info.
+pub trait DynLeafStore: Debug + Send + Sync {
+    fn key_resolvers(&self) -> Box<dyn Iterator<Item = &dyn KeyResolverImpl<Digest>> + '_>;
+    fn encryption_algorithms(
+        &self,
+    ) -> Box<dyn Iterator<Item = &dyn EncryptionAlgorithmImpl<Digest>> + '_>;
+
+    fn create_subspace(&self) -> BoxFuture<'_, Result<SubspaceId>>;
+    fn get_subspace_secret(
+        &self,
+        subspace: SubspaceId,
+    ) -> BoxFuture<'_, Result<Option<SubspaceSecretKey>>>;
+    fn list_subspaces(&self) -> BoxFuture<'_, Result<BoxStream<'_, Result<SubspaceId>>>>;
+    fn import_subspace_secret(
+        &self,
+        subspace_secret: SubspaceSecretKey,
+    ) -> BoxFuture<'_, Result<SubspaceId>>;
+
+    fn create_namespace(&self) -> BoxFuture<'_, Result<NamespaceId>>;
+    fn list_namespaces(&self) -> BoxFuture<'_, Result<NamespaceStream<'_>>>;
+    fn get_namespace_secret(
+        &self,
+        namespace: NamespaceId,
+    ) -> BoxFuture<'_, Result<Option<NamespaceSecretKey>>>;
+    fn import_namespace_secret(&self, secret: [u8; 32]) -> BoxFuture<'_, Result<NamespaceId>>;
+    fn list_namespace_subspaces(
+        &self,
+        namespace: NamespaceId,
+    ) -> BoxFuture<'_, Result<BoxStream<'_, Result<SubspaceId>>>>;
+
+    fn store_blob<'a>(
+        &'a self,
+        data: &'a [u8],
+        link: &'a ExactLink,
+        entity_snapshot_id: Digest,
+    ) -> BoxFuture<'a, Result<Digest>>;
+    fn del_blobs<'a>(
+        &'a self,
+        link: &'a ExactLink,
+        entity_snapshot_id: Digest,
+    ) -> BoxFuture<'a, Result<usize>>;
+    fn get_blob(&self, digest: Digest) -> BoxFuture<'_, Result<Vec<u8>>>;
+
+    fn store_entity<'a>(
+        &'a self,
+        link: &'a ExactLink,
+        data: Vec<u8>,
+    ) -> BoxFuture<'a, Result<Digest>>;
+    fn del_entity<'a>(&'a self, link: &'a ExactLink) -> BoxFuture<'a, Result<()>>;
+    fn get_entity<'a>(&'a self, link: &'a ExactLink) -> BoxFuture<'a, Result<Option<Digest>>>;
+    fn store_signed_entity<'a>(
+        &'a self,
+        link: &'a ExactLink,
+        data: Vec<u8>,
+        authorship: EntityAuthorship,
+    ) -> BoxFuture<'a, Result<Digest>>;
+    fn get_entity_signature<'a>(
+        &'a self,
+        link: &'a ExactLink,
+    ) -> BoxFuture<'a, Result<Option<EntityAuthorship>>>;
+    fn list(
+        &self,
+        link: ExactLink,
+        depth: Option<u32>,
+        after: Option<EntityPath>,
+        limit: Option<u64>,
+    ) -> BoxFuture<'_, Result<BoxStream<'_, Result<ExactLink>>>>;
+
+    fn share_namespace(
+        &self,
+        namespace: NamespaceId,
+        mode: ShareMode,
+    ) -> BoxFuture<'_, Result<String>>;
+    fn join_namespace<'a>(&'a self, ticket: &'a str) -> BoxFuture<'a, Result<NamespaceId>>;
+    fn list_sync_peers(&self, namespace: NamespaceId) -> BoxFuture<'_, Result<Vec<SyncPeer>>>;
+
+    /// Clone the store into a new box.
+    fn clone_box(&self) -> Box<dyn DynLeafStore>;
+}
+
+impl<S: LeafStore + Clone + 'static> DynLeafStore for S {
+    fn key_resolvers(&self) -> Box<dyn Iterator<Item = &dyn KeyResolverImpl<Digest>> + '_> {
+        LeafStore::key_resolvers(self)
+    }
+
+    fn encryption_algorithms(
+        &self,
+    ) -> Box<dyn Iterator<Item = &dyn EncryptionAlgorithmImpl<Digest>> + '_> {
+        LeafStore::encryption_algorithms(self)
+    }
+
+    fn create_subspace(&self) -> BoxFuture<'_, Result<SubspaceId>> {
+        LeafStore::create_subspace(self).boxed()
+    }
+
+    fn get_subspace_secret(
+        &self,
+        subspace: SubspaceId,
+    ) -> BoxFuture<'_, Result<Option<SubspaceSecretKey>>> {
+        LeafStore::get_subspace_secret(self, subspace).boxed()
+    }
+
+    fn list_subspaces(&self) -> BoxFuture<'_, Result<BoxStream<'_, Result<SubspaceId>>>> {
+        async move { Ok(LeafStore::list_subspaces(self).await?.boxed()) }.boxed()
+    }
+
+    fn import_subspace_secret(
+        &self,
+        subspace_secret: SubspaceSecretKey,
+    ) -> BoxFuture<'_, Result<SubspaceId>> {
+        LeafStore::import_subspace_secret(self, subspace_secret).boxed()
+    }
+
+    fn create_namespace(&self) -> BoxFuture<'_, Result<NamespaceId>> {
+        LeafStore::create_namespace(self).boxed()
+    }
+
+    fn list_namespaces(&self) -> BoxFuture<'_, Result<NamespaceStream<'_>>> {
+        async move { Ok(LeafStore::list_namespaces(self).await?.boxed()) }.boxed()
+    }
+
+    fn get_namespace_secret(
+        &self,
+        namespace: NamespaceId,
+    ) -> BoxFuture<'_, Result<Option<NamespaceSecretKey>>> {
+        LeafStore::get_namespace_secret(self, namespace).boxed()
+    }
+
+    fn import_namespace_secret(&self, secret: [u8; 32]) -> BoxFuture<'_, Result<NamespaceId>> {
+        LeafStore::import_namespace_secret(self, secret).boxed()
+    }
+
+    fn list_namespace_subspaces(
+        &self,
+        namespace: NamespaceId,
+    ) -> BoxFuture<'_, Result<BoxStream<'_, Result<SubspaceId>>>> {
+        async move {
+            Ok(LeafStore::list_namespace_subspaces(self, namespace)
+                .await?
+                .boxed())
+        }
+        .boxed()
+    }
+
+    fn store_blob<'a>(
+        &'a self,
+        data: &'a [u8],
+        link: &'a ExactLink,
+        entity_snapshot_id: Digest,
+    ) -> BoxFuture<'a, Result<Digest>> {
+        LeafStore::store_blob(self, data, link, entity_snapshot_id).boxed()
+    }
+
+    fn del_blobs<'a>(
+        &'a self,
+        link: &'a ExactLink,
+        entity_snapshot_id: Digest,
+    ) -> BoxFuture<'a, Result<usize>> {
+        LeafStore::del_blobs(self, link, entity_snapshot_id).boxed()
+    }
+
+    fn get_blob(&self, digest: Digest) -> BoxFuture<'_, Result<Vec<u8>>> {
+        LeafStore::get_blob(self, digest).boxed()
+    }
+
+    fn store_entity<'a>(
+        &'a self,
+        link: &'a ExactLink,
+        data: Vec<u8>,
+    ) -> BoxFuture<'a, Result<Digest>> {
+        LeafStore::store_entity(self, link, data).boxed()
+    }
+
+    fn del_entity<'a>(&'a self, link: &'a ExactLink) -> BoxFuture<'a, Result<()>> {
+        LeafStore::del_entity(self, link).boxed()
+    }
+
+    fn get_entity<'a>(&'a self, link: &'a ExactLink) -> BoxFuture<'a, Result<Option<Digest>>> {
+        LeafStore::get_entity(self, link).boxed()
+    }
+
+    fn store_signed_entity<'a>(
+        &'a self,
+        link: &'a ExactLink,
+        data: Vec<u8>,
+        authorship: EntityAuthorship,
+    ) -> BoxFuture<'a, Result<Digest>> {
+        LeafStore::store_signed_entity(self, link, data, authorship).boxed()
+    }
+
+    fn get_entity_signature<'a>(
+        &'a self,
+        link: &'a ExactLink,
+    ) -> BoxFuture<'a, Result<Option<EntityAuthorship>>> {
+        LeafStore::get_entity_signature(self, link).boxed()
+    }
+
+    fn list(
+        &self,
+        link: ExactLink,
+        depth: Option<u32>,
+        after: Option<EntityPath>,
+        limit: Option<u64>,
+    ) -> BoxFuture<'_, Result<BoxStream<'_, Result<ExactLink>>>> {
+        async move {
+            Ok(LeafStore::list(self, link, depth, after, limit)
+                .await?
+                .boxed())
+        }
+        .boxed()
+    }
+
+    fn share_namespace(
+        &self,
+        namespace: NamespaceId,
+        mode: ShareMode,
+    ) -> BoxFuture<'_, Result<String>> {
+        LeafStore::share_namespace(self, namespace, mode).boxed()
+    }
+
+    fn join_namespace<'a>(&'a self, ticket: &'a str) -> BoxFuture<'a, Result<NamespaceId>> {
+        LeafStore::join_namespace(self, ticket).boxed()
+    }
+
+    fn list_sync_peers(&self, namespace: NamespaceId) -> BoxFuture<'_, Result<Vec<SyncPeer>>> {
+        LeafStore::list_sync_peers(self, namespace).boxed()
+    }
+
+    fn clone_box(&self) -> Box<dyn DynLeafStore> {
+        Box::new(self.clone())
+    }
+}
+
+impl<'s> Clone for Box<dyn DynLeafStore + 's> {
+    fn clone(&self) -> Self {
+        // The box is a `DynLeafStore` itself, so make sure to clone the store inside of it.
+        DynLeafStore::clone_box(&**self)
+    }
+}
+
+// Implemented for boxes with any lifetime, instead of only `'static` ones, because the compiler
+// can lose track of the lifetime inside of async blocks. Otherwise it can't tell that a future
+// using a `Leaf<Box<dyn DynLeafStore>>` is `Send`, so it can't be spawned.
+impl<'s> LeafStore for Box<dyn DynLeafStore + 's> {
+    fn key_resolvers(&self) -> Box<dyn Iterator<Item = &dyn KeyResolverImpl<Digest>> + '_> {
+        DynLeafStore::key_resolvers(&**self)
+    }
+
+    fn encryption_algorithms(
+        &self,
+    ) -> Box<dyn Iterator<Item = &dyn EncryptionAlgorithmImpl<Digest>> + '_> {
+        DynLeafStore::encryption_algorithms(&**self)
+    }
+
+    async fn create_subspace(&self) -> Result<SubspaceId> {
+        DynLeafStore::create_subspace(&**self).await
+    }
+
+    async fn get_subspace_secret(&self, subspace: SubspaceId) -> Result<Option<SubspaceSecretKey>> {
+        DynLeafStore::get_subspace_secret(&**self, subspace).await
+    }
+
+    async fn list_subspaces(
+        &self,
+    ) -> Result<impl futures::Stream<Item = anyhow::Result<SubspaceId>>> {
+        DynLeafStore::list_subspaces(&**self).await
+    }
+
+    async fn import_subspace_secret(
+        &self,
+        subspace_secret: SubspaceSecretKey,
+    ) -> Result<SubspaceId> {
+        DynLeafStore::import_subspace_secret(&**self, subspace_secret).await
+    }
+
+    async fn create_namespace(&self) -> Result<NamespaceId> {
+        DynLeafStore::create_namespace(&**self).await
+    }
+
+    async fn list_namespaces(
+        &self,
+    ) -> Result<impl futures::Stream<Item = anyhow::Result<(NamespaceId, NamespaceCapability)>>>
+    {
+        DynLeafStore::list_namespaces(&**self).await
+    }
+
+    async fn get_namespace_secret(
+        &self,
+        namespace: NamespaceId,
+    ) -> Result<Option<NamespaceSecretKey>> {
+        DynLeafStore::get_namespace_secret(&**self, namespace).await
+    }
+
+    async fn import_namespace_secret(&self, secret: [u8; 32]) -> Result<NamespaceId> {
+        DynLeafStore::import_namespace_secret(&**self, secret).await
+    }
+
+    async fn list_namespace_subspaces(
+        &self,
+        namespace: NamespaceId,
+    ) -> Result<impl futures::Stream<Item = anyhow::Result<SubspaceId>>> {
+        DynLeafStore::list_namespace_subspaces(&**self, namespace).await
+    }
+
+    async fn store_blob(
+        &self,
+        data: &[u8],
+        link: &ExactLink,
+        entity_snapshot_id: Digest,
+    ) -> Result<Digest> {
+        DynLeafStore::store_blob(&**self, data, link, entity_snapshot_id).await
+    }
+
+    async fn del_blobs(&self, link: &ExactLink, entity_snapshot_id: Digest) -> Result<usize> {
+        DynLeafStore::del_blobs(&**self, link, entity_snapshot_id).await
+    }
+
+    async fn get_blob(&self, digest: Digest) -> Result<Vec<u8>> {
+        DynLeafStore::get_blob(&**self, digest).await
+    }
+
+    async fn store_entity(&self, link: &ExactLink, data: Vec<u8>) -> Result<Digest> {
+        DynLeafStore::store_entity(&**self, link, data).await
+    }
+
+    async fn del_entity(&self, link: &ExactLink) -> Result<()> {
+        DynLeafStore::del_entity(&**self, link).await
+    }
+
+    async fn get_entity(&self, link: &ExactLink) -> Result<Option<Digest>> {
+        DynLeafStore::get_entity(&**self, link).await
+    }
+
+    async fn store_signed_entity(
+        &self,
+        link: &ExactLink,
+        data: Vec<u8>,
+        authorship: EntityAuthorship,
+    ) -> Result<Digest> {
+        DynLeafStore::store_signed_entity(&**self, link, data, authorship).await
+    }
+
+    async fn get_entity_signature(&self, link: &ExactLink) -> Result<Option<EntityAuthorship>> {
+        DynLeafStore::get_entity_signature(&**self, link).await
+    }
+
+    async fn list(
+        &self,
+        link: ExactLink,
+        depth: Option<u32>,
+        after: Option<EntityPath>,
+        limit: Option<u64>,
+    ) -> Result<impl futures::Stream<Item = anyhow::Result<ExactLink>>> {
+        DynLeafStore::list(&**self, link, depth, after, limit).await
+    }
+
+    async fn share_namespace(&self, namespace: NamespaceId, mode: ShareMode) -> Result<String> {
+        DynLeafStore::share_namespace(&**self, namespace, mode).await
+    }
+
+    async fn join_namespace(&self, ticket: &str) -> Result<NamespaceId> {
+        DynLeafStore::join_namespace(&**self, ticket).await
+    }
+
+    async fn list_sync_peers(&self, namespace: NamespaceId) -> Result<Vec<SyncPeer>> {
+        DynLeafStore::list_sync_peers(&**self, namespace).await
+    }
+}
@@ -0,0 +1,494 @@
+//! A [`LeafStore`] wrapper that caches blobs and entity lookups in memory.
+//!
+//! Blobs, including entity snapshots, are content-addressed and never change, so they can be
+//! cached by their [`Digest`] for as long as we like. The digest of the entity at each path can
+//! change, so those mappings are updated when an entity is written through the cache, and expire
+//! after [`CacheLimits::entity_ttl`] in case the entity was changed some other way.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use quick_cache::{sync::Cache, Weighter};
+
+use crate::{
+    store::{
+        EncryptionAlgorithmImpl, EntityAuthorship, KeyResolverImpl, LeafStore, NamespaceCapability,
+        ShareMode, SyncPeer,
+    },
+    types::{
+        EntityPath, ExactLink, NamespaceId, NamespaceSecretKey, SubspaceId, SubspaceSecretKey,
+    },
+    Digest,
+};
+
+/// How much a [`LeafCacheStore`] may keep in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheLimits {
+    /// The total size of the cached blobs, in bytes. `0` disables the blob cache.
+    pub blob_bytes: u64,
+    /// The number of entity paths to cache the current entity digest for. `0` disables the entity
+    /// cache.
+    pub entities: usize,
+    /// How long the digest of an entity is cached for before it is looked up again.
+    ///
+    /// Entities that are written through the cache are always up to date, but entities that are
+    /// written by a sync, or directly to the inner store, are only seen once their cached digest
+    /// expires.
+    pub entity_ttl: Duration,
+}
+
+impl Default for CacheLimits {
+    /// 64 MiB of blobs and 10,000 entities, which are cached for 5 seconds.
+    fn default() -> Self {
+        Self {
+            blob_bytes: 64 * 1024 * 1024,
+            entities: 10_000,
+            entity_ttl: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Hit and miss counters for a [`LeafCacheStore`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub blob_hits: u64,
+    pub blob_misses: u64,
+    pub entity_hits: u64,
+    pub entity_misses: u64,
+    /// The total size of the blobs currently in the cache, in bytes.
+    pub blob_bytes: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheCounters {
+    blob_hits: AtomicU64,
+    blob_misses: AtomicU64,
+    entity_hits: AtomicU64,
+    entity_misses: AtomicU64,
+}
+
+#[derive(Debug, Clone)]
+struct BlobWeighter;
+impl Weighter<Digest, Arc<[u8]>> for BlobWeighter {
+    fn weight(&self, _key: &Digest, val: &Arc<[u8]>) -> u64 {
+        // Zero weight items are never evicted, so even empty blobs have to weigh something.
+        (val.len() as u64).max(1)
+    }
+}
+
+type BlobCache = Cache<Digest, Arc<[u8]>, BlobWeighter>;
+
+/// The digest of the entity at a path, and when it has to be looked up again.
+#[derive(Debug, Clone, Copy)]
+struct CachedEntity {
+    digest: Option<Digest>,
+    expires: Instant,
+}
+
+/// A [`LeafStore`] that caches the blobs and entity lookups of another store.
+///
+/// Cloning the store is cheap and the clones share the same cache.
+///
+/// Entities that are written directly to the inner store, or by a sync, bypass the cache, so their
+/// paths may resolve to an old snapshot for up to [`CacheLimits::entity_ttl`]. Disable the entity
+/// cache with [`CacheLimits::entities`] if that is a problem.
+#[derive(Clone)]
+pub struct LeafCacheStore<S> {
+    pub store: S,
+    blobs: Option<Arc<BlobCache>>,
+    entities: Option<Arc<Cache<ExactLink, CachedEntity>>>,
+    entity_ttl: Duration,
+    counters: Arc<CacheCounters>,
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for LeafCacheStore<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LeafCacheStore")
+            .field("store", &self.store)
+            .field("stats", &self.stats())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> LeafCacheStore<S> {
+    /// Cache the blobs and entity lookups of `store`, within the given limits.
+    pub fn new(store: S, limits: CacheLimits) -> Self {
+        // Estimate an average blob size of 1 KiB to size the blob cache.
+        let blobs = (limits.blob_bytes > 0).then(|| {
+            Arc::new(Cache::with_weighter(
+                (limits.blob_bytes / 1024).max(1) as usize,
+                limits.blob_bytes,
+                BlobWeighter,
+            ))
+        });
+        let entities = (limits.entities > 0).then(|| Arc::new(Cache::new(limits.entities)));
+        Self {
+            store,
+            blobs,
+            entities,
+            entity_ttl: limits.entity_ttl,
+            counters: Default::default(),
+        }
+    }
+
+    /// Get the hit and miss counts of the cache.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            blob_hits: self.counters.blob_hits.load(Ordering::Relaxed),
+            blob_misses: self.counters.blob_misses.load(Ordering::Relaxed),
+            entity_hits: self.counters.entity_hits.load(Ordering::Relaxed),
+            entity_misses: self.counters.entity_misses.load(Ordering::Relaxed),
+            blob_bytes: self.blobs.as_ref().map(|x| x.weight()).unwrap_or(0),
+        }
+    }
+
+    fn cache_blob(&self, digest: Digest, data: &[u8]) {
+        if let Some(blobs) = &self.blobs {
+            blobs.insert(digest, data.into());
+        }
+    }
+
+    fn cached_entity(&self, digest: Option<Digest>) -> CachedEntity {
+        CachedEntity {
+            digest,
+            expires: Instant::now() + self.entity_ttl,
+        }
+    }
+
+    fn cache_entity(&self, link: &ExactLink, digest: Option<Digest>) {
+        if let Some(entities) = &self.entities {
+            entities.insert(link.clone(), self.cached_entity(digest));
+        }
+    }
+}
+
+impl<S: LeafStore> LeafStore for LeafCacheStore<S> {
+    fn key_resolvers(&self) -> Box<dyn Iterator<Item = &dyn KeyResolverImpl<Digest>> + '_> {
+        self.store.key_resolvers()
+    }
+
+    fn encryption_algorithms(
+        &self,
+    ) -> Box<dyn Iterator<Item = &dyn EncryptionAlgorithmImpl<Digest>> + '_> {
+        self.store.encryption_algorithms()
+    }
+
+    async fn create_subspace(&self) -> anyhow::Result<SubspaceId> {
+        self.store.create_subspace().await
+    }
+
+    async fn get_subspace_secret(
+        &self,
+        subspace: SubspaceId,
+    ) -> anyhow::Result<Option<SubspaceSecretKey>> {
+        self.store.get_subspace_secret(subspace).await
+    }
+
+    async fn list_subspaces(
+        &self,
+    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<SubspaceId>> + Send> {
+        self.store.list_subspaces().await
+    }
+
+    async fn import_subspace_secret(
+        &self,
+        subspace_secret: SubspaceSecretKey,
+    ) -> anyhow::Result<SubspaceId> {
+        self.store.import_subspace_secret(subspace_secret).await
+    }
+
+    async fn create_namespace(&self) -> anyhow::Result<NamespaceId> {
+        self.store.create_namespace().await
+    }
+
+    async fn list_namespaces(
+        &self,
+    ) -> anyhow::Result<
+        impl futures::Stream<Item = anyhow::Result<(NamespaceId, NamespaceCapability)>> + Send,
+    > {
+        self.store.list_namespaces().await
+    }
+
+    async fn get_namespace_secret(
+        &self,
+        namespace: NamespaceId,
+    ) -> anyhow::Result<Option<NamespaceSecretKey>> {
+        self.store.get_namespace_secret(namespace).await
+    }
+
+    async fn import_namespace_secret(&self, secret: [u8; 32]) -> anyhow::Result<NamespaceId> {
+        self.store.import_namespace_secret(secret).await
+    }
+
+    async fn list_namespace_subspaces(
+        &self,
+        namespace: NamespaceId,
+    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<SubspaceId>> + Send> {
+        self.store.list_namespace_subspaces(namespace).await
+    }
+
+    async fn store_blob(
+        &self,
+        data: &[u8],
+        link: &ExactLink,
+        entity_snapshot_id: Digest,
+    ) -> anyhow::Result<Digest> {
+        let digest = self
+            .store
+            .store_blob(data, link, entity_snapshot_id)
+            .await?;
+        self.cache_blob(digest, data);
+        Ok(digest)
+    }
+
+    async fn del_blobs(
+        &self,
+        link: &ExactLink,
+        entity_snapshot_id: Digest,
+    ) -> anyhow::Result<usize> {
+        // Blobs never change, so the cached blobs are still correct even if the inner store
+        // deletes them.
+        self.store.del_blobs(link, entity_snapshot_id).await
+    }
+
+    async fn get_blob(&self, digest: Digest) -> anyhow::Result<Vec<u8>> {
+        if let Some(data) = self.blobs.as_ref().and_then(|x| x.get(&digest)) {
+            self.counters.blob_hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(data.to_vec());
+        }
+        self.counters.blob_misses.fetch_add(1, Ordering::Relaxed);
+        let data = self.store.get_blob(digest).await?;
+        self.cache_blob(digest, &data);
+        Ok(data)
+    }
+
+    async fn store_entity(&self, link: &ExactLink, data: Vec<u8>) -> anyhow::Result<Digest> {
+        let digest = match self.store.store_entity(link, data.clone()).await {
+            Ok(digest) => digest,
+            Err(e) => {
+                // We don't know whether the write happened or not.
+                if let Some(entities) = &self.entities {
+                    entities.remove(link);
+                }
+                return Err(e);
+            }
+        };
+        self.cache_blob(digest, &data);
+        self.cache_entity(link, Some(digest));
+        Ok(digest)
+    }
+
+    async fn del_entity(&self, link: &ExactLink) -> anyhow::Result<()> {
+        let result = self.store.del_entity(link).await;
+        match &result {
+            Ok(()) => self.cache_entity(link, None),
+            Err(_) => {
+                if let Some(entities) = &self.entities {
+                    entities.remove(link);
+                }
+            }
+        }
+        result
+    }
+
+    async fn get_entity(&self, link: &ExactLink) -> anyhow::Result<Option<Digest>> {
+        let Some(entities) = &self.entities else {
+            self.counters.entity_misses.fetch_add(1, Ordering::Relaxed);
+            return self.store.get_entity(link).await;
+        };
+        let guard = loop {
+            match entities.get_value_or_guard_async(link).await {
+                Ok(cached) if cached.expires > Instant::now() => {
+                    self.counters.entity_hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(cached.digest);
+                }
+                // Removing the entity at worst removes a digest that was just written, which
+                // only means that we look it up again.
+                Ok(_) => {
+                    entities.remove(link);
+                }
+                Err(guard) => break guard,
+            }
+        };
+        self.counters.entity_misses.fetch_add(1, Ordering::Relaxed);
+        let digest = self.store.get_entity(link).await?;
+        // Writing the entity while we were looking it up replaces the placeholder of the guard,
+        // so the digest that we looked up is only cached if it can't be older than the one that
+        // was written.
+        let _ = guard.insert(self.cached_entity(digest));
+        Ok(digest)
+    }
+
+    async fn store_signed_entity(
+        &self,
+        link: &ExactLink,
+        data: Vec<u8>,
+        authorship: EntityAuthorship,
+    ) -> anyhow::Result<Digest> {
+        let digest = match self
+            .store
+            .store_signed_entity(link, data.clone(), authorship)
+            .await
+        {
+            Ok(digest) => digest,
+            Err(e) => {
+                // We don't know whether the write happened or not.
+                if let Some(entities) = &self.entities {
+                    entities.remove(link);
+                }
+                return Err(e);
+            }
+        };
+        self.cache_blob(digest, &data);
+        self.cache_entity(link, Some(digest));
+        Ok(digest)
+    }
+
+    async fn get_entity_signature(
+        &self,
+        link: &ExactLink,
+    ) -> anyhow::Result<Option<EntityAuthorship>> {
+        self.store.get_entity_signature(link).await
+    }
+
+    async fn list(
+        &self,
+        link: ExactLink,
+        depth: Option<u32>,
+        after: Option<EntityPath>,
+        limit: Option<u64>,
+    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<ExactLink>> + Send> {
+        self.store.list(link, depth, after, limit).await
+    }
+
+    async fn share_namespace(
+        &self,
+        namespace: NamespaceId,
+        mode: ShareMode,
+    ) -> anyhow::Result<String> {
+        self.store.share_namespace(namespace, mode).await
+    }
+
+    async fn join_namespace(&self, ticket: &str) -> anyhow::Result<NamespaceId> {
+        self.store.join_namespace(ticket).await
+    }
+
+    async fn list_sync_peers(&self, namespace: NamespaceId) -> anyhow::Result<Vec<SyncPeer>> {
+        self.store.list_sync_peers(namespace).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::LeafMemoryStore;
+
+    async fn store(
+        limits: CacheLimits,
+    ) -> anyhow::Result<(LeafCacheStore<LeafMemoryStore>, ExactLink)> {
+        let store = LeafCacheStore::new(LeafMemoryStore::new(), limits);
+        let namespace = store.create_namespace().await?;
+        let subspace = store.create_subspace().await?;
+        Ok((store, (namespace, subspace, ["entity"]).into()))
+    }
+
+    async fn store_without_cache() -> anyhow::Result<(LeafCacheStore<LeafMemoryStore>, ExactLink)> {
+        store(CacheLimits {
+            blob_bytes: 0,
+            entities: 0,
+            ..Default::default()
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn counts_hits_and_misses() -> anyhow::Result<()> {
+        let (store, link) = store(CacheLimits::default()).await?;
+        let digest = store
+            .store
+            .store_blob(b"blob", &link, Digest::new(b"snapshot"))
+            .await?;
+
+        assert_eq!(store.get_blob(digest).await?, b"blob");
+        assert_eq!(store.get_blob(digest).await?, b"blob");
+        assert_eq!(store.get_entity(&link).await?, None);
+        assert_eq!(store.get_entity(&link).await?, None);
+        assert_eq!(
+            store.stats(),
+            CacheStats {
+                blob_hits: 1,
+                blob_misses: 1,
+                entity_hits: 1,
+                entity_misses: 1,
+                blob_bytes: 4,
+            }
+        );
+
+        // Disabled caches count every lookup as a miss.
+        let (store, link) = store_without_cache().await?;
+        store.get_entity(&link).await?;
+        store.get_entity(&link).await?;
+        assert_eq!(store.stats().entity_misses, 2);
+        assert_eq!(store.stats().entity_hits, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn writes_update_the_cache() -> anyhow::Result<()> {
+        let (store, link) = store(CacheLimits::default()).await?;
+        assert_eq!(store.get_entity(&link).await?, None);
+
+        let digest = store.store_entity(&link, b"entity".to_vec()).await?;
+        assert_eq!(store.get_entity(&link).await?, Some(digest));
+        assert_eq!(store.get_blob(digest).await?, b"entity");
+        store.del_entity(&link).await?;
+        assert_eq!(store.get_entity(&link).await?, None);
+
+        // Only the first lookup missed, and the blob was cached when the entity was stored.
+        let stats = store.stats();
+        assert_eq!((stats.entity_hits, stats.entity_misses), (2, 1));
+        assert_eq!((stats.blob_hits, stats.blob_misses), (1, 0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_writes_invalidate_the_cache() -> anyhow::Result<()> {
+        let (store, link) = store(CacheLimits::default()).await?;
+        let digest = store.store_entity(&link, b"entity".to_vec()).await?;
+
+        // Writing to a subspace that we don't have the secret of fails.
+        let mut other = link.clone();
+        other.subspace = [0; 32];
+        assert!(store.store_entity(&other, b"other".to_vec()).await.is_err());
+        assert_eq!(store.get_entity(&other).await?, None);
+        assert_eq!(store.stats().entity_misses, 1);
+        assert_eq!(store.get_entity(&link).await?, Some(digest));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn entities_written_elsewhere_expire() -> anyhow::Result<()> {
+        let (store, link) = store(CacheLimits {
+            entity_ttl: Duration::from_millis(100),
+            ..Default::default()
+        })
+        .await?;
+        assert_eq!(store.get_entity(&link).await?, None);
+
+        // Writing to the inner store bypasses the cache until the cached lookup expires.
+        let digest = store.store.store_entity(&link, b"entity".to_vec()).await?;
+        assert_eq!(store.get_entity(&link).await?, None);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(store.get_entity(&link).await?, Some(digest));
+        assert_eq!(store.get_entity(&link).await?, Some(digest));
+
+        let stats = store.stats();
+        assert_eq!((stats.entity_hits, stats.entity_misses), (2, 2));
+        Ok(())
+    }
+}
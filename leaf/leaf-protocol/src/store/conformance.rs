@@ -0,0 +1,567 @@
+//! A test suite for [`LeafStore`] implementations.
+//!
+//! The [`LeafStore`] trait leaves a lot of behavior up to the backend, but [`Leaf`] relies on it
+//! anyway. [`check_store()`] checks that a store behaves the same way as the built-in backends, so
+//! a new backend can call it from its tests to prove that it is compatible:
+//!
+//! ```ignore
+//! #[tokio::test]
+//! async fn conformance() -> anyhow::Result<()> {
+//!     leaf_protocol::store::conformance::check_store(MyStore::new()).await
+//! }
+//! ```
+
+use anyhow::{ensure, Result};
+use futures::{pin_mut, StreamExt, TryStreamExt};
+
+use crate::{
+    components::{Description, Name},
+    store::{
+        EntityAuthorship, EntitySignature, InvalidEntitySignature, LeafStore, NamespaceCapability,
+        ReadOnlyNamespace,
+    },
+    types::{EntityPath, ExactLink, NamespaceId, PathSegment, SubspaceId},
+    Digest, Leaf,
+};
+
+/// Check that a store implements all of the behavior that [`Leaf`] expects from a [`LeafStore`].
+///
+/// The checks create their own namespace and subspace, so the store doesn't need to be empty, but
+/// it must allow creating namespaces and subspaces. Returns an error describing the first check
+/// that failed.
+///
+/// The store is checked for:
+/// - Creating, importing, and exporting namespace and subspace secrets, with IDs derived from the
+///   secrets the same way as Iroh derives them, and listing namespaces as writable once we have
+///   their secret.
+/// - Content-addressed blobs, with garbage collector pins that are tracked per entity snapshot,
+///   and [`LeafStore::del_blobs()`] returning the number of pins that it removed.
+/// - Storing, replacing, and deleting entities, where deleting an entity doesn't delete the
+///   entities below it, and writing or deleting requires the namespace and subspace secrets,
+///   failing with [`ReadOnlyNamespace`] without the namespace secret.
+/// - Proving the authorship of entities written with the subspace secret, rejecting entities with
+///   forged signatures, and keeping the authorship of entities stored with
+///   [`LeafStore::store_signed_entity()`]. With the `backend_iroh` feature, stores must accept
+///   signed Iroh entries for subspaces that they don't have the secret of.
+/// - Listing entities by path prefix and depth, in a stable order that can be resumed with
+///   `after`, where the entities that are too deep don't count against the limit.
+/// - Saving, loading, and deleting entities and their components through [`Leaf`].
+pub async fn check_store<S: LeafStore + Clone>(store: S) -> Result<()> {
+    let (namespace, subspace) = check_secrets(&store).await?;
+    let link = |path: &[&str]| ExactLink {
+        namespace,
+        subspace,
+        path: EntityPath(path.iter().map(|&x| x.into()).collect()),
+    };
+    check_blobs(&store, &link(&["conformance", "blobs"])).await?;
+    check_entities(&store, &link).await?;
+    check_signatures(&store, &link(&["conformance", "signatures"])).await?;
+    check_list(&store, &link).await?;
+    check_leaf(store, link(&["conformance", "leaf"])).await?;
+    Ok(())
+}
+
+async fn check_secrets<S: LeafStore>(store: &S) -> Result<(NamespaceId, SubspaceId)> {
+    // These are the IDs that Iroh derives from the secret, so that secrets can be moved between
+    // stores.
+    let secret = [7; 32];
+    let expected_id = [
+        234, 74, 108, 99, 226, 156, 82, 10, 190, 245, 80, 123, 19, 46, 197, 249, 149, 71, 118, 174,
+        190, 190, 123, 146, 66, 30, 234, 105, 20, 70, 210, 44,
+    ];
+
+    let namespace = store.create_namespace().await?;
+    ensure!(
+        store.get_namespace_secret(namespace).await?.is_some(),
+        "Created namespace has no secret"
+    );
+    let imported = store.import_namespace_secret(secret).await?;
+    ensure!(
+        imported == expected_id,
+        "Namespace ID is not derived from its secret"
+    );
+    ensure!(
+        store.get_namespace_secret(imported).await? == Some(secret),
+        "Imported namespace secret doesn't round-trip"
+    );
+    let namespaces: Vec<_> = store.list_namespaces().await?.try_collect().await?;
+    ensure!(
+        namespaces.contains(&(namespace, NamespaceCapability::Write))
+            && namespaces.contains(&(imported, NamespaceCapability::Write)),
+        "Created and imported namespaces are not listed as writable"
+    );
+
+    let subspace = store.create_subspace().await?;
+    ensure!(
+        store.get_subspace_secret(subspace).await?.is_some(),
+        "Created subspace has no secret"
+    );
+    let imported = store.import_subspace_secret(secret).await?;
+    ensure!(
+        imported == expected_id,
+        "Subspace ID is not derived from its secret"
+    );
+    ensure!(
+        store.get_subspace_secret(imported).await? == Some(secret),
+        "Imported subspace secret doesn't round-trip"
+    );
+    let subspaces: Vec<_> = store.list_subspaces().await?.try_collect().await?;
+    ensure!(
+        subspaces.contains(&subspace) && subspaces.contains(&imported),
+        "Created and imported subspaces are not listed"
+    );
+
+    Ok((namespace, subspace))
+}
+
+async fn check_blobs<S: LeafStore>(store: &S, link: &ExactLink) -> Result<()> {
+    let snapshot1 = Digest::new(b"conformance snapshot 1");
+    let snapshot2 = Digest::new(b"conformance snapshot 2");
+
+    let digest = store.store_blob(b"shared", link, snapshot1).await?;
+    ensure!(
+        digest == Digest::new(b"shared"),
+        "Blobs are not addressed by their digest"
+    );
+    ensure!(
+        store.get_blob(digest).await? == b"shared",
+        "Blob doesn't round-trip"
+    );
+    // Pinning the same blob to the same snapshot again only counts once.
+    store.store_blob(b"shared", link, snapshot1).await?;
+    store.store_blob(b"only in 1", link, snapshot1).await?;
+    store.store_blob(b"shared", link, snapshot2).await?;
+
+    let deleted = store.del_blobs(link, snapshot1).await?;
+    ensure!(
+        deleted == 2,
+        "Deleting the pins of a snapshot with 2 blobs deleted {deleted} pins"
+    );
+    ensure!(
+        store.get_blob(digest).await? == b"shared",
+        "Blob was deleted while it was still pinned by another snapshot"
+    );
+    let deleted = store.del_blobs(link, snapshot2).await?;
+    ensure!(
+        deleted == 1,
+        "Deleting the pins of a snapshot with 1 blob deleted {deleted} pins"
+    );
+
+    Ok(())
+}
+
+async fn check_entities<S: LeafStore>(
+    store: &S,
+    link: &impl Fn(&[&str]) -> ExactLink,
+) -> Result<()> {
+    let parent = link(&["conformance", "entities"]);
+    let child = link(&["conformance", "entities", "child"]);
+
+    ensure!(
+        store.get_entity(&parent).await?.is_none(),
+        "Missing entity was found"
+    );
+    let digest = store.store_entity(&parent, b"first".to_vec()).await?;
+    ensure!(
+        digest == Digest::new(b"first"),
+        "Entities are not addressed by their digest"
+    );
+    ensure!(
+        store.get_entity(&parent).await? == Some(digest),
+        "Stored entity was not found"
+    );
+    ensure!(
+        store.get_blob(digest).await? == b"first",
+        "Entity data can't be read as a blob"
+    );
+    let digest = store.store_entity(&parent, b"second".to_vec()).await?;
+    ensure!(
+        store.get_entity(&parent).await? == Some(digest),
+        "Entity was not replaced"
+    );
+
+    // Deleting an entity must not delete the entities below it, even though their keys may start
+    // with the parent's key.
+    store.store_entity(&child, b"child".to_vec()).await?;
+    store.del_entity(&parent).await?;
+    ensure!(
+        store.get_entity(&parent).await?.is_none(),
+        "Entity was not deleted"
+    );
+    ensure!(
+        store.get_entity(&child).await?.is_some(),
+        "Deleting an entity deleted the entity below it"
+    );
+
+    let subspaces: Vec<_> = store
+        .list_namespace_subspaces(parent.namespace)
+        .await?
+        .try_collect()
+        .await?;
+    ensure!(
+        subspaces.contains(&parent.subspace),
+        "Subspace with entities is not listed in its namespace"
+    );
+    store.del_entity(&child).await?;
+
+    let mut unknown_namespace = parent.clone();
+    unknown_namespace.namespace = [0; 32];
+    ensure!(
+        matches!(
+            store.store_entity(&unknown_namespace, b"x".to_vec()).await,
+            Err(e) if e.is::<ReadOnlyNamespace>()
+        ),
+        "Writing to a namespace without its secret didn't fail with `ReadOnlyNamespace`"
+    );
+    let mut unknown_subspace = parent.clone();
+    unknown_subspace.subspace = [0; 32];
+    ensure!(
+        store
+            .store_entity(&unknown_subspace, b"x".to_vec())
+            .await
+            .is_err(),
+        "Wrote to a subspace without its secret"
+    );
+    ensure!(
+        store.del_entity(&unknown_subspace).await.is_err(),
+        "Deleted from a subspace without its secret"
+    );
+
+    Ok(())
+}
+
+async fn check_signatures<S: LeafStore>(store: &S, link: &ExactLink) -> Result<()> {
+    let Some(secret) = store.get_subspace_secret(link.subspace).await? else {
+        anyhow::bail!("Created subspace has no secret");
+    };
+    ensure!(
+        store.get_entity_signature(link).await?.is_none(),
+        "Missing entity has a signature"
+    );
+    let digest = store.store_entity(link, b"signed".to_vec()).await?;
+    let Some(authorship) = store.get_entity_signature(link).await? else {
+        anyhow::bail!("Entity in a subspace that we have the secret of has no authorship");
+    };
+    ensure!(
+        authorship.verify(link, digest).is_ok(),
+        "Authorship of an entity in a subspace that we have the secret of doesn't verify"
+    );
+
+    let forged = EntitySignature::sign(&secret, link, Digest::new(b"something else"));
+    ensure!(
+        matches!(
+            store.store_signed_entity(link, b"forged".to_vec(), EntityAuthorship::Signature(forged)).await,
+            Err(e) if e.is::<InvalidEntitySignature>()
+        ),
+        "Storing an entity with a forged signature didn't fail with `InvalidEntitySignature`"
+    );
+    ensure!(
+        store.get_entity(link).await? == Some(digest),
+        "Entity with a forged signature replaced the existing entity"
+    );
+
+    // Stores may refuse to write to a subspace that we don't have the secret of even with a valid
+    // signature, but if they do write the entity, they must keep its signature.
+    let author_secret = [9; 32];
+    let foreign = foreign_link(link, author_secret);
+    let signature = EntityAuthorship::Signature(EntitySignature::sign(
+        &author_secret,
+        &foreign,
+        Digest::new(b"foreign"),
+    ));
+    if let Ok(digest) = store
+        .store_signed_entity(&foreign, b"foreign".to_vec(), signature.clone())
+        .await
+    {
+        ensure!(
+            digest == Digest::new(b"foreign"),
+            "Signed entities are not addressed by their digest"
+        );
+        ensure!(
+            store.get_entity_signature(&foreign).await? == Some(signature),
+            "Signature of an entity in a subspace that we don't have the secret of was not kept"
+        );
+        ensure!(
+            store.del_entity(&foreign).await.is_err()
+                && store.get_entity(&foreign).await? == Some(digest),
+            "Deleted an entity in a subspace that we don't have the secret of"
+        );
+    }
+
+    #[cfg(feature = "backend_iroh")]
+    check_iroh_entries(store, link).await?;
+
+    store.del_entity(link).await?;
+    ensure!(
+        store.get_entity_signature(link).await?.is_none(),
+        "Deleted entity has a signature"
+    );
+
+    Ok(())
+}
+
+/// Get the link of the entity at the same path as `link` in the subspace with the given secret.
+fn foreign_link(link: &ExactLink, author_secret: [u8; 32]) -> ExactLink {
+    let mut foreign = link.clone();
+    foreign.subspace = *iroh_base::key::SecretKey::from_bytes(&author_secret)
+        .public()
+        .as_bytes();
+    foreign
+}
+
+#[cfg(feature = "backend_iroh")]
+async fn check_iroh_entries<S: LeafStore>(store: &S, link: &ExactLink) -> Result<()> {
+    use iroh::docs::{Author, NamespaceSecret, Record, SignedEntry};
+
+    let Some(namespace_secret) = store.get_namespace_secret(link.namespace).await? else {
+        anyhow::bail!("Created namespace has no secret");
+    };
+    let author_secret = [10; 32];
+    let foreign = foreign_link(link, author_secret);
+    let data = b"foreign iroh entry";
+    let entry = SignedEntry::from_parts(
+        &NamespaceSecret::from_bytes(&namespace_secret),
+        &Author::from_bytes(&author_secret),
+        crate::store::iroh::LeafIrohStore::get_entity_key(foreign.subspace, &foreign.path.0),
+        Record::new_current(Digest::new(data).0, data.len() as u64),
+    );
+    let authorship = EntityAuthorship::IrohEntry(postcard::to_allocvec(&entry)?);
+
+    ensure!(
+        matches!(
+            store.store_signed_entity(&foreign, b"forged".to_vec(), authorship.clone()).await,
+            Err(e) if e.is::<InvalidEntitySignature>()
+        ),
+        "Storing an entity with a signed Iroh entry for other data didn't fail with \
+        `InvalidEntitySignature`"
+    );
+    let digest = store
+        .store_signed_entity(&foreign, data.to_vec(), authorship.clone())
+        .await?;
+    ensure!(
+        digest == Digest::new(data),
+        "Signed entities are not addressed by their digest"
+    );
+    ensure!(
+        store.get_entity(&foreign).await? == Some(digest),
+        "Entity stored with a signed Iroh entry can't be read"
+    );
+    ensure!(
+        store.get_entity_signature(&foreign).await? == Some(authorship),
+        "Signed Iroh entry of an entity in a subspace that we don't have the secret of was not \
+        kept"
+    );
+    Ok(())
+}
+
+async fn check_list<S: LeafStore>(store: &S, link: &impl Fn(&[&str]) -> ExactLink) -> Result<()> {
+    let paths: &[&[&str]] = &[
+        &["conformance", "list"],
+        &["conformance", "list", "a"],
+        &["conformance", "list", "a", "b"],
+        &["conformance", "list", "a", "b", "c"],
+        &["conformance", "list", "ab"],
+        &["conformance", "list", "b"],
+        &["conformance", "listing"],
+    ];
+    for path in paths {
+        store
+            .store_entity(&link(path), path.join("/").into())
+            .await?;
+    }
+
+    async fn list<S: LeafStore>(
+        store: &S,
+        link: ExactLink,
+        depth: Option<u32>,
+        after: Option<EntityPath>,
+        limit: Option<u64>,
+    ) -> Result<Vec<EntityPath>> {
+        let stream = store.list(link, depth, after, limit).await?;
+        pin_mut!(stream);
+        let mut paths = Vec::new();
+        while let Some(link) = stream.next().await {
+            paths.push(link?.path);
+        }
+        Ok(paths)
+    }
+    let path = |path: &[&str]| link(path).path;
+    let sorted = |mut paths: Vec<EntityPath>| {
+        paths.sort();
+        paths
+    };
+
+    let root = link(&["conformance", "list"]);
+    let all = list(store, root.clone(), None, None, None).await?;
+    ensure!(
+        sorted(all.clone()) == paths[..6].iter().map(|x| path(x)).collect::<Vec<_>>(),
+        "Listing by prefix returned {all:?}"
+    );
+    ensure!(
+        list(store, root.clone(), None, None, None).await? == all,
+        "Listing order is not stable"
+    );
+
+    let depth0 = list(store, root.clone(), Some(0), None, None).await?;
+    ensure!(
+        depth0 == [path(paths[0])],
+        "Listing with depth 0 returned {depth0:?}"
+    );
+    let depth1 = sorted(list(store, root.clone(), Some(1), None, None).await?);
+    ensure!(
+        depth1 == [paths[0], paths[1], paths[4], paths[5]].map(path),
+        "Listing with depth 1 returned {depth1:?}"
+    );
+    let nested = sorted(list(store, link(paths[1]), Some(1), None, None).await?);
+    ensure!(
+        nested == [paths[1], paths[2]].map(path),
+        "Listing a nested path with depth 1 returned {nested:?}"
+    );
+
+    // Page through the entities two at a time.
+    let mut paged = Vec::new();
+    let mut after = None;
+    loop {
+        let page = list(store, root.clone(), None, after, Some(2)).await?;
+        ensure!(page.len() <= 2, "Listing returned more than the limit");
+        let Some(last) = page.last().cloned() else {
+            break;
+        };
+        paged.extend(page);
+        after = Some(last);
+    }
+    ensure!(
+        paged == all,
+        "Paginated listing returned {paged:?} instead of {all:?}"
+    );
+
+    // Listing resumes after a deleted entity, in the same order.
+    store.del_entity(&link(paths[2])).await?;
+    let after_deleted = list(store, root.clone(), None, Some(all[2].clone()), None).await?;
+    let expected = all[3..]
+        .iter()
+        .filter(|x| **x != path(paths[2]))
+        .cloned()
+        .collect::<Vec<_>>();
+    ensure!(
+        after_deleted == expected,
+        "Listing after a deleted entity returned {after_deleted:?} instead of {expected:?}"
+    );
+
+    for path in paths {
+        store.del_entity(&link(path)).await?;
+    }
+    ensure!(
+        list(store, root, None, None, None).await?.is_empty(),
+        "Deleted entities are still listed"
+    );
+
+    // The entities below a path that is deeper than a listing's depth don't count against its
+    // limit, even if there are a lot of them.
+    let deep = (0..40)
+        .flat_map(|i| {
+            let i = format!("{i:02}");
+            [
+                vec!["a".to_string(), i.clone()],
+                vec!["a".into(), i, "x".into()],
+            ]
+        })
+        .chain([vec![], vec!["a".into()], vec!["b".into()], vec!["c".into()]])
+        .map(|path| {
+            let mut link = link(&["conformance", "deep"]);
+            link.path.0.extend(path.into_iter().map(PathSegment::from));
+            link
+        })
+        .collect::<Vec<_>>();
+    for link in &deep {
+        store.store_entity(link, b"deep".to_vec()).await?;
+    }
+    let root = link(&["conformance", "deep"]);
+    let page = list(store, root.clone(), Some(1), None, Some(3)).await?;
+    ensure!(
+        page.len() == 3,
+        "Listing a page with depth 1 returned {page:?}"
+    );
+    let mut paged = Vec::new();
+    let mut after = None;
+    loop {
+        let page = list(store, root.clone(), Some(1), after, Some(2)).await?;
+        let Some(last) = page.last().cloned() else {
+            break;
+        };
+        paged.extend(page);
+        after = Some(last);
+    }
+    let expected = [&[][..], &["a"], &["b"], &["c"]].map(|x| {
+        let mut path = root.path.clone();
+        path.0.extend(x.iter().map(|&x| PathSegment::from(x)));
+        path
+    });
+    ensure!(
+        sorted(paged.clone()) == sorted(expected.to_vec()),
+        "Paginated listing with depth 1 returned {paged:?}"
+    );
+    for link in &deep {
+        store.del_entity(link).await?;
+    }
+
+    Ok(())
+}
+
+async fn check_leaf<S: LeafStore + Clone>(store: S, link: ExactLink) -> Result<()> {
+    let leaf = Leaf::new(store);
+
+    let mut entity = leaf.entity(link.clone()).await?.get_or_init();
+    entity.set_component(Name("first".into()))?;
+    entity.set_component(Description("unchanged".into()))?;
+    entity.save().await?;
+
+    // Only change one component, so that the other one has to stay pinned to the new snapshot.
+    entity.set_component(Name("second".into()))?;
+    entity.save().await?;
+
+    let entity = leaf.entity(link.clone()).await?.entity()?;
+    ensure!(
+        entity
+            .get_component::<Name>()
+            .await?
+            .map(|x| x.0)
+            .as_deref()
+            == Some("second"),
+        "Changed component doesn't round-trip through Leaf"
+    );
+    ensure!(
+        entity
+            .get_component::<Description>()
+            .await?
+            .map(|x| x.0)
+            .as_deref()
+            == Some("unchanged"),
+        "Unchanged component was lost when the entity was saved"
+    );
+
+    let mut child = link.clone();
+    child.path.0.push(PathSegment::from("child"));
+    let mut child_entity = leaf.entity(child.clone()).await?.get_or_init();
+    child_entity.add_component(Name("child".into()))?;
+    child_entity.save().await?;
+    let listed: Vec<_> = leaf
+        .list(link.clone(), None, None)
+        .await?
+        .try_collect()
+        .await?;
+    ensure!(
+        listed.len() == 2 && listed.contains(&link) && listed.contains(&child),
+        "Entities saved through Leaf are not listed"
+    );
+
+    leaf.del_entity(child).await?;
+    let mut entity = leaf.entity(link.clone()).await?.entity()?;
+    entity.delete().await?;
+    ensure!(
+        leaf.entity(link).await?.entity().is_err(),
+        "Entity deleted through Leaf can still be loaded"
+    );
+
+    Ok(())
+}
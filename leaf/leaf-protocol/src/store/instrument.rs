@@ -0,0 +1,424 @@
+//! A [`LeafStore`] wrapper that records [`tracing`] spans and [`StoreMetrics`] for every call.
+//!
+//! Each call runs in a `DEBUG` level span named after the method, with the namespace, subspace,
+//! path and digest of the call as fields where it has them. Failed calls also log their error at
+//! `DEBUG` level inside of the span.
+
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use tracing::{Instrument, Span};
+
+use crate::{
+    store::{
+        EncryptionAlgorithmImpl, EntityAuthorship, KeyResolverImpl, LeafStore, NamespaceCapability,
+        OperationMetrics, ShareMode, StoreMetrics, StoreOperation, SyncPeer, LATENCY_BUCKETS_US,
+    },
+    types::{
+        EntityPath, ExactLink, NamespaceId, NamespaceSecretKey, SubspaceId, SubspaceSecretKey,
+    },
+    Digest,
+};
+
+#[derive(Debug, Default)]
+struct OperationCounters {
+    calls: AtomicU64,
+    errors: AtomicU64,
+    total_us: AtomicU64,
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS_US.len() + 1],
+}
+
+#[derive(Debug, Default)]
+struct MetricsCounters {
+    operations: [OperationCounters; StoreOperation::ALL.len()],
+    blob_bytes_read: AtomicU64,
+    blob_bytes_written: AtomicU64,
+    entity_bytes_written: AtomicU64,
+}
+
+/// A [`LeafStore`] that traces the calls to another store and records how long they take.
+///
+/// Cloning the store is cheap and the clones share the same metrics.
+#[derive(Debug, Clone)]
+pub struct LeafInstrumentedStore<S> {
+    pub store: S,
+    counters: Arc<MetricsCounters>,
+}
+
+impl<S> LeafInstrumentedStore<S> {
+    /// Instrument `store`.
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            counters: Default::default(),
+        }
+    }
+
+    /// Get a snapshot of the metrics recorded so far.
+    pub fn metrics(&self) -> StoreMetrics {
+        let operations = StoreOperation::ALL
+            .iter()
+            .zip(&self.counters.operations)
+            .filter(|(_, counters)| counters.calls.load(Ordering::Relaxed) > 0)
+            .map(|(&operation, counters)| OperationMetrics {
+                operation,
+                calls: counters.calls.load(Ordering::Relaxed),
+                errors: counters.errors.load(Ordering::Relaxed),
+                total_us: counters.total_us.load(Ordering::Relaxed),
+                latency_buckets: counters
+                    .latency_buckets
+                    .iter()
+                    .map(|x| x.load(Ordering::Relaxed))
+                    .collect(),
+            })
+            .collect();
+        StoreMetrics {
+            operations,
+            blob_bytes_read: self.counters.blob_bytes_read.load(Ordering::Relaxed),
+            blob_bytes_written: self.counters.blob_bytes_written.load(Ordering::Relaxed),
+            entity_bytes_written: self.counters.entity_bytes_written.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record(&self, operation: StoreOperation, elapsed: Duration, ok: bool) {
+        let counters = &self.counters.operations[operation as usize];
+        let elapsed_us = elapsed.as_micros().try_into().unwrap_or(u64::MAX);
+        let bucket = LATENCY_BUCKETS_US
+            .iter()
+            .position(|&bound| elapsed_us <= bound)
+            .unwrap_or(LATENCY_BUCKETS_US.len());
+        counters.calls.fetch_add(1, Ordering::Relaxed);
+        if !ok {
+            counters.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        counters.total_us.fetch_add(elapsed_us, Ordering::Relaxed);
+        counters.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Run `fut` in `span` and record its metrics as a call to `operation`.
+    async fn instrument<T>(
+        &self,
+        operation: StoreOperation,
+        span: Span,
+        fut: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        let start = Instant::now();
+        let result = fut.instrument(span.clone()).await;
+        self.record(operation, start.elapsed(), result.is_ok());
+        if let Err(e) = &result {
+            tracing::debug!(parent: &span, "Error: {e:#}");
+        }
+        result
+    }
+}
+
+/// Create a span for a call that accesses an entity link.
+macro_rules! link_span {
+    ($name:literal, $link:expr $(, $($field:tt)*)?) => {
+        tracing::debug_span!(
+            $name,
+            namespace = %Digest::from_bytes($link.namespace),
+            subspace = %Digest::from_bytes($link.subspace),
+            path = ?$link.path,
+            $($($field)*)?
+        )
+    };
+}
+
+impl<S: LeafStore> LeafStore for LeafInstrumentedStore<S> {
+    fn key_resolvers(&self) -> Box<dyn Iterator<Item = &dyn KeyResolverImpl<Digest>> + '_> {
+        self.store.key_resolvers()
+    }
+
+    fn encryption_algorithms(
+        &self,
+    ) -> Box<dyn Iterator<Item = &dyn EncryptionAlgorithmImpl<Digest>> + '_> {
+        self.store.encryption_algorithms()
+    }
+
+    async fn create_subspace(&self) -> anyhow::Result<SubspaceId> {
+        self.instrument(
+            StoreOperation::CreateSubspace,
+            tracing::debug_span!("create_subspace"),
+            self.store.create_subspace(),
+        )
+        .await
+    }
+
+    async fn get_subspace_secret(
+        &self,
+        subspace: SubspaceId,
+    ) -> anyhow::Result<Option<SubspaceSecretKey>> {
+        self.instrument(
+            StoreOperation::GetSubspaceSecret,
+            tracing::debug_span!("get_subspace_secret", subspace = %Digest::from_bytes(subspace)),
+            self.store.get_subspace_secret(subspace),
+        )
+        .await
+    }
+
+    async fn list_subspaces(
+        &self,
+    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<SubspaceId>> + Send> {
+        self.instrument(
+            StoreOperation::ListSubspaces,
+            tracing::debug_span!("list_subspaces"),
+            self.store.list_subspaces(),
+        )
+        .await
+    }
+
+    async fn import_subspace_secret(
+        &self,
+        subspace_secret: SubspaceSecretKey,
+    ) -> anyhow::Result<SubspaceId> {
+        self.instrument(
+            StoreOperation::ImportSubspaceSecret,
+            tracing::debug_span!("import_subspace_secret"),
+            self.store.import_subspace_secret(subspace_secret),
+        )
+        .await
+    }
+
+    async fn create_namespace(&self) -> anyhow::Result<NamespaceId> {
+        self.instrument(
+            StoreOperation::CreateNamespace,
+            tracing::debug_span!("create_namespace"),
+            self.store.create_namespace(),
+        )
+        .await
+    }
+
+    async fn list_namespaces(
+        &self,
+    ) -> anyhow::Result<
+        impl futures::Stream<Item = anyhow::Result<(NamespaceId, NamespaceCapability)>> + Send,
+    > {
+        self.instrument(
+            StoreOperation::ListNamespaces,
+            tracing::debug_span!("list_namespaces"),
+            self.store.list_namespaces(),
+        )
+        .await
+    }
+
+    async fn get_namespace_secret(
+        &self,
+        namespace: NamespaceId,
+    ) -> anyhow::Result<Option<NamespaceSecretKey>> {
+        self.instrument(
+            StoreOperation::GetNamespaceSecret,
+            tracing::debug_span!(
+                "get_namespace_secret",
+                namespace = %Digest::from_bytes(namespace)
+            ),
+            self.store.get_namespace_secret(namespace),
+        )
+        .await
+    }
+
+    async fn import_namespace_secret(&self, secret: [u8; 32]) -> anyhow::Result<NamespaceId> {
+        self.instrument(
+            StoreOperation::ImportNamespaceSecret,
+            tracing::debug_span!("import_namespace_secret"),
+            self.store.import_namespace_secret(secret),
+        )
+        .await
+    }
+
+    async fn list_namespace_subspaces(
+        &self,
+        namespace: NamespaceId,
+    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<SubspaceId>> + Send> {
+        self.instrument(
+            StoreOperation::ListNamespaceSubspaces,
+            tracing::debug_span!(
+                "list_namespace_subspaces",
+                namespace = %Digest::from_bytes(namespace)
+            ),
+            self.store.list_namespace_subspaces(namespace),
+        )
+        .await
+    }
+
+    async fn store_blob(
+        &self,
+        data: &[u8],
+        link: &ExactLink,
+        entity_snapshot_id: Digest,
+    ) -> anyhow::Result<Digest> {
+        let result = self
+            .instrument(
+                StoreOperation::StoreBlob,
+                link_span!(
+                    "store_blob",
+                    link,
+                    entity_snapshot_id = %entity_snapshot_id,
+                    bytes = data.len()
+                ),
+                self.store.store_blob(data, link, entity_snapshot_id),
+            )
+            .await;
+        if result.is_ok() {
+            self.counters
+                .blob_bytes_written
+                .fetch_add(data.len() as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    async fn del_blobs(
+        &self,
+        link: &ExactLink,
+        entity_snapshot_id: Digest,
+    ) -> anyhow::Result<usize> {
+        self.instrument(
+            StoreOperation::DelBlobs,
+            link_span!("del_blobs", link, entity_snapshot_id = %entity_snapshot_id),
+            self.store.del_blobs(link, entity_snapshot_id),
+        )
+        .await
+    }
+
+    async fn get_blob(&self, digest: Digest) -> anyhow::Result<Vec<u8>> {
+        let result = self
+            .instrument(
+                StoreOperation::GetBlob,
+                tracing::debug_span!("get_blob", digest = %digest),
+                self.store.get_blob(digest),
+            )
+            .await;
+        if let Ok(data) = &result {
+            self.counters
+                .blob_bytes_read
+                .fetch_add(data.len() as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    async fn store_entity(&self, link: &ExactLink, data: Vec<u8>) -> anyhow::Result<Digest> {
+        let len = data.len() as u64;
+        let result = self
+            .instrument(
+                StoreOperation::StoreEntity,
+                link_span!("store_entity", link, bytes = len),
+                self.store.store_entity(link, data),
+            )
+            .await;
+        if result.is_ok() {
+            self.counters
+                .entity_bytes_written
+                .fetch_add(len, Ordering::Relaxed);
+        }
+        result
+    }
+
+    async fn del_entity(&self, link: &ExactLink) -> anyhow::Result<()> {
+        self.instrument(
+            StoreOperation::DelEntity,
+            link_span!("del_entity", link),
+            self.store.del_entity(link),
+        )
+        .await
+    }
+
+    async fn get_entity(&self, link: &ExactLink) -> anyhow::Result<Option<Digest>> {
+        self.instrument(
+            StoreOperation::GetEntity,
+            link_span!("get_entity", link),
+            self.store.get_entity(link),
+        )
+        .await
+    }
+
+    async fn store_signed_entity(
+        &self,
+        link: &ExactLink,
+        data: Vec<u8>,
+        authorship: EntityAuthorship,
+    ) -> anyhow::Result<Digest> {
+        let len = data.len() as u64;
+        let result = self
+            .instrument(
+                StoreOperation::StoreSignedEntity,
+                link_span!("store_signed_entity", link, bytes = len),
+                self.store.store_signed_entity(link, data, authorship),
+            )
+            .await;
+        if result.is_ok() {
+            self.counters
+                .entity_bytes_written
+                .fetch_add(len, Ordering::Relaxed);
+        }
+        result
+    }
+
+    async fn get_entity_signature(
+        &self,
+        link: &ExactLink,
+    ) -> anyhow::Result<Option<EntityAuthorship>> {
+        self.instrument(
+            StoreOperation::GetEntitySignature,
+            link_span!("get_entity_signature", link),
+            self.store.get_entity_signature(link),
+        )
+        .await
+    }
+
+    async fn list(
+        &self,
+        link: ExactLink,
+        depth: Option<u32>,
+        after: Option<EntityPath>,
+        limit: Option<u64>,
+    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<ExactLink>> + Send> {
+        let span = link_span!("list", link, depth, limit);
+        self.instrument(
+            StoreOperation::List,
+            span,
+            self.store.list(link, depth, after, limit),
+        )
+        .await
+    }
+
+    async fn share_namespace(
+        &self,
+        namespace: NamespaceId,
+        mode: ShareMode,
+    ) -> anyhow::Result<String> {
+        self.instrument(
+            StoreOperation::ShareNamespace,
+            tracing::debug_span!(
+                "share_namespace",
+                namespace = %Digest::from_bytes(namespace),
+                mode = ?mode
+            ),
+            self.store.share_namespace(namespace, mode),
+        )
+        .await
+    }
+
+    async fn join_namespace(&self, ticket: &str) -> anyhow::Result<NamespaceId> {
+        self.instrument(
+            StoreOperation::JoinNamespace,
+            tracing::debug_span!("join_namespace"),
+            self.store.join_namespace(ticket),
+        )
+        .await
+    }
+
+    async fn list_sync_peers(&self, namespace: NamespaceId) -> anyhow::Result<Vec<SyncPeer>> {
+        self.instrument(
+            StoreOperation::ListSyncPeers,
+            tracing::debug_span!("list_sync_peers", namespace = %Digest::from_bytes(namespace)),
+            self.store.list_sync_peers(namespace),
+        )
+        .await
+    }
+}
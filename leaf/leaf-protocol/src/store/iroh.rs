@@ -1,18 +1,29 @@
 use std::{
-    io::{Cursor, Read, Write},
+    collections::{BTreeSet, HashMap, HashSet},
+    io::Write,
     sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use borsh::{BorshDeserialize, BorshSerialize};
-use futures::TryStreamExt;
+use futures::{StreamExt, TryStreamExt};
 use iroh::{
     base::node_addr::AddrInfoOptions,
-    docs::{store::Query, Author, AuthorId, Capability, NamespaceSecret},
+    docs::{
+        actor::{OpenOpts, SyncHandle},
+        store::Query,
+        Author, AuthorId, Capability, CapabilityKind, ContentStatus, DocTicket, NamespaceSecret,
+        SignedEntry,
+    },
+    net::{endpoint::ConnectionType, relay::RelayMode},
 };
 use once_cell::sync::Lazy;
 
 use crate::{
-    store::LeafStore,
+    store::{
+        EntityAuthorship, GcReport, InvalidEntitySignature, LeafStore, NamespaceCapability,
+        PeerConnection, ReadOnlyNamespace, ShareMode, SyncPeer,
+    },
     types::{EntityPath, NamespaceSecretKey, PathSegment, SubspaceId},
     Digest, ExactLink,
 };
@@ -23,6 +34,63 @@ pub const LEAF_GC_PREFIX_STR: &str = "_leaf_gc_";
 pub static LEAF_GC_PREFIX: Lazy<PathSegment> =
     Lazy::new(|| PathSegment::String(LEAF_GC_PREFIX_STR.into()));
 
+/// The version of the document key format that is written by the store.
+///
+/// Keys that were written before the format was versioned start with the little-endian length of
+/// their first field instead, which is never `1`, so the two can be told apart by their first
+/// byte. See [`KeyFormat`].
+pub const KEY_FORMAT_VERSION: u8 = 1;
+/// Comes before each path segment in a version 1 entity key.
+const KEY_SEGMENT: u8 = 1;
+/// Ends a version 1 entity key. It is different from [`KEY_SEGMENT`], so the key of an entity is
+/// never a prefix of the keys of the entities below it, which would make deleting the entity
+/// delete them too.
+const KEY_END: u8 = 0;
+/// Comes after the version in a version 1 garbage collector pin key.
+const KEY_GC_PIN: u8 = 2;
+
+/// The number of entities below an entity that is too deep for a listing that are stepped over
+/// one by one, before searching for the end of them instead. See
+/// [`LeafIrohStore::query_within_depth()`].
+const LIST_SKIP_SCAN: usize = 16;
+
+/// The format of a document key.
+///
+/// The store writes [`KeyFormat::V1`] keys, but still reads [`KeyFormat::Legacy`] keys, so that
+/// namespaces written by older versions of Leaf, or synced from nodes running them, can still be
+/// used. [`LeafIrohStore::migrate_keys()`] rewrites the legacy keys of a namespace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFormat {
+    /// The unversioned format, where an entity key is its length-prefixed path segments followed
+    /// by a null byte, and a garbage collector pin key is the borsh encoded [`LeafGcPath`].
+    ///
+    /// The key of an entity may be a prefix of the key of an entity below it, if the next
+    /// segment of the child's path is a multiple of 256 bytes long.
+    Legacy,
+    /// The [`KEY_FORMAT_VERSION`] byte, followed by a marker before each path segment and a null
+    /// byte for entity keys, or by a marker and the borsh encoded [`LeafGcPath`] for garbage
+    /// collector pin keys.
+    V1,
+}
+
+impl KeyFormat {
+    /// Get the format of an encoded key.
+    pub fn detect(key: &[u8]) -> Self {
+        match key.first() {
+            Some(&KEY_FORMAT_VERSION) => KeyFormat::V1,
+            _ => KeyFormat::Legacy,
+        }
+    }
+
+    /// The bytes that every garbage collector pin key in this format starts with.
+    fn gc_prefix(self) -> Vec<u8> {
+        match self {
+            KeyFormat::Legacy => borsh::to_vec(LEAF_GC_PREFIX_STR).unwrap(),
+            KeyFormat::V1 => vec![KEY_FORMAT_VERSION, KEY_GC_PIN],
+        }
+    }
+}
+
 #[derive(borsh::BorshSerialize, borsh::BorshDeserialize)]
 pub struct LeafGcPathPrefix {
     pub leaf_gc_prefix_str: String,
@@ -41,7 +109,13 @@ impl LeafGcPathPrefix {
         }
     }
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
+        self.to_bytes_with_format(KeyFormat::V1)
+    }
+    pub fn to_bytes_with_format(&self, format: KeyFormat) -> Vec<u8> {
+        let mut buf = match format {
+            KeyFormat::Legacy => Vec::new(),
+            KeyFormat::V1 => vec![KEY_FORMAT_VERSION, KEY_GC_PIN],
+        };
         self.serialize(&mut buf).unwrap();
         buf
     }
@@ -77,12 +151,38 @@ impl LeafGcPath {
         }
     }
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
+        self.to_bytes_with_format(KeyFormat::V1)
+    }
+    pub fn to_bytes_with_format(&self, format: KeyFormat) -> Vec<u8> {
+        let mut buf = match format {
+            KeyFormat::Legacy => Vec::new(),
+            KeyFormat::V1 => vec![KEY_FORMAT_VERSION, KEY_GC_PIN],
+        };
         self.serialize(&mut buf).unwrap();
         buf
     }
-    pub fn from_bytes(mut bytes: &[u8]) -> std::io::Result<Self> {
-        Self::deserialize(&mut bytes)
+    /// Decode a garbage collector pin key in either [`KeyFormat`].
+    pub fn from_bytes(bytes: &[u8]) -> std::io::Result<Self> {
+        let mut bytes = match KeyFormat::detect(bytes) {
+            KeyFormat::Legacy => bytes,
+            KeyFormat::V1 => match bytes {
+                [_, KEY_GC_PIN, rest @ ..] => rest,
+                _ => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "Not a garbage collector pin key.",
+                    ))
+                }
+            },
+        };
+        let path = Self::deserialize(&mut bytes)?;
+        if path.prefix.leaf_gc_prefix_str != LEAF_GC_PREFIX_STR {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Not a garbage collector pin key.",
+            ));
+        }
+        Ok(path)
     }
 }
 
@@ -90,7 +190,19 @@ impl LeafGcPath {
 pub struct LeafIrohStore {
     pub client: iroh::client::Iroh,
     pub docs: Arc<quick_cache::sync::Cache<iroh::docs::NamespaceId, iroh::client::Doc>>,
+    /// Namespaces that we know we can write to. A namespace can't lose its write capability, so we
+    /// only need to look up the capability of namespaces that aren't in here.
+    writable: Arc<quick_cache::sync::Cache<iroh::docs::NamespaceId, ()>>,
+    /// Started the first time that a signed entry is read or written.
+    entry_sync: Arc<tokio::sync::OnceCell<EntrySync>>,
+    /// The query positions of the last entries of full pages returned by
+    /// [`LeafStore::list()`], by namespace, key prefix and key, so that the next page can start
+    /// from there. See [`LeafIrohStore::query_after()`].
+    cursors: Arc<quick_cache::sync::Cache<ListCursor, u64>>,
 }
+/// A namespace, the key prefix of a listing and the key of an entry in it.
+type ListCursor = (iroh::docs::NamespaceId, Vec<u8>, Vec<u8>);
+
 pub struct IrohDocumentKeyFormat {
     pub path: Vec<PathSegment>,
 }
@@ -100,47 +212,259 @@ impl IrohDocumentKeyFormat {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
+        self.to_bytes_with_format(KeyFormat::V1)
+    }
+
+    pub fn to_bytes_with_format(&self, format: KeyFormat) -> Vec<u8> {
+        let mut buf = self.to_prefix_bytes(format);
+        // Add the null trailing byte to make sure it doesn't trigger a prefix deletion.
+        buf.push(KEY_END);
+        buf
+    }
+
+    /// Encode the path without the trailing null byte, which gives a prefix of the keys of the
+    /// path and all of the paths below it.
+    pub fn to_prefix_bytes(&self, format: KeyFormat) -> Vec<u8> {
+        let mut buf = match format {
+            KeyFormat::Legacy => Vec::new(),
+            KeyFormat::V1 => vec![KEY_FORMAT_VERSION],
+        };
         let mut segment_bytes = Vec::new();
         for segment in &self.path {
             segment.serialize(&mut segment_bytes).unwrap();
             let len: u32 = segment_bytes.len().try_into().unwrap();
+            if format == KeyFormat::V1 {
+                buf.push(KEY_SEGMENT);
+            }
             buf.write_all(&len.to_le_bytes()[..]).unwrap();
             buf.write_all(&segment_bytes).unwrap();
             segment_bytes.clear();
         }
-        buf.push(0); // Add the null trailing byte to make sure it doesn't trigger a prefix deletion.
         buf
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
-        let last_byte_idx = bytes.len() - 1;
-        if bytes[last_byte_idx] != 0 {
+    /// Split an encoded key into the encoded segments, without decoding them.
+    fn split_segments(bytes: &[u8]) -> anyhow::Result<Vec<&[u8]>> {
+        let format = KeyFormat::detect(bytes);
+        let Some((&KEY_END, bytes)) = bytes.split_last() else {
             anyhow::bail!("Expected null terminating byte.")
+        };
+        let mut bytes = match format {
+            KeyFormat::Legacy => bytes,
+            KeyFormat::V1 => &bytes[1..],
+        };
+        let mut segments = Vec::new();
+        while !bytes.is_empty() {
+            if format == KeyFormat::V1 {
+                let Some((&KEY_SEGMENT, rest)) = bytes.split_first() else {
+                    anyhow::bail!("Expected path segment marker.")
+                };
+                bytes = rest;
+            }
+            let Some((len, rest)) = bytes.split_first_chunk::<4>() else {
+                anyhow::bail!("Unexpected end of key.")
+            };
+            let len = u32::from_le_bytes(*len) as usize;
+            if rest.len() < len {
+                anyhow::bail!("Unexpected end of key.")
+            }
+            segments.push(&rest[..len]);
+            bytes = &rest[len..];
         }
-        let bytes = &bytes[0..last_byte_idx];
-        let len = bytes.len();
-        if len == 0 {
-            return Ok(Self { path: Vec::new() });
+        Ok(segments)
+    }
+
+    /// Count the number of path segments in an encoded key without decoding the segments.
+    pub fn segment_count(bytes: &[u8]) -> anyhow::Result<usize> {
+        Ok(Self::split_segments(bytes)?.len())
+    }
+
+    /// Decode an entity key in either [`KeyFormat`].
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let path = Self::split_segments(bytes)?
+            .into_iter()
+            .map(PathSegment::try_from_slice)
+            .collect::<std::io::Result<_>>()?;
+        Ok(Self { path })
+    }
+}
+
+/// The result of [`LeafIrohStore::migrate_keys()`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyMigrationReport {
+    /// The number of entities that were moved to [`KeyFormat::V1`] keys.
+    pub entities: u64,
+    /// The number of garbage collector pins that were moved to [`KeyFormat::V1`] keys.
+    pub pins: u64,
+    /// The number of legacy entries that were left alone, because they were written by an author
+    /// that we don't have the secret of, or because their key couldn't be decoded.
+    pub skipped: u64,
+}
+
+/// An Iroh document store that syncs with the node of a [`LeafIrohStore`], so that we can get at
+/// the signed entries of the node's documents.
+///
+/// The Iroh client only gives us entries without their signatures, and only inserts entries that
+/// the node signs itself. Syncing a document into this store gives us the entries exactly as
+/// their authors signed them, and syncing entries that were inserted here back into the node
+/// hands it entries signed by other authors, which it checks and inserts like entries from any
+/// other peer.
+///
+/// The documents that have been synced are kept until the store is dropped, so that they don't
+/// have to be synced again for every entry that is read from them. They are kept in a temporary
+/// file rather than in memory, since they hold every entry of the documents.
+#[derive(Debug, Clone)]
+struct EntrySync {
+    endpoint: iroh::net::Endpoint,
+    sync: SyncHandle,
+    /// The documents that have been opened in the sync store. Syncs are run one at a time while
+    /// this is locked, because each one may start and stop syncing the document on the node.
+    opened: Arc<tokio::sync::Mutex<HashSet<iroh::docs::NamespaceId>>>,
+    /// The file of the sync store. It comes after the sync handle, so that the store is closed
+    /// before the file is deleted.
+    _file: Arc<TempFile>,
+}
+
+/// A file that is deleted when it is dropped.
+#[derive(Debug)]
+struct TempFile(std::path::PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+impl EntrySync {
+    async fn spawn() -> anyhow::Result<Self> {
+        let endpoint = iroh::net::Endpoint::builder()
+            .relay_mode(RelayMode::Disabled)
+            .bind(0)
+            .await?;
+        // The endpoint has a new random node ID, so the file name doesn't clash with the sync
+        // stores of other Leaf stores.
+        let file = TempFile(
+            std::env::temp_dir().join(format!("leaf-entry-sync-{}.redb", endpoint.node_id())),
+        );
+        let store = iroh::docs::store::Store::persistent(&file.0)?;
+        let sync = SyncHandle::spawn(store, None, "leaf".into());
+        Ok(Self {
+            endpoint,
+            sync,
+            opened: Default::default(),
+            _file: Arc::new(file),
+        })
+    }
+
+    /// Get the node ID that the node sees us syncing from.
+    fn node_id(&self) -> [u8; 32] {
+        *self.endpoint.node_id().as_bytes()
+    }
+
+    /// Open a document in the sync store if it isn't open yet.
+    async fn open(
+        &self,
+        opened: &mut HashSet<iroh::docs::NamespaceId>,
+        namespace: iroh::docs::NamespaceId,
+    ) -> anyhow::Result<()> {
+        if !opened.contains(&namespace) {
+            self.sync
+                .import_namespace(Capability::Read(namespace))
+                .await?;
+            self.sync
+                .open(namespace, OpenOpts::default().sync())
+                .await?;
+            opened.insert(namespace);
         }
-        let mut reader = Cursor::new(bytes);
-        let mut path = Vec::new();
-        let mut segment_bytes = Vec::new();
-        loop {
-            if reader.position() as usize == len {
-                break;
-            }
-            let mut segment_len_bytes = [0u8; 4];
-            reader.read_exact(&mut segment_len_bytes)?;
-            let segment_len = u32::from_le_bytes(segment_len_bytes);
+        Ok(())
+    }
 
-            segment_bytes.extend(std::iter::repeat(0u8).take(segment_len as _));
-            reader.read_exact(&mut segment_bytes)?;
+    /// Get an entry from the sync store, as of the last sync.
+    async fn get_exact(
+        &self,
+        namespace: iroh::docs::NamespaceId,
+        author: AuthorId,
+        key: &[u8],
+    ) -> anyhow::Result<Option<SignedEntry>> {
+        if !self.opened.lock().await.contains(&namespace) {
+            return Ok(None);
+        }
+        self.sync
+            .get_exact(namespace, author, key.to_vec().into(), false)
+            .await
+    }
 
-            path.push(PathSegment::deserialize(&mut &segment_bytes[..])?);
-            segment_bytes.clear();
+    /// Sync a document of the node with the sync store, in both directions, after inserting
+    /// `entry` into the sync store if there is one.
+    async fn sync_with(
+        &self,
+        client: &iroh::client::Iroh,
+        doc: &iroh::client::Doc,
+        entry: Option<SignedEntry>,
+    ) -> anyhow::Result<()> {
+        let namespace = doc.id();
+        let mut opened = self.opened.lock().await;
+        self.open(&mut opened, namespace).await?;
+        if let Some(entry) = entry {
+            self.sync
+                .insert_remote(namespace, entry, self.node_id(), ContentStatus::Complete)
+                .await?;
         }
-        Ok(Self { path })
+
+        // The node only accepts syncs for documents that it is syncing, so sync the document
+        // for as long as it takes if the node isn't already.
+        let syncing = doc.status().await?.sync;
+        if !syncing {
+            doc.start_sync(Vec::new()).await?;
+        }
+        let addr = client.node().node_addr().await?;
+        let result = iroh::docs::net::connect_and_sync(&self.endpoint, &self.sync, namespace, addr)
+            .await
+            .map_err(|e| anyhow::format_err!("Could not sync signed entries with the node: {e}"));
+        if !syncing {
+            doc.leave().await?;
+        }
+        result?;
+        Ok(())
+    }
+}
+
+/// Decode a signed Iroh entry and check that it was written by the author of the subspace of
+/// `link` for the entity snapshot with `digest`.
+pub(crate) fn verify_signed_entry(
+    entry: &[u8],
+    link: &ExactLink,
+    digest: Digest,
+) -> anyhow::Result<SignedEntry> {
+    let error = || InvalidEntitySignature {
+        link: link.clone(),
+        digest,
+    };
+    let entry: SignedEntry = postcard::from_bytes(entry).map_err(|_| error())?;
+    entry.verify(&()).map_err(|_| error())?;
+    if link.path.0.first() == Some(&*LEAF_GC_PREFIX)
+        || entry.entry().namespace() != link.namespace.into()
+        || entry.author_bytes() != link.subspace.into()
+        || entry.content_hash() != digest.0
+    {
+        return Err(error().into());
+    }
+    let key = entry.key();
+    if [KeyFormat::V1, KeyFormat::Legacy]
+        .into_iter()
+        .all(|format| {
+            key != LeafIrohStore::get_entity_key_with_format(link.subspace, &link.path.0, format)
+        })
+    {
+        return Err(error().into());
+    }
+    Ok(entry)
+}
+
+fn capability_from_kind(kind: CapabilityKind) -> NamespaceCapability {
+    match kind {
+        CapabilityKind::Read => NamespaceCapability::Read,
+        CapabilityKind::Write => NamespaceCapability::Write,
     }
 }
 
@@ -149,7 +473,227 @@ impl LeafIrohStore {
         Self {
             client,
             docs: Arc::new(quick_cache::sync::Cache::new(10)),
+            writable: Arc::new(quick_cache::sync::Cache::new(1000)),
+            entry_sync: Default::default(),
+            cursors: Arc::new(quick_cache::sync::Cache::new(1000)),
+        }
+    }
+
+    /// Query the entries of `author` below `prefix` in key order, starting after the entry with
+    /// the key `after`, along with their positions in the query.
+    ///
+    /// Iroh can't start a query at a key, only at an offset, so if we remember where a previous
+    /// page ended we start there, after checking that the entry is still at that position. Iroh
+    /// still has to step over the entries before the offset, but it does so without sending
+    /// them to us. If the position is unknown or stale, we fall back to skipping entries until we
+    /// pass `after`.
+    async fn query_after(
+        &self,
+        doc: &iroh::client::Doc,
+        author: AuthorId,
+        prefix: Vec<u8>,
+        after: Option<Vec<u8>>,
+    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<(u64, iroh::client::docs::Entry)>>>
+    {
+        let mut offset = 0;
+        if let Some(after) = &after {
+            let cursor = (doc.id(), prefix.clone(), after.clone());
+            if let Some(position) = self.cursors.get(&cursor) {
+                let entry = doc
+                    .get_one(
+                        Query::key_prefix(&prefix)
+                            .author(author)
+                            .offset(position)
+                            .limit(1),
+                    )
+                    .await?;
+                if entry.is_some_and(|x| x.key() == &after[..]) {
+                    offset = position + 1;
+                } else {
+                    self.cursors.remove(&cursor);
+                }
+            }
+        }
+
+        let entries = Self::query_from(doc, author, &prefix, offset).await?;
+        let after = (offset == 0).then_some(after).flatten();
+        Ok(entries.try_filter(move |(_, x)| {
+            std::future::ready(
+                after
+                    .as_ref()
+                    .map(|after| x.key() > &after[..])
+                    .unwrap_or(true),
+            )
+        }))
+    }
+
+    /// Query the entries of `author` below `prefix` in key order, starting at the position
+    /// `offset`, along with their positions in the query.
+    async fn query_from(
+        doc: &iroh::client::Doc,
+        author: AuthorId,
+        prefix: &[u8],
+        offset: u64,
+    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<(u64, iroh::client::docs::Entry)>>>
+    {
+        let entries = doc
+            .get_many(Query::key_prefix(prefix).author(author).offset(offset))
+            .await?;
+        Ok(entries
+            .enumerate()
+            .map(move |(i, x)| x.map(|x| (offset + i as u64, x))))
+    }
+
+    /// Query the entries of `author` like [`LeafIrohStore::query_after()`], leaving out the
+    /// entities with keys that have more than `max_segments` path segments.
+    ///
+    /// The entities below an entity all come right after it, so when we find one that is too deep
+    /// we skip past the rest of the entities below its parent at the maximum depth. We step over
+    /// the first few of them, and if there are more we search for the position of the first entry
+    /// after them and restart the query there. The key of a [`KeyFormat::Legacy`] entity may come
+    /// between the keys of the entities below it, so legacy keys are only filtered one by one.
+    async fn query_within_depth(
+        &self,
+        doc: &iroh::client::Doc,
+        author: AuthorId,
+        prefix: Vec<u8>,
+        after: Option<Vec<u8>>,
+        max_segments: Option<usize>,
+    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<(u64, iroh::client::docs::Entry)>>>
+    {
+        let entries = self
+            .query_after(doc, author, prefix.clone(), after)
+            .await?
+            .fuse()
+            .boxed();
+        let Some(max_segments) = max_segments else {
+            return Ok(entries);
+        };
+        let format = KeyFormat::detect(&prefix);
+        let doc = doc.clone();
+
+        // The state is the query, and an entry that we read while skipping entities, but that
+        // hasn't been returned yet.
+        let stream = futures::stream::try_unfold((entries, None), move |(mut entries, pending)| {
+            let doc = doc.clone();
+            let prefix = prefix.clone();
+            async move {
+                let mut pending = pending;
+                loop {
+                    let next = match pending.take() {
+                        Some(entry) => Some(entry),
+                        None => entries.try_next().await?,
+                    };
+                    let Some((position, entry)) = next else {
+                        return Ok(None);
+                    };
+                    let Ok(mut key) = IrohDocumentKeyFormat::from_bytes(entry.key()) else {
+                        continue;
+                    };
+                    if key.path.len() <= max_segments {
+                        return Ok(Some(((position, entry), (entries, None))));
+                    }
+                    if format == KeyFormat::Legacy {
+                        continue;
+                    }
+
+                    // The prefix of the keys of the entities below the parent of this entity at
+                    // the maximum depth.
+                    key.path.truncate(max_segments);
+                    let mut subtree = key.to_prefix_bytes(format);
+                    subtree.push(KEY_SEGMENT);
+
+                    let mut last = position;
+                    let mut skipped = 0;
+                    while pending.is_none() && skipped < LIST_SKIP_SCAN {
+                        match entries.try_next().await? {
+                            Some((position, entry)) if entry.key().starts_with(&subtree) => {
+                                last = position;
+                                skipped += 1;
+                            }
+                            Some(entry) => pending = Some(entry),
+                            None => return Ok(None),
+                        }
+                    }
+                    if pending.is_none() {
+                        let end = Self::subtree_end(&doc, author, &prefix, &subtree, last).await?;
+                        entries = Self::query_from(&doc, author, &prefix, end)
+                            .await?
+                            .fuse()
+                            .boxed();
+                    }
+                }
+            }
+        });
+        Ok(stream.boxed())
+    }
+
+    /// Find the position of the first entry of `author` below `prefix` that comes after the
+    /// entries with keys starting with `subtree`, given that the entry at `position` is one of
+    /// them.
+    ///
+    /// The positions are searched for with queries that each get a single entry, first doubling
+    /// the distance from `position` until we pass the end and then halving it, so skipping `n`
+    /// entries takes about `2 * log2(n)` queries.
+    async fn subtree_end(
+        doc: &iroh::client::Doc,
+        author: AuthorId,
+        prefix: &[u8],
+        subtree: &[u8],
+        position: u64,
+    ) -> anyhow::Result<u64> {
+        let in_subtree = |position: u64| async move {
+            let entry = doc
+                .get_one(
+                    Query::key_prefix(prefix)
+                        .author(author)
+                        .offset(position)
+                        .limit(1),
+                )
+                .await?;
+            anyhow::Ok(entry.is_some_and(|x| x.key().starts_with(subtree)))
+        };
+
+        // The entry at `low` is in the subtree and the one at `high` isn't.
+        let mut low = position;
+        let mut step = 1;
+        let mut high = loop {
+            if in_subtree(low + step).await? {
+                low += step;
+                step *= 2;
+            } else {
+                break low + step;
+            }
+        };
+        while high - low > 1 {
+            let middle = low + (high - low) / 2;
+            if in_subtree(middle).await? {
+                low = middle;
+            } else {
+                high = middle;
+            }
         }
+        Ok(high)
+    }
+
+    async fn entry_sync(&self) -> anyhow::Result<&EntrySync> {
+        self.entry_sync.get_or_try_init(EntrySync::spawn).await
+    }
+
+    /// Get the entry of the entity at `link`, falling back to the legacy key for entities that
+    /// haven't been migrated yet.
+    async fn get_entry(
+        &self,
+        doc: &iroh::client::Doc,
+        link: &ExactLink,
+    ) -> anyhow::Result<Option<iroh::client::docs::Entry>> {
+        for format in [KeyFormat::V1, KeyFormat::Legacy] {
+            let key = Self::get_entity_key_with_format(link.subspace, &link.path.0, format);
+            if let Some(entry) = doc.get_exact(link.subspace.into(), key, false).await? {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
     }
 
     /// Open a document using the local document cache.
@@ -164,7 +708,47 @@ impl LeafIrohStore {
             .await
     }
 
+    /// Get our capability for a namespace, or [`None`] if the namespace isn't in the store.
+    pub async fn namespace_capability(
+        &self,
+        namespace: iroh::docs::NamespaceId,
+    ) -> anyhow::Result<Option<NamespaceCapability>> {
+        if self.writable.get(&namespace).is_some() {
+            return Ok(Some(NamespaceCapability::Write));
+        }
+        let mut namespaces = self.client.docs().list().await?;
+        while let Some((id, kind)) = namespaces.try_next().await? {
+            if id == namespace {
+                let capability = capability_from_kind(kind);
+                if capability == NamespaceCapability::Write {
+                    self.writable.insert(namespace, ());
+                }
+                return Ok(Some(capability));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Return a [`ReadOnlyNamespace`] error if we can't write to the namespace.
+    async fn check_writable(
+        &self,
+        namespace: leaf_protocol_types::NamespaceId,
+    ) -> anyhow::Result<()> {
+        if self.namespace_capability(namespace.into()).await? != Some(NamespaceCapability::Write) {
+            return Err(ReadOnlyNamespace { namespace }.into());
+        }
+        Ok(())
+    }
+
     pub fn get_entity_key(subspace: SubspaceId, path: &[PathSegment]) -> Vec<u8> {
+        Self::get_entity_key_with_format(subspace, path, KeyFormat::V1)
+    }
+
+    pub fn get_entity_key_with_format(
+        subspace: SubspaceId,
+        path: &[PathSegment],
+        format: KeyFormat,
+    ) -> Vec<u8> {
         assert_ne!(
             path.first(),
             Some(&*LEAF_GC_PREFIX),
@@ -173,7 +757,237 @@ impl LeafIrohStore {
         );
         let mut path = path.to_vec();
         path.insert(0, PathSegment::Bytes(subspace.to_vec()));
-        IrohDocumentKeyFormat::new(path).to_bytes()
+        IrohDocumentKeyFormat::new(path).to_bytes_with_format(format)
+    }
+
+    /// Copy a legacy entry to `key`, unless `key` already has an entry that is at least as new.
+    async fn copy_legacy_entry(
+        doc: &iroh::client::Doc,
+        entry: &iroh::client::docs::Entry,
+        key: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        let existing = doc.get_exact(entry.author(), key.clone(), true).await?;
+        if existing.is_some_and(|x| x.timestamp() >= entry.timestamp()) {
+            return Ok(());
+        }
+        doc.set_hash(
+            entry.author(),
+            key,
+            entry.content_hash(),
+            entry.content_len(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Delete the legacy entry of an entity, if there is one.
+    ///
+    /// Deleting a legacy key also deletes the entries with keys that it is a prefix of, which may
+    /// include the legacy entries of entities below it, so those are copied to their new keys
+    /// first.
+    async fn del_legacy_entity(
+        &self,
+        doc: &iroh::client::Doc,
+        link: &ExactLink,
+    ) -> anyhow::Result<()> {
+        let key = Self::get_entity_key_with_format(link.subspace, &link.path.0, KeyFormat::Legacy);
+        let entries = doc
+            .get_many(Query::key_prefix(&key).author(link.subspace.into()))
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        if entries.is_empty() {
+            return Ok(());
+        }
+        for entry in &entries {
+            if entry.key() == &key[..] {
+                continue;
+            }
+            if let Ok(child) = IrohDocumentKeyFormat::from_bytes(entry.key()) {
+                Self::copy_legacy_entry(doc, entry, child.to_bytes()).await?;
+            }
+        }
+        doc.del(link.subspace.into(), key).await?;
+        Ok(())
+    }
+
+    /// Get the [`KeyFormat::V1`] key of a legacy key, and whether it is a garbage collector pin.
+    fn migrated_key(key: &[u8]) -> Option<(Vec<u8>, bool)> {
+        if let Ok(pin) = LeafGcPath::from_bytes(key) {
+            Some((pin.to_bytes(), true))
+        } else if let Ok(key) = IrohDocumentKeyFormat::from_bytes(key) {
+            Some((key.to_bytes(), false))
+        } else {
+            None
+        }
+    }
+
+    /// Delete a migrated legacy key.
+    ///
+    /// Deleting a legacy key also deletes the entries of the same author with keys that it is a
+    /// prefix of, so those are copied to their new keys first. If any of them can't be copied,
+    /// because its key couldn't be decoded, the key is left alone.
+    async fn del_migrated_key(
+        doc: &iroh::client::Doc,
+        author: AuthorId,
+        key: &[u8],
+    ) -> anyhow::Result<()> {
+        let mut entries = doc.get_many(Query::key_prefix(key).author(author)).await?;
+        let mut found = false;
+        while let Some(entry) = entries.try_next().await? {
+            if entry.key() == key {
+                found = true;
+                continue;
+            }
+            let Some((child, _)) = Self::migrated_key(entry.key()) else {
+                return Ok(());
+            };
+            Self::copy_legacy_entry(doc, &entry, child).await?;
+        }
+        // The key was already deleted along with a key that is a prefix of it.
+        if found {
+            doc.del(author, key.to_vec()).await?;
+        }
+        Ok(())
+    }
+
+    /// Rewrite the legacy keys in a namespace to the current [`KeyFormat`].
+    ///
+    /// Only the entries written by authors that we have the secret of can be rewritten, so the
+    /// legacy entries of other authors are left alone, and are still read as before. Each entry is
+    /// copied to its new key before its legacy key is deleted, and a legacy key is only deleted
+    /// once every entry that deleting it would delete too has been copied. An entry isn't copied
+    /// if its new key already has an entry that is at least as new, so an interrupted migration
+    /// can be resumed by running it again. Running it on a namespace that has already been
+    /// migrated does nothing.
+    pub async fn migrate_keys(
+        &self,
+        namespace: leaf_protocol_types::NamespaceId,
+    ) -> anyhow::Result<KeyMigrationReport> {
+        self.check_writable(namespace).await?;
+        let authors = self
+            .client
+            .authors()
+            .list()
+            .await?
+            .try_collect::<HashSet<_>>()
+            .await?;
+        let doc = self.open(namespace.into()).await?;
+
+        let mut report = KeyMigrationReport::default();
+        // The entries are read from a snapshot of the document, so they may include legacy
+        // entries that have already been deleted along with a key that is a prefix of theirs.
+        // Those have already been copied, so copying and deleting them again does nothing.
+        let mut entries = doc.get_many(Query::all()).await?;
+        while let Some(entry) = entries.try_next().await? {
+            if KeyFormat::detect(entry.key()) != KeyFormat::Legacy || entry.content_len() == 0 {
+                continue;
+            }
+            if !authors.contains(&entry.author()) {
+                report.skipped += 1;
+                continue;
+            }
+            let Some((key, pin)) = Self::migrated_key(entry.key()) else {
+                report.skipped += 1;
+                continue;
+            };
+            if pin {
+                report.pins += 1;
+            } else {
+                report.entities += 1;
+            }
+            Self::copy_legacy_entry(&doc, &entry, key).await?;
+            Self::del_migrated_key(&doc, entry.author(), entry.key()).await?;
+        }
+        Ok(report)
+    }
+
+    /// Remove the blob pins of entity snapshots that are no longer current, which may be left
+    /// behind when an entity is changed by a sync while it is being saved.
+    ///
+    /// Pins are only removed if they are older than `min_age`, so that we don't remove the pins
+    /// for an entity that is in the middle of being saved, and if they were written by one of our
+    /// own authors in a namespace that we can write to, since we can't delete any other entries.
+    /// Blobs that aren't pinned anymore are deleted by the Iroh node's blob garbage collector, if
+    /// it is enabled.
+    pub async fn collect_garbage(&self, min_age: Duration) -> anyhow::Result<GcReport> {
+        let authors = self
+            .client
+            .authors()
+            .list()
+            .await?
+            .try_collect::<HashSet<_>>()
+            .await?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let cutoff = now.saturating_sub(min_age).as_micros() as u64;
+
+        let mut report = GcReport::default();
+        // The size of each pinned blob, and whether it is still pinned after the sweep.
+        let mut blobs = HashMap::<Digest, (u64, bool)>::new();
+        let mut namespaces = self.client.docs().list().await?;
+        while let Some((namespace, kind)) = namespaces.try_next().await? {
+            // We can only delete entries in namespaces that we can write to.
+            let writable = capability_from_kind(kind) == NamespaceCapability::Write;
+            let doc = self.open(namespace).await?;
+            let mut pins = Vec::new();
+            for format in [KeyFormat::V1, KeyFormat::Legacy] {
+                let mut stream = doc
+                    .get_many(Query::all().key_prefix(format.gc_prefix()))
+                    .await?;
+                while let Some(pin) = stream.try_next().await? {
+                    pins.push(pin);
+                }
+            }
+
+            // Mark the pins of snapshots that are no longer the current snapshot of their entity.
+            let mut current = HashMap::<ExactLink, Option<Digest>>::new();
+            for pin in pins {
+                // Skip deletion markers and keys that aren't pins.
+                if pin.content_len() == 0 {
+                    continue;
+                }
+                let Ok(key) = LeafGcPath::from_bytes(pin.key()) else {
+                    continue;
+                };
+                let link = ExactLink {
+                    namespace: *namespace.as_bytes(),
+                    subspace: key.prefix.subspace,
+                    path: EntityPath(key.prefix.entity_path),
+                };
+                let snapshot = match current.get(&link) {
+                    Some(snapshot) => *snapshot,
+                    None => {
+                        let snapshot = self.get_entity(&link).await?;
+                        current.insert(link, snapshot);
+                        snapshot
+                    }
+                };
+
+                let dead = writable
+                    && snapshot != Some(key.prefix.entity_snapshot_id)
+                    && pin.timestamp() < cutoff
+                    && authors.contains(&pin.author());
+                let blob = blobs
+                    .entry(Digest(pin.content_hash()))
+                    .or_insert((pin.content_len(), false));
+                if dead {
+                    // Sweep the pin. The key ends with a digest, so it isn't a prefix of any other
+                    // pin.
+                    doc.del(pin.author(), pin.key().to_vec()).await?;
+                    report.pins += 1;
+                } else {
+                    blob.1 = true;
+                }
+            }
+        }
+
+        for (size, pinned) in blobs.into_values() {
+            if !pinned {
+                report.blobs += 1;
+                report.bytes += size;
+            }
+        }
+        Ok(report)
     }
 }
 
@@ -194,6 +1008,7 @@ impl LeafStore for LeafIrohStore {
         link: &ExactLink,
         entity_snapshot_id: Digest,
     ) -> anyhow::Result<Digest> {
+        self.check_writable(link.namespace).await?;
         let doc = self.open(link.namespace.into()).await?;
         let hash = self.client.blobs().add_bytes(data.to_vec()).await?.hash;
 
@@ -210,11 +1025,17 @@ impl LeafStore for LeafIrohStore {
         link: &ExactLink,
         entity_snapshot_id: Digest,
     ) -> anyhow::Result<usize> {
+        self.check_writable(link.namespace).await?;
         let doc = self.open(link.namespace.into()).await?;
 
-        let path_prefix = LeafGcPathPrefix::new(link, entity_snapshot_id).to_bytes();
+        let path_prefix = LeafGcPathPrefix::new(link, entity_snapshot_id);
         let author_id = self.client.authors().default().await?;
-        let deleted = doc.del(author_id, path_prefix).await?;
+        let mut deleted = 0;
+        for format in [KeyFormat::V1, KeyFormat::Legacy] {
+            deleted += doc
+                .del(author_id, path_prefix.to_bytes_with_format(format))
+                .await?;
+        }
 
         Ok(deleted)
     }
@@ -224,59 +1045,219 @@ impl LeafStore for LeafIrohStore {
     }
 
     async fn store_entity(&self, link: &ExactLink, data: Vec<u8>) -> anyhow::Result<Digest> {
+        self.check_writable(link.namespace).await?;
         let doc = self.open(link.namespace.into()).await?;
         let key = Self::get_entity_key(link.subspace, &link.path.0);
         let digest = doc.set_bytes(link.subspace.into(), key, data).await?;
+        self.del_legacy_entity(&doc, link).await?;
         Ok(Digest(digest))
     }
     async fn del_entity(&self, link: &ExactLink) -> anyhow::Result<()> {
+        self.check_writable(link.namespace).await?;
         let doc = self.open(link.namespace.into()).await?;
         let key = Self::get_entity_key(link.subspace, &link.path.0);
         doc.del(link.subspace.into(), key).await?;
+        self.del_legacy_entity(&doc, link).await?;
         Ok(())
     }
 
     async fn get_entity(&self, link: &ExactLink) -> anyhow::Result<Option<Digest>> {
         let doc = self.open(link.namespace.into()).await?;
-        let key = Self::get_entity_key(link.subspace, &link.path.0);
-        let entity = doc.get_exact(link.subspace.into(), key, false).await?;
-        let entity = entity.map(|entry| Digest(entry.content_hash()));
-        Ok(entity)
+        let entry = self.get_entry(&doc, link).await?;
+        Ok(entry.map(|entry| Digest(entry.content_hash())))
+    }
+
+    /// Signed Iroh entries are synced into the node, so they keep the signatures and timestamp
+    /// that their author wrote them with, and they don't need the subspace secret. An
+    /// [`EntityAuthorship::Signature`] can't be turned into an Iroh entry though, so those are
+    /// only stored for subspaces that we have the secret of, by signing a new entry.
+    async fn store_signed_entity(
+        &self,
+        link: &ExactLink,
+        data: Vec<u8>,
+        authorship: EntityAuthorship,
+    ) -> anyhow::Result<Digest> {
+        let digest = Digest::new(&data);
+        let entry = match authorship {
+            EntityAuthorship::Signature(signature) => {
+                signature.verify(link, digest)?;
+                if self.get_subspace_secret(link.subspace).await?.is_none() {
+                    anyhow::bail!(
+                        "The Iroh store can only write entities with a Leaf signature to \
+                        subspaces that it has the secret of."
+                    );
+                }
+                return self.store_entity(link, data).await;
+            }
+            EntityAuthorship::IrohEntry(entry) => verify_signed_entry(&entry, link, digest)?,
+        };
+        if entry.content_len() != data.len() as u64 {
+            return Err(InvalidEntitySignature {
+                link: link.clone(),
+                digest,
+            }
+            .into());
+        }
+        self.check_writable(link.namespace).await?;
+        let doc = self.open(link.namespace.into()).await?;
+        let entry_sync = self.entry_sync().await?;
+
+        // Add the content before the entry, so that the node doesn't try to download it from
+        // us. The tag only needs to protect the content until the entry does.
+        let added = self.client.blobs().add_bytes(data).await?;
+        let result = entry_sync.sync_with(&self.client, &doc, Some(entry)).await;
+        self.client.tags().delete(added.tag).await?;
+        result?;
+
+        if self.get_entity(link).await? != Some(digest) {
+            anyhow::bail!(
+                "The Iroh node has a newer entry for the entity at {link:?} than the one being \
+                stored."
+            );
+        }
+        Ok(digest)
+    }
+
+    /// Returns the signed Iroh entry of the entity, which is synced from the node the first time
+    /// it is needed.
+    async fn get_entity_signature(
+        &self,
+        link: &ExactLink,
+    ) -> anyhow::Result<Option<EntityAuthorship>> {
+        let doc = self.open(link.namespace.into()).await?;
+        let Some(entry) = self.get_entry(&doc, link).await? else {
+            return Ok(None);
+        };
+        let entry_sync = self.entry_sync().await?;
+        let is_current = |signed: &Option<SignedEntry>| {
+            signed.as_ref().is_some_and(|signed| {
+                signed.content_hash() == entry.content_hash()
+                    && signed.timestamp() == entry.timestamp()
+            })
+        };
+
+        let mut signed = entry_sync
+            .get_exact(doc.id(), entry.author(), entry.key())
+            .await?;
+        if !is_current(&signed) {
+            entry_sync.sync_with(&self.client, &doc, None).await?;
+            signed = entry_sync
+                .get_exact(doc.id(), entry.author(), entry.key())
+                .await?;
+        }
+        match signed {
+            Some(signed) if is_current(&Some(signed.clone())) => Ok(Some(
+                EntityAuthorship::IrohEntry(postcard::to_allocvec(&signed)?),
+            )),
+            _ => anyhow::bail!("Could not sync the signed entry of the entity at {link:?}."),
+        }
     }
 
     async fn list(
         &self,
         link: ExactLink,
+        depth: Option<u32>,
+        after: Option<EntityPath>,
         limit: Option<u64>,
-        offset: Option<u64>,
     ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<ExactLink>>> {
         let link = link.clone();
         let doc = self.open(link.namespace.into()).await?;
+        let namespace = doc.id();
 
         let mut path = vec![PathSegment::Bytes(link.subspace.to_vec())];
         path.extend(link.path.0.iter().cloned());
-        let path_bytes = IrohDocumentKeyFormat::new(path).to_bytes();
-        // Remove the null terminator so that we find all of the children of this path
-        let path_bytes = &path_bytes[0..(path_bytes.len() - 1)];
+        let max_segments = depth.map(|depth| path.len() + depth as usize);
+        let key = IrohDocumentKeyFormat::new(path);
+        let author: AuthorId = link.subspace.into();
 
-        let mut query = Query::key_prefix(path_bytes).author(link.subspace.into());
-        if let Some(limit) = limit {
-            query = query.limit(limit);
-        }
-        if let Some(offset) = offset {
-            query = query.limit(offset);
+        // Entities with current keys are listed first, followed by the entities that still have
+        // legacy keys, so to resume after a path we have to find out which of the two it was
+        // listed from.
+        let mut after_key = None;
+        let mut after_legacy_key = None;
+        let mut skip_current = false;
+        if let Some(after) = after {
+            let current = Self::get_entity_key(link.subspace, &after.0);
+            let legacy =
+                Self::get_entity_key_with_format(link.subspace, &after.0, KeyFormat::Legacy);
+            skip_current = doc
+                .get_exact(author, current.clone(), false)
+                .await?
+                .is_none()
+                && doc
+                    .get_exact(author, legacy.clone(), false)
+                    .await?
+                    .is_some();
+            if skip_current {
+                after_legacy_key = Some(legacy);
+            } else {
+                after_key = Some(current);
+            }
         }
-        let stream = doc.get_many(query).await?;
 
-        let s = stream.and_then(move |x| async move {
-            let mut key = IrohDocumentKeyFormat::from_bytes(x.key())?;
-            key.path.remove(0); // Remove the subspace path segment
+        let current_prefix = key.to_prefix_bytes(KeyFormat::V1);
+        let current = self
+            .query_within_depth(
+                &doc,
+                author,
+                current_prefix.clone(),
+                after_key,
+                max_segments,
+            )
+            .await?
+            .try_filter(move |_| std::future::ready(!skip_current))
+            .map_ok(move |(position, x)| (KeyFormat::V1, position, x));
 
-            Ok(ExactLink {
-                namespace: link.namespace,
-                subspace: link.subspace,
-                path: EntityPath(key.path),
-            })
+        // Legacy entries are skipped if the entity has since been written with a current key.
+        let legacy_prefix = key.to_prefix_bytes(KeyFormat::Legacy);
+        let legacy = self
+            .query_within_depth(
+                &doc,
+                author,
+                legacy_prefix.clone(),
+                after_legacy_key,
+                max_segments,
+            )
+            .await?
+            .try_filter_map(move |(position, x)| {
+                let doc = doc.clone();
+                async move {
+                    let key = IrohDocumentKeyFormat::from_bytes(x.key())?;
+                    let shadowed = doc.get_exact(author, key.to_bytes(), false).await?;
+                    Ok(shadowed
+                        .is_none()
+                        .then_some((KeyFormat::Legacy, position, x)))
+                }
+            });
+
+        let stream = current.chain(legacy);
+        let limit = limit.map(|x| x as usize).unwrap_or(usize::MAX);
+        let stream = stream.take(limit);
+
+        // Remember where a full page ended, so that the next page can skip to it. We remember the
+        // entry before the last one too, because callers that list one extra entity to find out
+        // if there is another page, like `Leaf::list_page()`, continue from there.
+        let cursors = self.cursors.clone();
+        let mut listed = 0;
+        let s = stream.and_then(move |(format, position, x)| {
+            listed += 1;
+            if listed >= limit.saturating_sub(1) {
+                let prefix = match format {
+                    KeyFormat::V1 => current_prefix.clone(),
+                    KeyFormat::Legacy => legacy_prefix.clone(),
+                };
+                cursors.insert((namespace, prefix, x.key().to_vec()), position);
+            }
+            async move {
+                let mut key = IrohDocumentKeyFormat::from_bytes(x.key())?;
+                key.path.remove(0); // Remove the subspace path segment
+
+                Ok(ExactLink {
+                    namespace: link.namespace,
+                    subspace: link.subspace,
+                    path: EntityPath(key.path),
+                })
+            }
         });
 
         Ok(s)
@@ -317,6 +1298,7 @@ impl LeafStore for LeafIrohStore {
             .docs()
             .import_namespace(iroh::docs::Capability::Write(secret))
             .await?;
+        self.writable.insert(id.into(), ());
         Ok(id)
     }
 
@@ -324,6 +1306,9 @@ impl LeafStore for LeafIrohStore {
         &self,
         namespace: crate::prelude::NamespaceId,
     ) -> anyhow::Result<Option<NamespaceSecretKey>> {
+        if self.namespace_capability(namespace.into()).await? != Some(NamespaceCapability::Write) {
+            return Ok(None);
+        }
         let doc = self.open(namespace.into()).await?;
         let capability = doc
             .share(iroh::client::docs::ShareMode::Write, AddrInfoOptions::Id)
@@ -353,13 +1338,94 @@ impl LeafStore for LeafIrohStore {
 
     async fn list_namespaces(
         &self,
-    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<leaf_protocol_types::NamespaceId>>>
-    {
+    ) -> anyhow::Result<
+        impl futures::Stream<
+            Item = anyhow::Result<(leaf_protocol_types::NamespaceId, NamespaceCapability)>,
+        >,
+    > {
         Ok(self
             .client
             .docs()
             .list()
             .await?
-            .map_ok(move |(id, _)| *id.as_bytes()))
+            .map_ok(move |(id, kind)| (*id.as_bytes(), capability_from_kind(kind))))
+    }
+
+    async fn list_namespace_subspaces(
+        &self,
+        namespace: leaf_protocol_types::NamespaceId,
+    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<SubspaceId>>> {
+        let doc = self.open(namespace.into()).await?;
+
+        let mut subspaces = BTreeSet::new();
+        let mut entries = doc.get_many(Query::all()).await?;
+        while let Some(entry) = entries.try_next().await? {
+            // Entity keys start with the subspace that they are written by, so we can skip any
+            // entries, such as GC pins, where the key doesn't start with the entry author.
+            let author = *entry.author().as_bytes();
+            if subspaces.contains(&author) {
+                continue;
+            }
+            let key = IrohDocumentKeyFormat::new(vec![PathSegment::Bytes(author.to_vec())]);
+            let format = KeyFormat::detect(entry.key());
+            if entry.key().starts_with(&key.to_prefix_bytes(format)) {
+                subspaces.insert(author);
+            }
+        }
+
+        Ok(futures::stream::iter(subspaces.into_iter().map(Ok)))
+    }
+
+    async fn share_namespace(
+        &self,
+        namespace: leaf_protocol_types::NamespaceId,
+        mode: ShareMode,
+    ) -> anyhow::Result<String> {
+        let doc = self.open(namespace.into()).await?;
+        let mode = match mode {
+            ShareMode::Read => iroh::client::docs::ShareMode::Read,
+            ShareMode::Write => iroh::client::docs::ShareMode::Write,
+        };
+        let ticket = doc.share(mode, AddrInfoOptions::RelayAndAddresses).await?;
+        Ok(ticket.to_string())
+    }
+
+    async fn join_namespace(
+        &self,
+        ticket: &str,
+    ) -> anyhow::Result<leaf_protocol_types::NamespaceId> {
+        let ticket: DocTicket = ticket.parse()?;
+        let doc = self.client.docs().import(ticket).await?;
+        Ok(doc.id().to_bytes())
+    }
+
+    async fn list_sync_peers(
+        &self,
+        namespace: leaf_protocol_types::NamespaceId,
+    ) -> anyhow::Result<Vec<SyncPeer>> {
+        let doc = self.open(namespace.into()).await?;
+        let mut peers = Vec::new();
+        for node_id in doc.get_sync_peers().await?.unwrap_or_default() {
+            // The node remembers the store's own entry sync as a peer.
+            if self.entry_sync.get().map(EntrySync::node_id) == Some(node_id) {
+                continue;
+            }
+            let info = self
+                .client
+                .connection_info(iroh::net::NodeId::from_bytes(&node_id)?)
+                .await?;
+            let connection = match info.as_ref().map(|x| &x.conn_type) {
+                Some(ConnectionType::Direct(_)) => PeerConnection::Direct,
+                Some(ConnectionType::Relay(_)) => PeerConnection::Relay,
+                Some(ConnectionType::Mixed(_, _)) => PeerConnection::Mixed,
+                Some(ConnectionType::None) | None => PeerConnection::None,
+            };
+            peers.push(SyncPeer {
+                node_id,
+                connection,
+                latency_ms: info.and_then(|x| x.latency).map(|x| x.as_millis() as u64),
+            });
+        }
+        Ok(peers)
     }
 }
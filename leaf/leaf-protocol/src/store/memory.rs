@@ -0,0 +1,612 @@
+//! A [`LeafStore`] that keeps everything in memory.
+//!
+//! This is useful for tests and for embedding Leaf in small tools that don't need to persist or
+//! sync their data. Namespace and subspace IDs are derived from their secrets the same way as in
+//! the Iroh backend, so secrets can be moved between the two.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    ops::Bound,
+    sync::{Arc, Mutex},
+};
+
+use iroh_base::key::SecretKey;
+
+use crate::{
+    store::{EntityAuthorship, EntitySignature, LeafStore, NamespaceCapability, ReadOnlyNamespace},
+    types::{
+        EntityPath, NamespaceId, NamespaceSecretKey, PathSegment, SubspaceId, SubspaceSecretKey,
+    },
+    Digest, ExactLink,
+};
+
+pub type LeafMemory = crate::Leaf<LeafMemoryStore>;
+
+/// An in-memory [`LeafStore`].
+///
+/// Cloning the store is cheap and the clones share the same data.
+///
+/// Blobs are reference counted by their garbage collector pins and by the entities that point to
+/// them, and they are dropped as soon as nothing references them anymore.
+#[derive(Debug, Clone, Default)]
+pub struct LeafMemoryStore {
+    inner: Arc<Mutex<MemoryStoreInner>>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryStoreInner {
+    blobs: HashMap<Digest, MemoryBlob>,
+    /// The blobs pinned for each entity snapshot.
+    pins: HashMap<PinKey, HashSet<Digest>>,
+    namespaces: HashMap<NamespaceId, MemoryNamespace>,
+    subspaces: HashMap<SubspaceId, SubspaceSecretKey>,
+}
+
+#[derive(Debug)]
+struct MemoryBlob {
+    data: Vec<u8>,
+    refs: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct PinKey {
+    namespace: NamespaceId,
+    subspace: SubspaceId,
+    path: EntityPath,
+    entity_snapshot_id: Digest,
+}
+
+impl PinKey {
+    fn new(link: &ExactLink, entity_snapshot_id: Digest) -> Self {
+        Self {
+            namespace: link.namespace,
+            subspace: link.subspace,
+            path: link.path.clone(),
+            entity_snapshot_id,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct MemoryNamespace {
+    /// The namespace secret, if we have write access to the namespace.
+    secret: Option<NamespaceSecretKey>,
+    /// The digest of the entity snapshot at each subspace and path.
+    entities: BTreeMap<(SubspaceId, EntityPath), Digest>,
+    /// The authorship of the entities that were stored with
+    /// [`LeafStore::store_signed_entity()`].
+    signatures: HashMap<(SubspaceId, EntityPath), EntityAuthorship>,
+}
+
+impl MemoryStoreInner {
+    fn add_blob_ref(&mut self, data: &[u8]) -> Digest {
+        let digest = Digest::new(data);
+        self.blobs
+            .entry(digest)
+            .or_insert_with(|| MemoryBlob {
+                data: data.to_vec(),
+                refs: 0,
+            })
+            .refs += 1;
+        digest
+    }
+
+    fn del_blob_ref(&mut self, digest: Digest) {
+        if let Some(blob) = self.blobs.get_mut(&digest) {
+            blob.refs -= 1;
+            if blob.refs == 0 {
+                self.blobs.remove(&digest);
+            }
+        }
+    }
+
+    /// Get a namespace that we can write to.
+    fn writable_namespace(&mut self, link: &ExactLink) -> anyhow::Result<&mut MemoryNamespace> {
+        if !self.subspaces.contains_key(&link.subspace) {
+            anyhow::bail!("Cannot write to subspace without its secret.");
+        }
+        self.writable_namespace_without_subspace(link)
+    }
+
+    /// Get a namespace that we can write to, without checking that we have the subspace secret.
+    fn writable_namespace_without_subspace(
+        &mut self,
+        link: &ExactLink,
+    ) -> anyhow::Result<&mut MemoryNamespace> {
+        match self.namespaces.get_mut(&link.namespace) {
+            Some(namespace) if namespace.secret.is_some() => Ok(namespace),
+            _ => Err(ReadOnlyNamespace {
+                namespace: link.namespace,
+            }
+            .into()),
+        }
+    }
+}
+
+/// Get the first path that comes after `path` and all of the paths below it, or [`None`] if
+/// `path` is the root path, which every other path is below.
+fn subtree_end(path: &[PathSegment]) -> Option<EntityPath> {
+    let mut path = path.to_vec();
+    // The next segment after the last one, with no other segments in between.
+    let next = match path.pop()? {
+        PathSegment::Null => PathSegment::Bool(false),
+        PathSegment::Bool(false) => PathSegment::Bool(true),
+        PathSegment::Bool(true) => PathSegment::Uint(0),
+        PathSegment::Uint(u64::MAX) => PathSegment::Int(i64::MIN),
+        PathSegment::Uint(x) => PathSegment::Uint(x + 1),
+        PathSegment::Int(i64::MAX) => PathSegment::String(String::new()),
+        PathSegment::Int(x) => PathSegment::Int(x + 1),
+        PathSegment::String(x) => PathSegment::String(x + "\0"),
+        PathSegment::Bytes(mut x) => {
+            x.push(0);
+            PathSegment::Bytes(x)
+        }
+    };
+    path.push(next);
+    Some(EntityPath(path))
+}
+
+impl LeafMemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn inner(&self) -> std::sync::MutexGuard<'_, MemoryStoreInner> {
+        self.inner.lock().unwrap()
+    }
+}
+
+impl LeafStore for LeafMemoryStore {
+    fn key_resolvers(&self) -> Box<dyn Iterator<Item = &dyn super::KeyResolverImpl<Digest>> + '_> {
+        Box::new([].into_iter())
+    }
+
+    fn encryption_algorithms(
+        &self,
+    ) -> Box<dyn Iterator<Item = &dyn super::EncryptionAlgorithmImpl<Digest>> + '_> {
+        Box::new([].into_iter())
+    }
+
+    async fn store_blob(
+        &self,
+        data: &[u8],
+        link: &ExactLink,
+        entity_snapshot_id: Digest,
+    ) -> anyhow::Result<Digest> {
+        let mut inner = self.inner();
+        let digest = Digest::new(data);
+        let newly_pinned = inner
+            .pins
+            .entry(PinKey::new(link, entity_snapshot_id))
+            .or_default()
+            .insert(digest);
+        if newly_pinned {
+            inner.add_blob_ref(data);
+        }
+        Ok(digest)
+    }
+
+    async fn del_blobs(
+        &self,
+        link: &ExactLink,
+        entity_snapshot_id: Digest,
+    ) -> anyhow::Result<usize> {
+        let mut inner = self.inner();
+        let Some(pinned) = inner.pins.remove(&PinKey::new(link, entity_snapshot_id)) else {
+            return Ok(0);
+        };
+        for &digest in &pinned {
+            inner.del_blob_ref(digest);
+        }
+        Ok(pinned.len())
+    }
+
+    async fn get_blob(&self, digest: Digest) -> anyhow::Result<Vec<u8>> {
+        let inner = self.inner();
+        let Some(blob) = inner.blobs.get(&digest) else {
+            anyhow::bail!("Blob not found: {digest}");
+        };
+        Ok(blob.data.clone())
+    }
+
+    async fn store_entity(&self, link: &ExactLink, data: Vec<u8>) -> anyhow::Result<Digest> {
+        let mut inner = self.inner();
+        inner.writable_namespace(link)?;
+        let digest = inner.add_blob_ref(&data);
+        let namespace = inner.writable_namespace(link)?;
+        let key = (link.subspace, link.path.clone());
+        namespace.signatures.remove(&key);
+        let old = namespace.entities.insert(key, digest);
+        if let Some(old) = old {
+            inner.del_blob_ref(old);
+        }
+        Ok(digest)
+    }
+
+    async fn del_entity(&self, link: &ExactLink) -> anyhow::Result<()> {
+        let mut inner = self.inner();
+        let namespace = inner.writable_namespace(link)?;
+        let key = (link.subspace, link.path.clone());
+        namespace.signatures.remove(&key);
+        let old = namespace.entities.remove(&key);
+        if let Some(old) = old {
+            inner.del_blob_ref(old);
+        }
+        Ok(())
+    }
+
+    async fn get_entity(&self, link: &ExactLink) -> anyhow::Result<Option<Digest>> {
+        let inner = self.inner();
+        Ok(inner
+            .namespaces
+            .get(&link.namespace)
+            .and_then(|x| x.entities.get(&(link.subspace, link.path.clone())))
+            .copied())
+    }
+
+    async fn store_signed_entity(
+        &self,
+        link: &ExactLink,
+        data: Vec<u8>,
+        authorship: EntityAuthorship,
+    ) -> anyhow::Result<Digest> {
+        authorship.verify(link, Digest::new(&data))?;
+        let mut inner = self.inner();
+        inner.writable_namespace_without_subspace(link)?;
+        let digest = inner.add_blob_ref(&data);
+        let namespace = inner.writable_namespace_without_subspace(link)?;
+        let key = (link.subspace, link.path.clone());
+        namespace.signatures.insert(key.clone(), authorship);
+        let old = namespace.entities.insert(key, digest);
+        if let Some(old) = old {
+            inner.del_blob_ref(old);
+        }
+        Ok(digest)
+    }
+
+    async fn get_entity_signature(
+        &self,
+        link: &ExactLink,
+    ) -> anyhow::Result<Option<EntityAuthorship>> {
+        let inner = self.inner();
+        let Some(namespace) = inner.namespaces.get(&link.namespace) else {
+            return Ok(None);
+        };
+        let key = (link.subspace, link.path.clone());
+        let Some(&digest) = namespace.entities.get(&key) else {
+            return Ok(None);
+        };
+        if let Some(authorship) = namespace.signatures.get(&key) {
+            return Ok(Some(authorship.clone()));
+        }
+        Ok(inner
+            .subspaces
+            .get(&link.subspace)
+            .map(|secret| EntityAuthorship::Signature(EntitySignature::sign(secret, link, digest))))
+    }
+
+    async fn list(
+        &self,
+        link: ExactLink,
+        depth: Option<u32>,
+        after: Option<EntityPath>,
+        limit: Option<u64>,
+    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<ExactLink>>> {
+        let inner = self.inner();
+        let max_len = depth.map(|depth| link.path.0.len() + depth as usize);
+        let limit = limit.map(|x| x as usize).unwrap_or(usize::MAX);
+        let Some(namespace) = inner.namespaces.get(&link.namespace) else {
+            return Ok(futures::stream::iter(Vec::new()));
+        };
+
+        // Entities are listed in the order of their paths, and all of the paths that start with
+        // the path of `link` come right after it.
+        let mut start = match after {
+            Some(after) if after > link.path => Bound::Excluded((link.subspace, after)),
+            _ => Bound::Included((link.subspace, link.path.clone())),
+        };
+        let mut links = Vec::new();
+        'listing: loop {
+            let mut skip_to = None;
+            for (subspace, path) in namespace
+                .entities
+                .range((start, Bound::Unbounded))
+                .map(|x| x.0)
+            {
+                if links.len() >= limit
+                    || *subspace != link.subspace
+                    || !path.0.starts_with(&link.path.0)
+                {
+                    break 'listing;
+                }
+                match max_len {
+                    // The entities below a path that is too deep all come right after it, so we
+                    // skip past all of them at once.
+                    Some(max) if path.0.len() > max => {
+                        match subtree_end(&path.0[..max]) {
+                            Some(end) => skip_to = Some(end),
+                            None => break 'listing,
+                        }
+                        break;
+                    }
+                    _ => links.push(Ok(ExactLink {
+                        namespace: link.namespace,
+                        subspace: *subspace,
+                        path: path.clone(),
+                    })),
+                }
+            }
+            match skip_to {
+                Some(path) => start = Bound::Included((link.subspace, path)),
+                None => break,
+            }
+        }
+
+        Ok(futures::stream::iter(links))
+    }
+
+    async fn create_subspace(&self) -> anyhow::Result<SubspaceId> {
+        self.import_subspace_secret(SecretKey::generate().to_bytes())
+            .await
+    }
+
+    async fn import_subspace_secret(
+        &self,
+        subspace_secret: SubspaceSecretKey,
+    ) -> anyhow::Result<SubspaceId> {
+        let id = *SecretKey::from_bytes(&subspace_secret).public().as_bytes();
+        self.inner().subspaces.insert(id, subspace_secret);
+        Ok(id)
+    }
+
+    async fn get_subspace_secret(
+        &self,
+        subspace: SubspaceId,
+    ) -> anyhow::Result<Option<SubspaceSecretKey>> {
+        Ok(self.inner().subspaces.get(&subspace).copied())
+    }
+
+    async fn create_namespace(&self) -> anyhow::Result<NamespaceId> {
+        self.import_namespace_secret(SecretKey::generate().to_bytes())
+            .await
+    }
+
+    async fn import_namespace_secret(
+        &self,
+        namespace_secret: [u8; 32],
+    ) -> anyhow::Result<NamespaceId> {
+        let id = *SecretKey::from_bytes(&namespace_secret).public().as_bytes();
+        self.inner().namespaces.entry(id).or_default().secret = Some(namespace_secret);
+        Ok(id)
+    }
+
+    async fn get_namespace_secret(
+        &self,
+        namespace: NamespaceId,
+    ) -> anyhow::Result<Option<NamespaceSecretKey>> {
+        Ok(self
+            .inner()
+            .namespaces
+            .get(&namespace)
+            .and_then(|x| x.secret))
+    }
+
+    async fn list_subspaces(
+        &self,
+    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<SubspaceId>>> {
+        let subspaces = self.inner().subspaces.keys().copied().collect::<Vec<_>>();
+        Ok(futures::stream::iter(subspaces.into_iter().map(Ok)))
+    }
+
+    async fn list_namespaces(
+        &self,
+    ) -> anyhow::Result<
+        impl futures::Stream<Item = anyhow::Result<(NamespaceId, NamespaceCapability)>>,
+    > {
+        let namespaces = self
+            .inner()
+            .namespaces
+            .iter()
+            .map(|(id, namespace)| {
+                let capability = match namespace.secret {
+                    Some(_) => NamespaceCapability::Write,
+                    None => NamespaceCapability::Read,
+                };
+                (*id, capability)
+            })
+            .collect::<Vec<_>>();
+        Ok(futures::stream::iter(namespaces.into_iter().map(Ok)))
+    }
+
+    async fn list_namespace_subspaces(
+        &self,
+        namespace: NamespaceId,
+    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<SubspaceId>>> {
+        let subspaces = self
+            .inner()
+            .namespaces
+            .get(&namespace)
+            .map(|x| {
+                x.entities
+                    .keys()
+                    .map(|(subspace, _)| *subspace)
+                    .collect::<BTreeSet<_>>()
+            })
+            .unwrap_or_default();
+        Ok(futures::stream::iter(subspaces.into_iter().map(Ok)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{components::*, Leaf};
+
+    async fn leaf() -> anyhow::Result<(Leaf<LeafMemoryStore>, NamespaceId, SubspaceId)> {
+        let leaf = Leaf::new(LeafMemoryStore::new());
+        let namespace = leaf.create_namespace().await?;
+        let subspace = leaf.create_subspace().await?;
+        Ok((leaf, namespace, subspace))
+    }
+
+    /// Get the components pinned to the current snapshot of the entity at `link`.
+    async fn pinned(store: &LeafMemoryStore, link: &ExactLink) -> anyhow::Result<usize> {
+        let snapshot = store.get_entity(link).await?.unwrap();
+        let inner = store.inner();
+        Ok(inner
+            .pins
+            .get(&PinKey::new(link, snapshot))
+            .map_or(0, |x| x.len()))
+    }
+
+    #[tokio::test]
+    async fn save_and_load() -> anyhow::Result<()> {
+        let (leaf, namespace, subspace) = leaf().await?;
+        let link: ExactLink = (namespace, subspace, ["entity"]).into();
+        let mut entity = leaf.entity(link.clone()).await?.get_or_init();
+        entity.add_component(Name("name".into()))?;
+        entity.save().await?;
+
+        let entity = leaf.entity(link.clone()).await?.entity()?;
+        assert_eq!(entity.get_component::<Name>().await?.unwrap().0, "name");
+        assert_eq!(pinned(&leaf.store, &link).await?, 1);
+        // The snapshot and the component.
+        assert_eq!(leaf.store.inner().blobs.len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn kept_components_stay_pinned() -> anyhow::Result<()> {
+        let (leaf, namespace, subspace) = leaf().await?;
+        let link: ExactLink = (namespace, subspace, ["entity"]).into();
+        let mut entity = leaf.entity(link.clone()).await?.get_or_init();
+        entity.add_component(Name("name".into()))?;
+        entity.save().await?;
+        let old_snapshot = leaf.store.get_entity(&link).await?.unwrap();
+
+        entity.add_component(Description("description".into()))?;
+        entity.save().await?;
+        assert_eq!(pinned(&leaf.store, &link).await?, 2);
+        assert!(!leaf
+            .store
+            .inner()
+            .pins
+            .contains_key(&PinKey::new(&link, old_snapshot)));
+
+        let entity = leaf.entity(link).await?.entity()?;
+        assert_eq!(entity.get_component::<Name>().await?.unwrap().0, "name");
+        // The old snapshot has been freed.
+        assert_eq!(leaf.store.inner().blobs.len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unchanged_save_keeps_snapshot() -> anyhow::Result<()> {
+        let (leaf, namespace, subspace) = leaf().await?;
+        let link: ExactLink = (namespace, subspace, ["entity"]).into();
+        let mut entity = leaf.entity(link.clone()).await?.get_or_init();
+        entity.add_component(Name("name".into()))?;
+        entity.save().await?;
+        let snapshot = leaf.store.get_entity(&link).await?;
+
+        entity.set_component(Name("name".into()))?;
+        entity.save().await?;
+        assert_eq!(leaf.store.get_entity(&link).await?, snapshot);
+        assert_eq!(pinned(&leaf.store, &link).await?, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unchanged_save_keeps_timestamps() -> anyhow::Result<()> {
+        let (leaf, namespace, subspace) = leaf().await?;
+        let leaf = leaf.with_timestamps();
+        let link: ExactLink = (namespace, subspace, ["entity"]).into();
+        let mut entity = leaf.entity(link.clone()).await?.get_or_init();
+        entity.add_component(Name("name".into()))?;
+        // Timestamps that are set explicitly are kept, so we can tell if they are replaced.
+        entity.add_component(DateCreated(1))?;
+        entity.add_component(DateUpdated(1))?;
+        entity.save().await?;
+
+        entity.set_component(Name("name".into()))?;
+        entity.save().await?;
+        let entity = leaf.entity(link.clone()).await?.entity()?;
+        assert_eq!(entity.get_component::<DateUpdated>().await?.unwrap().0, 1);
+
+        let mut entity = leaf.entity(link.clone()).await?.entity()?;
+        entity.set_component(Name("changed".into()))?;
+        entity.save().await?;
+        let entity = leaf.entity(link).await?.entity()?;
+        assert!(entity.get_component::<DateUpdated>().await?.unwrap().0 > 1);
+        assert_eq!(entity.get_component::<DateCreated>().await?.unwrap().0, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_frees_blobs() -> anyhow::Result<()> {
+        let (leaf, namespace, subspace) = leaf().await?;
+        let a: ExactLink = (namespace, subspace, ["a"]).into();
+        let b: ExactLink = (namespace, subspace, ["b"]).into();
+        for link in [&a, &b] {
+            let mut entity = leaf.entity(link.clone()).await?.get_or_init();
+            entity.add_component(Name(format!("{:?}", link.path)))?;
+            entity.add_component(Description("shared".into()))?;
+            entity.save().await?;
+        }
+        // Two snapshots, two names, and the shared description.
+        assert_eq!(leaf.store.inner().blobs.len(), 5);
+
+        leaf.del_entity(a.clone()).await?;
+        assert_eq!(leaf.store.get_entity(&a).await?, None);
+        assert_eq!(leaf.store.inner().blobs.len(), 3);
+        let entity = leaf.entity(b.clone()).await?.entity()?;
+        assert_eq!(
+            entity.get_component::<Description>().await?.unwrap().0,
+            "shared"
+        );
+
+        leaf.del_entity(b).await?;
+        let inner = leaf.store.inner();
+        assert!(inner.blobs.is_empty());
+        assert!(inner.pins.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn blobs_are_freed_when_unpinned() -> anyhow::Result<()> {
+        let (leaf, namespace, subspace) = leaf().await?;
+        let link: ExactLink = (namespace, subspace, ["blobs"]).into();
+        let snapshot = Digest::new(b"snapshot");
+        let digest = leaf.store.store_blob(b"blob", &link, snapshot).await?;
+        // Pinning the same blob twice to one snapshot only pins it once.
+        leaf.store.store_blob(b"blob", &link, snapshot).await?;
+        leaf.store
+            .store_blob(b"blob", &link, Digest::new(b"other"))
+            .await?;
+
+        assert_eq!(leaf.store.del_blobs(&link, snapshot).await?, 1);
+        assert_eq!(leaf.store.get_blob(digest).await?, b"blob");
+        assert_eq!(leaf.store.del_blobs(&link, Digest::new(b"other")).await?, 1);
+        assert!(leaf.store.get_blob(digest).await.is_err());
+        assert_eq!(leaf.store.del_blobs(&link, snapshot).await?, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn empty_pages_are_rejected() -> anyhow::Result<()> {
+        let (leaf, namespace, subspace) = leaf().await?;
+        let link: ExactLink = (namespace, subspace, ["entity"]).into();
+        leaf.entity(link.clone())
+            .await?
+            .get_or_init()
+            .save()
+            .await?;
+
+        let root: ExactLink = (namespace, subspace, ()).into();
+        assert!(leaf.list_page(root.clone(), None, None, 0).await.is_err());
+        let page = leaf.list_page(root, None, None, 1).await?;
+        assert_eq!(page.entities, vec![link]);
+        assert!(page.next.is_none());
+        Ok(())
+    }
+}
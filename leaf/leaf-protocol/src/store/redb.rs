@@ -0,0 +1,696 @@
+//! A [`LeafStore`] that keeps everything in a single [`redb`] database file.
+//!
+//! This is a durable store for single-node deployments that don't need to sync with other peers,
+//! and that don't want to pay for running an Iroh node. Namespace and subspace IDs are derived
+//! from their secrets the same way as in the Iroh backend, so secrets can be moved between the two.
+
+use std::{collections::BTreeSet, ops::Bound, path::Path, sync::Arc};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use futures::TryStreamExt;
+use iroh_base::key::SecretKey;
+use redb::{
+    Database, MultimapTableDefinition, ReadTransaction, ReadableTable, TableDefinition,
+    WriteTransaction,
+};
+
+use crate::{
+    store::{EntityAuthorship, EntitySignature, LeafStore, NamespaceCapability, ReadOnlyNamespace},
+    types::{
+        EntityPath, NamespaceId, NamespaceSecretKey, PathSegment, SubspaceId, SubspaceSecretKey,
+    },
+    Digest, ExactLink,
+};
+
+pub type LeafRedb = crate::Leaf<LeafRedbStore>;
+
+/// Blob data by digest.
+const BLOBS: TableDefinition<[u8; 32], &[u8]> = TableDefinition::new("leaf_blobs");
+/// The number of pins and entities that reference each blob.
+const BLOB_REFS: TableDefinition<[u8; 32], u64> = TableDefinition::new("leaf_blob_refs");
+/// The blobs pinned for each entity snapshot, keyed by the encoded [`PinKey`].
+const PINS: MultimapTableDefinition<&[u8], [u8; 32]> = MultimapTableDefinition::new("leaf_pins");
+/// The digest of the entity snapshot at each namespace, subspace, and path, keyed by
+/// [`entity_key()`].
+const ENTITIES: TableDefinition<&[u8], [u8; 32]> = TableDefinition::new("leaf_entities");
+/// The signatures of the entities that were stored with [`LeafStore::store_signed_entity()`],
+/// keyed by [`entity_key()`].
+const ENTITY_SIGNATURES: TableDefinition<&[u8], [u8; 64]> =
+    TableDefinition::new("leaf_entity_signatures");
+/// The signed Iroh entries of the entities that were stored with
+/// [`LeafStore::store_signed_entity()`], keyed by [`entity_key()`].
+const ENTITY_IROH_ENTRIES: TableDefinition<&[u8], &[u8]> =
+    TableDefinition::new("leaf_entity_iroh_entries");
+const NAMESPACE_SECRETS: TableDefinition<[u8; 32], [u8; 32]> =
+    TableDefinition::new("leaf_namespace_secrets");
+const SUBSPACE_SECRETS: TableDefinition<[u8; 32], [u8; 32]> =
+    TableDefinition::new("leaf_subspace_secrets");
+
+/// The number of entities that [`LeafStore::list()`] reads from the database at a time.
+const LIST_BATCH_SIZE: usize = 256;
+
+/// A [`LeafStore`] backed by a [`redb`] database.
+///
+/// Cloning the store is cheap and the clones share the same database.
+///
+/// Blobs are reference counted by their garbage collector pins and by the entities that point to
+/// them, and they are deleted in the same transaction that drops their last reference, so no
+/// blobs are leaked.
+#[derive(Debug, Clone)]
+pub struct LeafRedbStore {
+    db: Arc<Database>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+struct PinKey {
+    namespace: NamespaceId,
+    subspace: SubspaceId,
+    entity_snapshot_id: Digest,
+    path: Vec<PathSegment>,
+}
+
+impl PinKey {
+    fn to_bytes(link: &ExactLink, entity_snapshot_id: Digest) -> Vec<u8> {
+        borsh::to_vec(&PinKey {
+            namespace: link.namespace,
+            subspace: link.subspace,
+            entity_snapshot_id,
+            path: link.path.0.clone(),
+        })
+        .unwrap()
+    }
+}
+
+/// Encode the key of an entity in the [`ENTITIES`] table.
+///
+/// The key is the namespace and subspace, followed by each path segment prefixed with its
+/// length, so the key of an entity starts with the keys of all of its parents.
+fn entity_key(namespace: NamespaceId, subspace: SubspaceId, path: &[PathSegment]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(64);
+    key.extend_from_slice(&namespace);
+    key.extend_from_slice(&subspace);
+    for segment in path {
+        let segment = borsh::to_vec(segment).unwrap();
+        let len: u32 = segment.len().try_into().unwrap();
+        key.extend_from_slice(&len.to_le_bytes());
+        key.extend_from_slice(&segment);
+    }
+    key
+}
+
+/// Decode the path from an [`entity_key()`].
+fn entity_key_path(key: &[u8]) -> anyhow::Result<EntityPath> {
+    let Some(mut bytes) = key.get(64..) else {
+        anyhow::bail!("Unexpected end of key.")
+    };
+    let mut path = Vec::new();
+    while !bytes.is_empty() {
+        let Some((len, rest)) = bytes.split_first_chunk::<4>() else {
+            anyhow::bail!("Unexpected end of key.")
+        };
+        let len = u32::from_le_bytes(*len) as usize;
+        if rest.len() < len {
+            anyhow::bail!("Unexpected end of key.")
+        }
+        path.push(PathSegment::try_from_slice(&rest[..len])?);
+        bytes = &rest[len..];
+    }
+    Ok(EntityPath(path))
+}
+
+/// Get the first key that comes after all of the keys that start with `prefix`, or [`None`] if
+/// there isn't one.
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+/// A batch of entities read by [`list_batch()`], and the key that the next batch starts at.
+type ListBatch = (Vec<ExactLink>, Option<Bound<Vec<u8>>>);
+
+/// Read a batch of at most `size` entities for [`LeafStore::list()`], starting at the key
+/// `start`.
+///
+/// Returns the entities, and where the next batch starts, or [`None`] if there are no more
+/// entities to list.
+fn list_batch(
+    tx: &ReadTransaction,
+    link: &ExactLink,
+    prefix: &[u8],
+    max_len: Option<usize>,
+    mut start: Bound<Vec<u8>>,
+    size: usize,
+) -> anyhow::Result<ListBatch> {
+    let mut links = Vec::new();
+    let table = tx.open_table(ENTITIES)?;
+    loop {
+        let mut skip_to = None;
+        for entry in table.range::<&[u8]>((start.as_ref().map(|x| &x[..]), Bound::Unbounded))? {
+            let (key, _) = entry?;
+            let key = key.value();
+            if !key.starts_with(prefix) {
+                return Ok((links, None));
+            }
+            let path = entity_key_path(key)?;
+            match max_len {
+                // The keys of the entities below a path that is too deep all start with its key,
+                // so we seek past all of them at once.
+                Some(max) if path.0.len() > max => {
+                    let parent = entity_key(link.namespace, link.subspace, &path.0[..max]);
+                    match prefix_end(&parent) {
+                        Some(end) => skip_to = Some(end),
+                        None => return Ok((links, None)),
+                    }
+                    break;
+                }
+                _ => {
+                    links.push(ExactLink {
+                        namespace: link.namespace,
+                        subspace: link.subspace,
+                        path,
+                    });
+                    if links.len() >= size {
+                        return Ok((links, Some(Bound::Excluded(key.to_vec()))));
+                    }
+                }
+            }
+        }
+        match skip_to {
+            Some(end) => start = Bound::Included(end),
+            None => return Ok((links, None)),
+        }
+    }
+}
+
+fn add_blob_ref(tx: &WriteTransaction, data: &[u8]) -> anyhow::Result<Digest> {
+    let digest = Digest::new(data);
+    let mut refs = tx.open_table(BLOB_REFS)?;
+    let count = refs.get(digest.as_bytes())?.map(|x| x.value()).unwrap_or(0);
+    if count == 0 {
+        tx.open_table(BLOBS)?.insert(digest.as_bytes(), data)?;
+    }
+    refs.insert(digest.as_bytes(), count + 1)?;
+    Ok(digest)
+}
+
+fn del_blob_ref(tx: &WriteTransaction, digest: Digest) -> anyhow::Result<()> {
+    let mut refs = tx.open_table(BLOB_REFS)?;
+    let count = refs.get(digest.as_bytes())?.map(|x| x.value()).unwrap_or(0);
+    if count <= 1 {
+        refs.remove(digest.as_bytes())?;
+        tx.open_table(BLOBS)?.remove(digest.as_bytes())?;
+    } else {
+        refs.insert(digest.as_bytes(), count - 1)?;
+    }
+    Ok(())
+}
+
+/// Forget the authorship that the entity with the given [`entity_key()`] was stored with.
+fn del_authorship(tx: &WriteTransaction, key: &[u8]) -> anyhow::Result<()> {
+    tx.open_table(ENTITY_SIGNATURES)?.remove(key)?;
+    tx.open_table(ENTITY_IROH_ENTRIES)?.remove(key)?;
+    Ok(())
+}
+
+/// Make sure that we have the secrets needed to write to the entity at `link`.
+fn check_writable(tx: &WriteTransaction, link: &ExactLink) -> anyhow::Result<()> {
+    if tx
+        .open_table(SUBSPACE_SECRETS)?
+        .get(link.subspace)?
+        .is_none()
+    {
+        anyhow::bail!("Cannot write to subspace without its secret.");
+    }
+    check_namespace_writable(tx, link)
+}
+
+/// Make sure that we have the namespace secret needed to write to the entity at `link`.
+fn check_namespace_writable(tx: &WriteTransaction, link: &ExactLink) -> anyhow::Result<()> {
+    if tx
+        .open_table(NAMESPACE_SECRETS)?
+        .get(link.namespace)?
+        .is_none()
+    {
+        return Err(ReadOnlyNamespace {
+            namespace: link.namespace,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+impl LeafRedbStore {
+    /// Open the database at the given path, creating it if it doesn't exist.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::new(Database::create(path)?)
+    }
+
+    /// Create a store using an existing database.
+    ///
+    /// The store only uses tables with names starting with `leaf_`, so the database may be shared
+    /// with other tables.
+    pub fn new(db: Database) -> anyhow::Result<Self> {
+        let tx = db.begin_write()?;
+        {
+            // Make sure that all of the tables exist so that reads don't fail on a new database.
+            tx.open_table(BLOBS)?;
+            tx.open_table(BLOB_REFS)?;
+            tx.open_multimap_table(PINS)?;
+            tx.open_table(ENTITIES)?;
+            tx.open_table(ENTITY_SIGNATURES)?;
+            tx.open_table(ENTITY_IROH_ENTRIES)?;
+            tx.open_table(NAMESPACE_SECRETS)?;
+            tx.open_table(SUBSPACE_SECRETS)?;
+        }
+        tx.commit()?;
+        Ok(Self { db: Arc::new(db) })
+    }
+
+    /// Get the database that the store is kept in, which can be used to keep other data in the same
+    /// file.
+    pub fn database(&self) -> &Arc<Database> {
+        &self.db
+    }
+
+    /// Run a read transaction on a blocking thread.
+    async fn read<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&ReadTransaction) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || f(&db.begin_read()?))
+            .await
+            .map_err(|_| anyhow::format_err!("Error executing database operation"))?
+    }
+
+    /// Run a write transaction on a blocking thread, committing it if `f` succeeds.
+    async fn write<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&WriteTransaction) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || {
+            let tx = db.begin_write()?;
+            let value = f(&tx)?;
+            tx.commit()?;
+            Ok(value)
+        })
+        .await
+        .map_err(|_| anyhow::format_err!("Error executing database operation"))?
+    }
+}
+
+impl LeafStore for LeafRedbStore {
+    fn key_resolvers(&self) -> Box<dyn Iterator<Item = &dyn super::KeyResolverImpl<Digest>> + '_> {
+        Box::new([].into_iter())
+    }
+
+    fn encryption_algorithms(
+        &self,
+    ) -> Box<dyn Iterator<Item = &dyn super::EncryptionAlgorithmImpl<Digest>> + '_> {
+        Box::new([].into_iter())
+    }
+
+    async fn store_blob(
+        &self,
+        data: &[u8],
+        link: &ExactLink,
+        entity_snapshot_id: Digest,
+    ) -> anyhow::Result<Digest> {
+        let data = data.to_vec();
+        let pin_key = PinKey::to_bytes(link, entity_snapshot_id);
+        self.write(move |tx| {
+            let digest = Digest::new(&data);
+            let already_pinned = tx
+                .open_multimap_table(PINS)?
+                .insert(&pin_key[..], digest.as_bytes())?;
+            if !already_pinned {
+                add_blob_ref(tx, &data)?;
+            }
+            Ok(digest)
+        })
+        .await
+    }
+
+    async fn del_blobs(
+        &self,
+        link: &ExactLink,
+        entity_snapshot_id: Digest,
+    ) -> anyhow::Result<usize> {
+        let pin_key = PinKey::to_bytes(link, entity_snapshot_id);
+        self.write(move |tx| {
+            let pinned = tx
+                .open_multimap_table(PINS)?
+                .remove_all(&pin_key[..])?
+                .map(|x| Ok(Digest::from_bytes(x?.value())))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            for &digest in &pinned {
+                del_blob_ref(tx, digest)?;
+            }
+            Ok(pinned.len())
+        })
+        .await
+    }
+
+    async fn get_blob(&self, digest: Digest) -> anyhow::Result<Vec<u8>> {
+        self.read(move |tx| {
+            let Some(blob) = tx.open_table(BLOBS)?.get(digest.as_bytes())? else {
+                anyhow::bail!("Blob not found: {digest}");
+            };
+            Ok(blob.value().to_vec())
+        })
+        .await
+    }
+
+    async fn store_entity(&self, link: &ExactLink, data: Vec<u8>) -> anyhow::Result<Digest> {
+        let link = link.clone();
+        self.write(move |tx| {
+            check_writable(tx, &link)?;
+            let digest = add_blob_ref(tx, &data)?;
+            let key = entity_key(link.namespace, link.subspace, &link.path.0);
+            del_authorship(tx, &key)?;
+            let old = tx
+                .open_table(ENTITIES)?
+                .insert(&key[..], digest.as_bytes())?
+                .map(|x| Digest::from_bytes(x.value()));
+            if let Some(old) = old {
+                del_blob_ref(tx, old)?;
+            }
+            Ok(digest)
+        })
+        .await
+    }
+
+    async fn del_entity(&self, link: &ExactLink) -> anyhow::Result<()> {
+        let link = link.clone();
+        self.write(move |tx| {
+            check_writable(tx, &link)?;
+            let key = entity_key(link.namespace, link.subspace, &link.path.0);
+            del_authorship(tx, &key)?;
+            let old = tx
+                .open_table(ENTITIES)?
+                .remove(&key[..])?
+                .map(|x| Digest::from_bytes(x.value()));
+            if let Some(old) = old {
+                del_blob_ref(tx, old)?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_entity(&self, link: &ExactLink) -> anyhow::Result<Option<Digest>> {
+        let key = entity_key(link.namespace, link.subspace, &link.path.0);
+        self.read(move |tx| {
+            Ok(tx
+                .open_table(ENTITIES)?
+                .get(&key[..])?
+                .map(|x| Digest::from_bytes(x.value())))
+        })
+        .await
+    }
+
+    async fn store_signed_entity(
+        &self,
+        link: &ExactLink,
+        data: Vec<u8>,
+        authorship: EntityAuthorship,
+    ) -> anyhow::Result<Digest> {
+        authorship.verify(link, Digest::new(&data))?;
+        let link = link.clone();
+        self.write(move |tx| {
+            check_namespace_writable(tx, &link)?;
+            let digest = add_blob_ref(tx, &data)?;
+            let key = entity_key(link.namespace, link.subspace, &link.path.0);
+            del_authorship(tx, &key)?;
+            match &authorship {
+                EntityAuthorship::Signature(signature) => {
+                    tx.open_table(ENTITY_SIGNATURES)?
+                        .insert(&key[..], signature.0)?;
+                }
+                EntityAuthorship::IrohEntry(entry) => {
+                    tx.open_table(ENTITY_IROH_ENTRIES)?
+                        .insert(&key[..], &entry[..])?;
+                }
+            }
+            let old = tx
+                .open_table(ENTITIES)?
+                .insert(&key[..], digest.as_bytes())?
+                .map(|x| Digest::from_bytes(x.value()));
+            if let Some(old) = old {
+                del_blob_ref(tx, old)?;
+            }
+            Ok(digest)
+        })
+        .await
+    }
+
+    async fn get_entity_signature(
+        &self,
+        link: &ExactLink,
+    ) -> anyhow::Result<Option<EntityAuthorship>> {
+        let link = link.clone();
+        self.read(move |tx| {
+            let key = entity_key(link.namespace, link.subspace, &link.path.0);
+            let Some(digest) = tx.open_table(ENTITIES)?.get(&key[..])? else {
+                return Ok(None);
+            };
+            let digest = Digest::from_bytes(digest.value());
+            if let Some(signature) = tx.open_table(ENTITY_SIGNATURES)?.get(&key[..])? {
+                let signature = EntitySignature(signature.value());
+                return Ok(Some(EntityAuthorship::Signature(signature)));
+            }
+            if let Some(entry) = tx.open_table(ENTITY_IROH_ENTRIES)?.get(&key[..])? {
+                return Ok(Some(EntityAuthorship::IrohEntry(entry.value().to_vec())));
+            }
+            Ok(tx
+                .open_table(SUBSPACE_SECRETS)?
+                .get(link.subspace)?
+                .map(|secret| {
+                    EntityAuthorship::Signature(EntitySignature::sign(
+                        &secret.value(),
+                        &link,
+                        digest,
+                    ))
+                }))
+        })
+        .await
+    }
+
+    async fn list(
+        &self,
+        link: ExactLink,
+        depth: Option<u32>,
+        after: Option<EntityPath>,
+        limit: Option<u64>,
+    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<ExactLink>>> {
+        let prefix = entity_key(link.namespace, link.subspace, &link.path.0);
+        let max_len = depth.map(|depth| link.path.0.len() + depth as usize);
+
+        // Entities are listed in the order of their keys, and all of the keys that start with the
+        // key of `link` come right after it.
+        let after_key = after.map(|after| entity_key(link.namespace, link.subspace, &after.0));
+        let start = match after_key {
+            Some(after_key) if after_key > prefix => Bound::Excluded(after_key),
+            _ => Bound::Included(prefix.clone()),
+        };
+
+        // The entities are read in batches, each in its own transaction, so that we don't hold
+        // a large listing in memory, or keep a transaction open while the stream is consumed.
+        let store = self.clone();
+        let batches = futures::stream::try_unfold(
+            (Some(start), limit.unwrap_or(u64::MAX)),
+            move |(start, remaining)| {
+                let store = store.clone();
+                let link = link.clone();
+                let prefix = prefix.clone();
+                async move {
+                    let Some(start) = start.filter(|_| remaining > 0) else {
+                        return Ok(None);
+                    };
+                    let size = remaining.min(LIST_BATCH_SIZE as u64) as usize;
+                    let (links, next) = store
+                        .read(move |tx| list_batch(tx, &link, &prefix, max_len, start, size))
+                        .await?;
+                    let remaining = remaining - links.len() as u64;
+                    anyhow::Ok(Some((links, (next, remaining))))
+                }
+            },
+        );
+        Ok(batches
+            .map_ok(|links| futures::stream::iter(links.into_iter().map(Ok)))
+            .try_flatten())
+    }
+
+    async fn create_subspace(&self) -> anyhow::Result<SubspaceId> {
+        self.import_subspace_secret(SecretKey::generate().to_bytes())
+            .await
+    }
+
+    async fn import_subspace_secret(
+        &self,
+        subspace_secret: SubspaceSecretKey,
+    ) -> anyhow::Result<SubspaceId> {
+        let id = *SecretKey::from_bytes(&subspace_secret).public().as_bytes();
+        self.write(move |tx| {
+            tx.open_table(SUBSPACE_SECRETS)?
+                .insert(id, subspace_secret)?;
+            Ok(id)
+        })
+        .await
+    }
+
+    async fn get_subspace_secret(
+        &self,
+        subspace: SubspaceId,
+    ) -> anyhow::Result<Option<SubspaceSecretKey>> {
+        self.read(move |tx| {
+            Ok(tx
+                .open_table(SUBSPACE_SECRETS)?
+                .get(subspace)?
+                .map(|x| x.value()))
+        })
+        .await
+    }
+
+    async fn create_namespace(&self) -> anyhow::Result<NamespaceId> {
+        self.import_namespace_secret(SecretKey::generate().to_bytes())
+            .await
+    }
+
+    async fn import_namespace_secret(
+        &self,
+        namespace_secret: [u8; 32],
+    ) -> anyhow::Result<NamespaceId> {
+        let id = *SecretKey::from_bytes(&namespace_secret).public().as_bytes();
+        self.write(move |tx| {
+            tx.open_table(NAMESPACE_SECRETS)?
+                .insert(id, namespace_secret)?;
+            Ok(id)
+        })
+        .await
+    }
+
+    async fn get_namespace_secret(
+        &self,
+        namespace: NamespaceId,
+    ) -> anyhow::Result<Option<NamespaceSecretKey>> {
+        self.read(move |tx| {
+            Ok(tx
+                .open_table(NAMESPACE_SECRETS)?
+                .get(namespace)?
+                .map(|x| x.value()))
+        })
+        .await
+    }
+
+    async fn list_subspaces(
+        &self,
+    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<SubspaceId>>> {
+        let subspaces = self
+            .read(|tx| {
+                tx.open_table(SUBSPACE_SECRETS)?
+                    .iter()?
+                    .map(|x| Ok(x?.0.value()))
+                    .collect::<anyhow::Result<Vec<_>>>()
+            })
+            .await?;
+        Ok(futures::stream::iter(subspaces.into_iter().map(Ok)))
+    }
+
+    async fn list_namespaces(
+        &self,
+    ) -> anyhow::Result<
+        impl futures::Stream<Item = anyhow::Result<(NamespaceId, NamespaceCapability)>>,
+    > {
+        // This store can't sync, so we only ever have the namespaces that we have secrets for.
+        let namespaces = self
+            .read(|tx| {
+                tx.open_table(NAMESPACE_SECRETS)?
+                    .iter()?
+                    .map(|x| Ok((x?.0.value(), NamespaceCapability::Write)))
+                    .collect::<anyhow::Result<Vec<_>>>()
+            })
+            .await?;
+        Ok(futures::stream::iter(namespaces.into_iter().map(Ok)))
+    }
+
+    async fn list_namespace_subspaces(
+        &self,
+        namespace: NamespaceId,
+    ) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<SubspaceId>>> {
+        let subspaces = self
+            .read(move |tx| {
+                let mut subspaces = BTreeSet::new();
+                let table = tx.open_table(ENTITIES)?;
+                for entry in table.range::<&[u8]>(&namespace[..]..)? {
+                    let (key, _) = entry?;
+                    let key = key.value();
+                    if !key.starts_with(&namespace) {
+                        break;
+                    }
+                    subspaces.insert(<SubspaceId>::try_from(&key[32..64])?);
+                }
+                Ok(subspaces)
+            })
+            .await?;
+        Ok(futures::stream::iter(subspaces.into_iter().map(Ok)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn list_reads_in_batches() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = LeafRedbStore::open(dir.path().join("leaf.redb"))?;
+        let namespace = store.create_namespace().await?;
+        let subspace = store.create_subspace().await?;
+        let root: ExactLink = (namespace, subspace, ["list"]).into();
+
+        // More entities than fit in two batches, each with an entity below it that is skipped
+        // when listing with a depth.
+        let count = LIST_BATCH_SIZE as u64 * 2 + 10;
+        for i in 0..count {
+            let link: ExactLink =
+                (namespace, subspace, [PathSegment::from("list"), i.into()]).into();
+            store.store_entity(&link, b"entity".to_vec()).await?;
+            let mut child = link;
+            child.path.0.push("child".into());
+            store.store_entity(&child, b"child".to_vec()).await?;
+        }
+
+        let list = |depth, after, limit| {
+            let store = store.clone();
+            let root = root.clone();
+            async move {
+                store
+                    .list(root, depth, after, limit)
+                    .await?
+                    .try_collect::<Vec<_>>()
+                    .await
+            }
+        };
+        assert_eq!(list(None, None, None).await?.len() as u64, count * 2);
+        let children = list(Some(1), None, None).await?;
+        assert_eq!(children.len() as u64, count);
+        assert!(children.iter().all(|x| x.path.0.len() == 2));
+
+        let limited = list(Some(1), None, Some(LIST_BATCH_SIZE as u64 + 1)).await?;
+        assert_eq!(limited[..], children[..LIST_BATCH_SIZE + 1]);
+        let after = children[LIST_BATCH_SIZE].path.clone();
+        let rest = list(Some(1), Some(after), None).await?;
+        assert_eq!(rest[..], children[LIST_BATCH_SIZE + 1..]);
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+//! Soft deletion of entities.
+//!
+//! When the trash is enabled with [`Leaf::with_trash()`][crate::Leaf::with_trash], deleting an
+//! entity moves its last snapshot into the trash of its subspace instead, where it can be
+//! restored to its original link until it is purged. Entities are purged from the trash once they
+//! have been there for longer than the retention of the trash, by
+//! [`Leaf::purge_expired_trash()`][crate::Leaf::purge_expired_trash].
+//!
+//! The trash is kept in the store, under the reserved [`TRASH_PREFIX_STR`] path segment at the
+//! root of each subspace. A trashed entity's path is the prefix, the time that it was deleted, and
+//! then its original path. The blobs of trashed entities stay pinned, so they count towards the
+//! quota of their subspace and are synced like any other entity, but they are left out when
+//! listing entities with [`Leaf::list()`][crate::Leaf::list]. Entities can't be saved to the
+//! trash, and trashed entities can only be removed by restoring or purging them.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::{
+    store::LeafStore,
+    types::{Entity, EntityPath, ExactLink, NamespaceId, PathSegment, SubspaceId},
+    Digest, LeafConfig,
+};
+
+/// The first path segment of every entity in the trash.
+pub const TRASH_PREFIX_STR: &str = "_leaf_trash_";
+
+/// An entity in the trash.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TrashEntry {
+    /// The link of the entity in the trash.
+    pub link: ExactLink,
+    /// The link that the entity was deleted from, which it is restored to.
+    pub original: ExactLink,
+    /// The time that the entity was deleted, in milliseconds since the Unix epoch.
+    pub deleted_at: u64,
+}
+
+impl TrashEntry {
+    fn new(original: ExactLink, deleted_at: u64) -> Self {
+        let mut path = vec![
+            PathSegment::String(TRASH_PREFIX_STR.into()),
+            PathSegment::Uint(deleted_at),
+        ];
+        path.extend(original.path.0.iter().cloned());
+        Self {
+            link: ExactLink {
+                namespace: original.namespace,
+                subspace: original.subspace,
+                path: EntityPath(path),
+            },
+            original,
+            deleted_at,
+        }
+    }
+
+    /// Get the trash entry of the entity at `link`, or [`None`] if `link` isn't in the trash.
+    pub fn from_link(link: &ExactLink) -> Option<Self> {
+        let [PathSegment::String(prefix), PathSegment::Uint(deleted_at), path @ ..] =
+            &link.path.0[..]
+        else {
+            return None;
+        };
+        if prefix != TRASH_PREFIX_STR {
+            return None;
+        }
+        Some(Self {
+            link: link.clone(),
+            original: ExactLink {
+                namespace: link.namespace,
+                subspace: link.subspace,
+                path: EntityPath(path.to_vec()),
+            },
+            deleted_at: *deleted_at,
+        })
+    }
+}
+
+/// Whether `path` is in the trash of its subspace.
+pub fn is_trashed(path: &EntityPath) -> bool {
+    matches!(path.0.first(), Some(PathSegment::String(x)) if x == TRASH_PREFIX_STR)
+}
+
+/// Return an error if `link` is in the trash.
+///
+/// Entities in the trash may only be written by this module, so entities can't be saved to or
+/// deleted from the trash like other entities.
+pub(crate) fn check_not_trashed(link: &ExactLink) -> Result<()> {
+    if is_trashed(&link.path) {
+        anyhow::bail!(
+            "Can't write to {link:?}, because the {TRASH_PREFIX_STR:?} path segment is reserved \
+            for the trash."
+        );
+    }
+    Ok(())
+}
+
+/// Get the link that the trash of a subspace is kept under.
+pub(crate) fn trash_root(namespace: NamespaceId, subspace: SubspaceId) -> ExactLink {
+    ExactLink {
+        namespace,
+        subspace,
+        path: EntityPath(vec![PathSegment::String(TRASH_PREFIX_STR.into())]),
+    }
+}
+
+/// Get the current time in milliseconds since the Unix epoch.
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Move the snapshot of the entity at `link` into the trash.
+pub(crate) async fn move_to_trash<S: LeafStore>(
+    store: &S,
+    config: &LeafConfig,
+    link: &ExactLink,
+    digest: Digest,
+) -> Result<TrashEntry> {
+    let mut entry = TrashEntry::new(link.clone(), now_millis());
+    // Don't replace an entity that was deleted from the same link in the same millisecond.
+    while store.get_entity(&entry.link).await?.is_some() {
+        entry = TrashEntry::new(link.clone(), entry.deleted_at + 1);
+    }
+    move_entity(store, config, link, &entry.link, digest).await?;
+    Ok(entry)
+}
+
+/// Move the snapshot with the given digest from one link to another, along with the pins of its
+/// blobs, its size in the quota of its subspace and its unique component values.
+pub(crate) async fn move_entity<S: LeafStore>(
+    store: &S,
+    config: &LeafConfig,
+    from: &ExactLink,
+    to: &ExactLink,
+    digest: Digest,
+) -> Result<()> {
+    let bytes = store.get_blob(digest).await?;
+    let entity = Entity::deserialize(&mut &bytes[..])?;
+
+    // Pin the blobs to the new link before unpinning them from the old one, so that they are
+    // never left unpinned.
+    for entry in &entity.components {
+        let data = store.get_blob(entry.component_id).await?;
+        store.store_blob(&data, to, digest).await?;
+    }
+    store.store_entity(to, bytes).await?;
+    store.del_blobs(from, digest).await?;
+    store.del_entity(from).await?;
+
+    if let Some(quotas) = &config.quotas {
+        quotas.move_entity_size(from, to).await?;
+    }
+    config.unique.remove_entity(from);
+    config.unique.index_entity(to, &entity.components);
+    Ok(())
+}
@@ -0,0 +1,287 @@
+//! Unique-value constraints for components.
+//!
+//! A component schema can be declared unique within a namespace or a subspace, which prevents two
+//! different entities in that scope from holding a component with the same value. Because
+//! component IDs are the digest of the component data, two components have the same value exactly
+//! when they have the same [`ComponentEntry`].
+//!
+//! Uniqueness is only enforced locally, by the [`Leaf`][crate::Leaf] that saves the entity. Like
+//! the [`SearchIndex`][crate::search::SearchIndex], the entities holding each unique value are
+//! indexed in memory the first time that a namespace is checked, and the index is kept up to date
+//! as entities are saved, deleted or restored through that `Leaf`. Entities synced from other
+//! nodes, or written by another process sharing the store, aren't checked, and are only picked up
+//! when the namespace is re-indexed with [`Leaf::reindex()`][crate::Leaf::reindex]. Two nodes can
+//! still give the same value to different entities and then sync them to each other.
+
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Result;
+use borsh::BorshDeserialize;
+use futures::{lock::Mutex, pin_mut, TryStreamExt};
+
+use crate::{
+    store::LeafStore,
+    trash,
+    types::{ComponentEntry, Entity, ExactLink, NamespaceId},
+    Digest,
+};
+
+/// The scope that a component value must be unique in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UniqueScope {
+    /// No two entities in the same namespace may share the value, regardless of their subspace.
+    Namespace,
+    /// No two entities in the same subspace of a namespace may share the value.
+    Subspace,
+}
+
+impl std::fmt::Display for UniqueScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UniqueScope::Namespace => write!(f, "namespace"),
+            UniqueScope::Subspace => write!(f, "subspace"),
+        }
+    }
+}
+
+/// A declaration that components of a schema must be unique within a scope.
+///
+/// Can be parsed from a string formatted as `<scope>:<schema_id>`, for example
+/// `subspace:znqyvzghvyafsj6n5wyhgl7mspy3swlofxeh2nattvtql5amtcda`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UniqueConstraint {
+    pub schema: Digest,
+    pub scope: UniqueScope,
+}
+
+impl FromStr for UniqueConstraint {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((scope, schema)) = s.split_once(':') else {
+            anyhow::bail!("Expected unique constraint in the format `<scope>:<schema_id>`");
+        };
+        let scope = match scope {
+            "namespace" => UniqueScope::Namespace,
+            "subspace" => UniqueScope::Subspace,
+            other => anyhow::bail!(
+                "Invalid unique constraint scope `{other}`, expected `namespace` or `subspace`"
+            ),
+        };
+        Ok(Self {
+            schema: Digest::from_str(schema)?,
+            scope,
+        })
+    }
+}
+
+/// The set of [`UniqueConstraint`]s configured on a [`Leaf`][crate::Leaf].
+///
+/// Cloning the constraints is cheap and the clones share the same lock and index.
+#[derive(Debug, Clone, Default)]
+pub struct UniqueConstraints {
+    scopes: HashMap<Digest, UniqueScope>,
+    /// Held while checking and writing unique components so that two saves can't both claim the
+    /// same value. This only orders saves made through this process.
+    lock: Arc<Mutex<()>>,
+    index: Arc<RwLock<UniqueIndex>>,
+}
+
+/// An in-memory index of the entities that hold unique component values.
+#[derive(Debug, Default)]
+struct UniqueIndex {
+    /// The namespaces that have been indexed.
+    namespaces: HashSet<NamespaceId>,
+    /// The entities that hold each unique value in a namespace.
+    holders: HashMap<(NamespaceId, ComponentEntry), HashSet<ExactLink>>,
+    /// The unique values that each entity holds, so that we can clean up its values when it
+    /// changes.
+    held: HashMap<ExactLink, Vec<ComponentEntry>>,
+}
+
+impl UniqueIndex {
+    fn insert(&mut self, link: ExactLink, values: Vec<ComponentEntry>) {
+        if values.is_empty() {
+            return;
+        }
+        for value in &values {
+            self.holders
+                .entry((link.namespace, *value))
+                .or_default()
+                .insert(link.clone());
+        }
+        self.held.insert(link, values);
+    }
+
+    fn remove(&mut self, link: &ExactLink) {
+        for value in self.held.remove(link).unwrap_or_default() {
+            let key = (link.namespace, value);
+            if let Some(holders) = self.holders.get_mut(&key) {
+                holders.remove(link);
+                if holders.is_empty() {
+                    self.holders.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+impl UniqueConstraints {
+    /// Add a constraint, replacing any previous constraint on the same schema.
+    pub fn insert(&mut self, constraint: UniqueConstraint) {
+        self.scopes.insert(constraint.schema, constraint.scope);
+        // The indexed values depend on which schemas are unique.
+        self.index = Default::default();
+    }
+
+    /// Get the scope that components of the given schema must be unique in, if any.
+    pub fn scope(&self, schema: Digest) -> Option<UniqueScope> {
+        self.scopes.get(&schema).copied()
+    }
+
+    /// Iterate over the configured constraints.
+    pub fn iter(&self) -> impl Iterator<Item = UniqueConstraint> + '_ {
+        self.scopes
+            .iter()
+            .map(|(&schema, &scope)| UniqueConstraint { schema, scope })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scopes.is_empty()
+    }
+
+    pub(crate) fn lock(&self) -> &Mutex<()> {
+        &self.lock
+    }
+
+    /// Get the components of an entity that are subject to a constraint.
+    fn unique_values(&self, components: &[ComponentEntry]) -> Vec<ComponentEntry> {
+        components
+            .iter()
+            .filter(|x| x.schema_id.and_then(|x| self.scope(x)).is_some())
+            .copied()
+            .collect()
+    }
+
+    /// Whether the given namespace has been indexed yet.
+    pub fn is_indexed(&self, namespace: NamespaceId) -> bool {
+        self.index.read().unwrap().namespaces.contains(&namespace)
+    }
+
+    /// Replace the index for a namespace by reading every entity in it from the store.
+    pub(crate) async fn index_namespace<S: LeafStore>(
+        &self,
+        store: &S,
+        namespace: NamespaceId,
+    ) -> Result<()> {
+        let mut entities = Vec::new();
+        let subspaces = store.list_namespace_subspaces(namespace).await?;
+        pin_mut!(subspaces);
+        while let Some(subspace) = subspaces.try_next().await? {
+            let stream = store
+                .list((namespace, subspace, ()).into(), None, None, None)
+                .await?;
+            pin_mut!(stream);
+            while let Some(link) = stream.try_next().await? {
+                // Entities in the trash don't hold on to their unique values.
+                if trash::is_trashed(&link.path) {
+                    continue;
+                }
+                let Some(digest) = store.get_entity(&link).await? else {
+                    continue;
+                };
+                let bytes = store.get_blob(digest).await?;
+                let entity = Entity::deserialize(&mut &bytes[..])?;
+                entities.push((link, self.unique_values(&entity.components)));
+            }
+        }
+
+        let mut index = self.index.write().unwrap();
+        let stale = index
+            .held
+            .keys()
+            .filter(|x| x.namespace == namespace)
+            .cloned()
+            .collect::<Vec<_>>();
+        for link in stale {
+            index.remove(&link);
+        }
+        for (link, values) in entities {
+            index.insert(link, values);
+        }
+        index.namespaces.insert(namespace);
+        Ok(())
+    }
+
+    /// Update the unique values held by an entity from its components.
+    ///
+    /// This does nothing if the entity's namespace hasn't been indexed yet, and entities in the
+    /// trash are removed from the index instead.
+    pub(crate) fn index_entity(&self, link: &ExactLink, components: &[ComponentEntry]) {
+        let mut index = self.index.write().unwrap();
+        if index.namespaces.contains(&link.namespace) {
+            index.remove(link);
+            if !trash::is_trashed(&link.path) {
+                index.insert(link.clone(), self.unique_values(components));
+            }
+        }
+    }
+
+    /// Remove the unique values held by an entity.
+    pub(crate) fn remove_entity(&self, link: &ExactLink) {
+        self.index.write().unwrap().remove(link);
+    }
+
+    /// Find an entity other than `link`, in the given scope, that has the component `entry`.
+    ///
+    /// The namespace of `link` is indexed first if it hasn't been yet.
+    pub(crate) async fn find_holder<S: LeafStore>(
+        &self,
+        store: &S,
+        link: &ExactLink,
+        scope: UniqueScope,
+        entry: ComponentEntry,
+    ) -> Result<Option<ExactLink>> {
+        if !self.is_indexed(link.namespace) {
+            self.index_namespace(store, link.namespace).await?;
+        }
+        let index = self.index.read().unwrap();
+        let Some(holders) = index.holders.get(&(link.namespace, entry)) else {
+            return Ok(None);
+        };
+        Ok(holders
+            .iter()
+            .filter(|x| *x != link)
+            .filter(|x| scope == UniqueScope::Namespace || x.subspace == link.subspace)
+            .min()
+            .cloned())
+    }
+}
+
+/// Error returned when saving an entity would give it the same value for a unique component as
+/// another entity.
+#[derive(Debug, Clone)]
+pub struct UniqueConstraintViolation {
+    /// The schema of the unique component.
+    pub schema: Digest,
+    /// The scope the component must be unique in.
+    pub scope: UniqueScope,
+    /// The entity that already has a component with the same value.
+    pub existing: ExactLink,
+}
+
+impl std::fmt::Display for UniqueConstraintViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Component with schema {} must be unique in its {}, but the same value is \
+            already held by the entity at: {:?}",
+            self.schema, self.scope, self.existing
+        )
+    }
+}
+
+impl std::error::Error for UniqueConstraintViolation {}
@@ -0,0 +1,117 @@
+//! Typed views over multiple components of an entity.
+//!
+//! An [`EntityView`] is a struct whose fields are components, which can be loaded from and applied
+//! to a [`LoadedEntity`] all at once, instead of getting and setting each component separately.
+//! It is usually derived with the [`EntityView`][leaf_protocol_macros::EntityView] derive macro.
+
+use std::{collections::HashMap, future::Future};
+
+use anyhow::Result;
+
+use crate::{
+    store::LeafStore,
+    types::{ComponentKind, Digest},
+    Component, LoadedEntity,
+};
+
+/// A typed view over several components of an entity.
+///
+/// Implementers should usually derive this using the
+/// [`EntityView`][leaf_protocol_macros::EntityView] macro.
+///
+/// A component type can only be used by one field of a derived view, so neither of these compile:
+///
+/// ```compile_fail
+/// # use leaf_protocol::prelude::*;
+/// #[derive(EntityView)]
+/// struct Names {
+///     name: Name,
+///     other_names: Vec<Name>,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use leaf_protocol::prelude::*;
+/// type Alias = Name;
+///
+/// #[derive(EntityView)]
+/// struct Names {
+///     name: Name,
+///     alias: Option<Alias>,
+/// }
+/// ```
+pub trait EntityView: Sized {
+    /// The schemas of the components that make up the view.
+    fn schemas() -> Vec<Digest>;
+
+    /// Build the view out of the component data loaded from an entity.
+    fn from_components(components: &mut ComponentMap) -> Result<Self>;
+
+    /// Replace the components that make up the view on the entity with the values in the view.
+    ///
+    /// The change will not be persisted until [`LoadedEntity::save()`] is called.
+    fn apply<S: LeafStore>(&self, entity: &mut LoadedEntity<S>) -> Result<()>;
+
+    /// Load the view from an entity, fetching all of the components it needs in one pass.
+    fn load<S: LeafStore>(entity: &LoadedEntity<S>) -> impl Future<Output = Result<Self>> + Send;
+}
+
+/// The data of the components on an entity, grouped by schema.
+#[derive(Debug, Clone, Default)]
+pub struct ComponentMap(pub HashMap<Digest, Vec<Vec<u8>>>);
+
+impl ComponentMap {
+    /// Get the data of the components with the given schema.
+    pub fn get(&self, schema: Digest) -> &[Vec<u8>] {
+        self.0.get(&schema).map(|x| &x[..]).unwrap_or(&[])
+    }
+
+    /// Remove the components of type `C` from the map and deserialize the first one, if any.
+    pub fn take_one<C: Component>(&mut self) -> Result<Option<C>> {
+        let Some(data) = self
+            .0
+            .remove(&C::schema_id())
+            .and_then(|x| x.into_iter().next())
+        else {
+            return Ok(None);
+        };
+        Ok(Some(C::deserialize(&mut &data[..])?))
+    }
+
+    /// Like [`take_one()`][Self::take_one], but returns an error if there is no component of type
+    /// `C`.
+    pub fn take_required<C: Component>(&mut self) -> Result<C> {
+        self.take_one()?.ok_or_else(|| {
+            anyhow::format_err!(
+                "Entity is missing required component with schema: {}",
+                C::schema_id()
+            )
+        })
+    }
+
+    /// Remove the components of type `C` from the map and deserialize all of them.
+    pub fn take_all<C: Component>(&mut self) -> Result<Vec<C>> {
+        self.0
+            .remove(&C::schema_id())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|data| Ok(C::deserialize(&mut &data[..])?))
+            .collect()
+    }
+}
+
+impl<S: LeafStore> LoadedEntity<S> {
+    /// Replace all components of type `C` with the given components.
+    ///
+    /// The change will not be persisted until [`save()`][Self::save] is called.
+    pub fn replace_components<'a, C: Component + 'a>(
+        &mut self,
+        components: impl IntoIterator<Item = &'a C>,
+    ) -> Result<()> {
+        self.del_components::<C>();
+        for component in components {
+            self.add_component_data(ComponentKind::Unencrypted(component.make_data()?));
+        }
+        Ok(())
+    }
+}
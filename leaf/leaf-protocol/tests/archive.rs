@@ -0,0 +1,65 @@
+use leaf_protocol::{
+    archive::{export_archive, import_archive},
+    iroh::{
+        self,
+        docs::{Author, NamespaceSecret, Record, SignedEntry},
+        node::Node,
+    },
+    prelude::*,
+    store::EntityAuthorship,
+};
+
+#[tokio::test]
+async fn iroh_archive_keeps_signed_entries() -> anyhow::Result<()> {
+    let src_node = Node::memory().spawn().await?;
+    let src = Leaf::new(LeafIrohStore::new(src_node.client().clone()));
+    let namespace = src.create_namespace().await?;
+    let subspace = src.create_subspace().await?;
+    let mut entity = src
+        .entity((namespace, subspace, ["own"]))
+        .await?
+        .get_or_init();
+    entity.add_component(Name("own".into()))?;
+    entity.save().await?;
+
+    // Store an entity from a subspace that neither node has the secret of, as if it had been
+    // synced from its author.
+    let author_secret = [3; 32];
+    let author = *iroh::net::key::SecretKey::from_bytes(&author_secret)
+        .public()
+        .as_bytes();
+    let foreign: ExactLink = (namespace, author, ["foreign"]).into();
+    let own: ExactLink = (namespace, subspace, ["own"]).into();
+    let own = src.store.get_entity(&own).await?.unwrap();
+    let data = src.store.get_blob(own).await?;
+    let namespace_secret = src.get_namespace_secret(namespace).await?.unwrap();
+    let entry = SignedEntry::from_parts(
+        &NamespaceSecret::from_bytes(&namespace_secret),
+        &Author::from_bytes(&author_secret),
+        LeafIrohStore::get_entity_key(author, &foreign.path.0),
+        Record::new_current(Digest::new(&data).0, data.len() as u64),
+    );
+    let authorship = EntityAuthorship::IrohEntry(postcard::to_allocvec(&entry)?);
+    src.store
+        .store_signed_entity(&foreign, data.clone(), authorship.clone())
+        .await?;
+
+    let mut archive = Vec::new();
+    let exported = export_archive(&src, &mut archive, |_| ()).await?;
+    assert_eq!((exported.entities, exported.skipped), (2, 0));
+
+    let dst_node = Node::memory().spawn().await?;
+    let dst = Leaf::new(LeafIrohStore::new(dst_node.client().clone()));
+    import_archive(&dst, &archive[..], |_| ()).await?;
+    assert_eq!(
+        dst.store.get_entity(&foreign).await?,
+        Some(Digest::new(&data))
+    );
+    assert_eq!(
+        dst.store.get_entity_signature(&foreign).await?,
+        Some(authorship)
+    );
+    let own = dst.entity((namespace, subspace, ["own"])).await?.entity()?;
+    assert_eq!(own.get_component::<Name>().await?.unwrap().0, "own");
+    Ok(())
+}
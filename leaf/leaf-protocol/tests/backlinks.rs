@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use leaf_protocol::{
+    backlinks::{Backlink, BacklinkIndex},
+    prelude::*,
+    types::{KeyResolverKind, Link, NamespaceId, SubspaceId},
+    Component,
+};
+
+fn reply_to(link: &ExactLink) -> ReplyTo {
+    ReplyTo(Link {
+        namespace: KeyResolverKind::Inline(link.namespace),
+        subspace: KeyResolverKind::Inline(link.subspace),
+        path: link.path.0.clone(),
+        snapshot: None,
+    })
+}
+
+/// Create a leaf with a memory store and a backlink index persisted in `db`.
+fn leaf(db: &Arc<redb::Database>) -> anyhow::Result<Leaf<LeafMemoryStore>> {
+    let index = BacklinkIndex::default().with_database(db.clone())?;
+    Ok(Leaf::new(LeafMemoryStore::new()).with_backlink_index(index))
+}
+
+async fn post(
+    leaf: &Leaf<LeafMemoryStore>,
+) -> anyhow::Result<(NamespaceId, SubspaceId, ExactLink, ExactLink)> {
+    let namespace = leaf.create_namespace().await?;
+    let subspace = leaf.create_subspace().await?;
+    let post: ExactLink = (namespace, subspace, ["post"]).into();
+    let reply: ExactLink = (namespace, subspace, ["reply"]).into();
+    leaf.entity(post.clone())
+        .await?
+        .get_or_init()
+        .save()
+        .await?;
+    let mut entity = leaf.entity(reply.clone()).await?.get_or_init();
+    entity.add_component(reply_to(&post))?;
+    entity.save().await?;
+    Ok((namespace, subspace, post, reply))
+}
+
+#[tokio::test]
+async fn persisted_index_is_loaded() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let db = Arc::new(redb::Database::create(dir.path().join("index.redb"))?);
+    let first = leaf(&db)?;
+    let (_, _, post, reply) = post(&first).await?;
+    let expected = vec![Backlink {
+        link: reply.clone(),
+        schema: ReplyTo::schema_id(),
+    }];
+    assert_eq!(first.backlinks(post.clone(), None).await?, expected);
+
+    // The entities aren't in the store of the second leaf, so the backlinks can only come from
+    // the database.
+    let second = leaf(&db)?;
+    let index = second.config.backlinks.as_ref().unwrap();
+    assert!(index.is_indexed(post.namespace));
+    assert_eq!(second.backlinks(post.clone(), None).await?, expected);
+
+    // Changes are written to the database too.
+    first.del_entity(reply).await?;
+    assert!(leaf(&db)?.backlinks(post, None).await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn namespaces_indexed_with_other_schemas_are_not_loaded() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let db = Arc::new(redb::Database::create(dir.path().join("index.redb"))?);
+    let first = leaf(&db)?;
+    let (namespace, ..) = post(&first).await?;
+    first.reindex(namespace).await?;
+    let index = BacklinkIndex::default().with_database(db.clone())?;
+    assert!(index.is_indexed(namespace));
+
+    let index = BacklinkIndex::empty()
+        .with_component::<ReplyTo>()
+        .with_database(db)?;
+    assert!(!index.is_indexed(namespace));
+    Ok(())
+}
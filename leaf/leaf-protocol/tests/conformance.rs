@@ -0,0 +1,42 @@
+use leaf_protocol::{
+    iroh::node::Node,
+    prelude::*,
+    store::{cache::CacheLimits, conformance::check_store, DynLeafStore},
+};
+
+#[tokio::test]
+async fn memory_store() -> anyhow::Result<()> {
+    check_store(LeafMemoryStore::new()).await
+}
+
+#[tokio::test]
+async fn redb_store() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    check_store(LeafRedbStore::open(dir.path().join("leaf.redb"))?).await
+}
+
+#[tokio::test]
+async fn cache_store() -> anyhow::Result<()> {
+    check_store(LeafCacheStore::new(
+        LeafMemoryStore::new(),
+        CacheLimits::default(),
+    ))
+    .await
+}
+
+#[tokio::test]
+async fn instrumented_store() -> anyhow::Result<()> {
+    check_store(LeafInstrumentedStore::new(LeafMemoryStore::new())).await
+}
+
+#[tokio::test]
+async fn boxed_store() -> anyhow::Result<()> {
+    let store: Box<dyn DynLeafStore> = Box::new(LeafMemoryStore::new());
+    check_store(store).await
+}
+
+#[tokio::test]
+async fn iroh_store() -> anyhow::Result<()> {
+    let node = Node::memory().spawn().await?;
+    check_store(LeafIrohStore::new(node.client().clone())).await
+}
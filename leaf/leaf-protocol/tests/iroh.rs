@@ -0,0 +1,285 @@
+use std::{future::Future, time::Duration};
+
+use futures::TryStreamExt;
+use leaf_protocol::{
+    iroh::{client::Doc, docs::store::Query, node::Node},
+    prelude::*,
+    store::{GcReport, NamespaceCapability, ShareMode},
+};
+
+/// Get the live entries of a document, with their authors and keys.
+async fn entries(doc: &Doc) -> anyhow::Result<Vec<leaf_protocol::iroh::client::docs::Entry>> {
+    doc.get_many(Query::all()).await?.try_collect().await
+}
+
+/// Poll `f` until it returns [`Some`], failing after a while.
+async fn eventually<T, F: Future<Output = anyhow::Result<Option<T>>>>(
+    mut f: impl FnMut() -> F,
+) -> anyhow::Result<T> {
+    tokio::time::timeout(Duration::from_secs(30), async {
+        loop {
+            if let Some(value) = f().await? {
+                return Ok(value);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await?
+}
+
+/// Wait for an entity to be synced, and get its name.
+async fn synced_name(leaf: &Leaf<LeafIrohStore>, link: &ExactLink) -> anyhow::Result<String> {
+    eventually(|| async {
+        // The entry of the entity may be synced before its blobs are.
+        let Ok(EntityEntry::Entity(entity)) = leaf.entity(link.clone()).await else {
+            return Ok(None);
+        };
+        Ok(entity
+            .get_component::<Name>()
+            .await
+            .ok()
+            .flatten()
+            .map(|x| x.0))
+    })
+    .await
+}
+
+#[tokio::test]
+async fn migrate_keys_resumes_after_an_interruption() -> anyhow::Result<()> {
+    let node = Node::memory().spawn().await?;
+    let leaf = Leaf::new(LeafIrohStore::new(node.client().clone()));
+    let namespace = leaf.create_namespace().await?;
+    let subspace = leaf.create_subspace().await?;
+    // The legacy key of the parent is a prefix of the legacy key of the child, because the
+    // encoded child segment is 256 bytes long.
+    let parent: ExactLink = (namespace, subspace, ["parent"]).into();
+    let mut child = parent.clone();
+    child.path.0.push(PathSegment::String("c".repeat(251)));
+    let other: ExactLink = (namespace, subspace, ["other"]).into();
+    let mut digests = Vec::new();
+    for link in [&parent, &child, &other] {
+        let mut entity = leaf.entity(link.clone()).await?.get_or_init();
+        entity.add_component(Name(format!("{:?}", link.path)))?;
+        entity.save().await?;
+        digests.push(leaf.store.get_entity(link).await?.unwrap());
+    }
+
+    // Move every entry to its legacy key, as if an older version of Leaf had written it.
+    let doc = leaf.store.open(namespace.into()).await?;
+    let current = entries(&doc).await?;
+    for entry in &current {
+        doc.del(entry.author(), entry.key().to_vec()).await?;
+    }
+    let (mut entity_count, mut pin_count) = (0, 0);
+    let parent_key = LeafIrohStore::get_entity_key(subspace, &parent.path.0);
+    for entry in &current {
+        let legacy = if let Ok(pin) = LeafGcPath::from_bytes(entry.key()) {
+            pin_count += 1;
+            pin.to_bytes_with_format(KeyFormat::Legacy)
+        } else {
+            entity_count += 1;
+            IrohDocumentKeyFormat::from_bytes(entry.key())?.to_bytes_with_format(KeyFormat::Legacy)
+        };
+        doc.set_hash(
+            entry.author(),
+            legacy,
+            entry.content_hash(),
+            entry.content_len(),
+        )
+        .await?;
+    }
+    // Interrupt the migration of the parent after it was copied, but before its legacy key was
+    // deleted.
+    let parent_entry = current.iter().find(|x| x.key() == parent_key).unwrap();
+    doc.set_hash(
+        parent_entry.author(),
+        parent_key,
+        parent_entry.content_hash(),
+        parent_entry.content_len(),
+    )
+    .await?;
+
+    let report = leaf.store.migrate_keys(namespace).await?;
+    assert_eq!(
+        report,
+        KeyMigrationReport {
+            entities: entity_count,
+            pins: pin_count,
+            skipped: 0,
+        }
+    );
+    assert_eq!(
+        leaf.store.migrate_keys(namespace).await?,
+        KeyMigrationReport::default()
+    );
+
+    // Every entry is back at its current key, and no legacy keys are left.
+    let mut migrated = entries(&doc).await?;
+    assert!(migrated
+        .iter()
+        .all(|x| KeyFormat::detect(x.key()) == KeyFormat::V1));
+    let mut current = current;
+    current.sort_by(|a, b| a.key().cmp(b.key()));
+    migrated.sort_by(|a, b| a.key().cmp(b.key()));
+    assert_eq!(
+        current
+            .iter()
+            .map(|x| (x.key(), x.content_hash()))
+            .collect::<Vec<_>>(),
+        migrated
+            .iter()
+            .map(|x| (x.key(), x.content_hash()))
+            .collect::<Vec<_>>()
+    );
+
+    for (link, digest) in [&parent, &child, &other].into_iter().zip(digests) {
+        assert_eq!(leaf.store.get_entity(link).await?, Some(digest));
+        let entity = leaf.entity(link.clone()).await?.entity()?;
+        assert_eq!(
+            entity.get_component::<Name>().await?.unwrap().0,
+            format!("{:?}", link.path)
+        );
+    }
+    let listed = leaf
+        .list(parent.clone(), Some(1), None)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(listed, vec![parent, child]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn collect_garbage_removes_leaked_pins() -> anyhow::Result<()> {
+    let node = Node::memory().spawn().await?;
+    let leaf = Leaf::new(LeafIrohStore::new(node.client().clone()));
+    let namespace = leaf.create_namespace().await?;
+    let subspace = leaf.create_subspace().await?;
+    let link: ExactLink = (namespace, subspace, ["entity"]).into();
+    let mut entity = leaf.entity(link.clone()).await?.get_or_init();
+    entity.add_component(Name("first".into()))?;
+    entity.save().await?;
+
+    // A save pins the blobs of its new snapshot, but the entity is overwritten before the save
+    // writes the snapshot, so the pins are never cleaned up. One of the leaked blobs is also
+    // pinned by the current snapshot.
+    let leaked_snapshot = Digest::new(b"never written");
+    let leaked = b"only pinned by the leaked snapshot";
+    leaf.store
+        .store_blob(leaked, &link, leaked_snapshot)
+        .await?;
+    let mut entity = leaf.entity(link.clone()).await?.entity()?;
+    entity.add_component(Name("second".into()))?;
+    entity.save().await?;
+    let live = leaf.store.get_entity(&link).await?.unwrap();
+    let snapshot = leaf.store.get_blob(live).await?;
+    let component = Entity::deserialize(&mut &snapshot[..])?.components[0].component_id;
+    let shared = leaf.store.get_blob(component).await?;
+    leaf.store
+        .store_blob(&shared, &link, leaked_snapshot)
+        .await?;
+
+    let doc = leaf.store.open(namespace.into()).await?;
+    let pins = |snapshot| {
+        let prefix = LeafGcPathPrefix::new(&link, snapshot).to_bytes_with_format(KeyFormat::V1);
+        let doc = doc.clone();
+        async move {
+            anyhow::Ok(
+                doc.get_many(Query::key_prefix(prefix))
+                    .await?
+                    .try_collect::<Vec<_>>()
+                    .await?
+                    .len(),
+            )
+        }
+    };
+    let live_pins = pins(live).await?;
+    assert_eq!(pins(leaked_snapshot).await?, 2);
+
+    // The pins are too new to be collected.
+    assert_eq!(
+        leaf.store
+            .collect_garbage(Duration::from_secs(3600))
+            .await?,
+        GcReport::default()
+    );
+    assert_eq!(pins(leaked_snapshot).await?, 2);
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(
+        leaf.store
+            .collect_garbage(Duration::from_millis(10))
+            .await?,
+        GcReport {
+            pins: 2,
+            blobs: 1,
+            bytes: leaked.len() as u64,
+        }
+    );
+    assert_eq!(pins(leaked_snapshot).await?, 0);
+    assert_eq!(pins(live).await?, live_pins);
+    let entity = leaf.entity(link).await?.entity()?;
+    assert_eq!(entity.get_component::<Name>().await?.unwrap().0, "second");
+
+    // Nothing is left to collect.
+    assert_eq!(
+        leaf.store.collect_garbage(Duration::ZERO).await?,
+        GcReport::default()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn shared_namespaces_sync_both_ways() -> anyhow::Result<()> {
+    let first_node = Node::memory().spawn().await?;
+    let second_node = Node::memory().spawn().await?;
+    let first = Leaf::new(LeafIrohStore::new(first_node.client().clone()));
+    let second = Leaf::new(LeafIrohStore::new(second_node.client().clone()));
+
+    let namespace = first.create_namespace().await?;
+    let first_subspace = first.create_subspace().await?;
+    let first_link: ExactLink = (namespace, first_subspace, ["first"]).into();
+    let mut entity = first.entity(first_link.clone()).await?.get_or_init();
+    entity.add_component(Name("from the first node".into()))?;
+    entity.save().await?;
+
+    let ticket = first
+        .store
+        .share_namespace(namespace, ShareMode::Write)
+        .await?;
+    assert_eq!(second.store.join_namespace(&ticket).await?, namespace);
+    assert_eq!(
+        second
+            .list_namespaces()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?,
+        vec![(namespace, NamespaceCapability::Write)]
+    );
+    assert_eq!(
+        synced_name(&second, &first_link).await?,
+        "from the first node"
+    );
+
+    // The namespace was shared in write mode, so the second node can write to it too.
+    let second_subspace = second.create_subspace().await?;
+    let second_link: ExactLink = (namespace, second_subspace, ["second"]).into();
+    let mut entity = second.entity(second_link.clone()).await?.get_or_init();
+    entity.add_component(Name("from the second node".into()))?;
+    entity.save().await?;
+    assert_eq!(
+        synced_name(&first, &second_link).await?,
+        "from the second node"
+    );
+
+    let first_id = *first_node.node_id().as_bytes();
+    let second_id = *second_node.node_id().as_bytes();
+    for (leaf, peer) in [(&first, second_id), (&second, first_id)] {
+        eventually(|| async {
+            let peers = leaf.store.list_sync_peers(namespace).await?;
+            Ok(peers.iter().any(|x| x.node_id == peer).then_some(()))
+        })
+        .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use leaf_protocol::{
+    merge::{MergePolicies, MergePolicy, MergedEntity},
+    prelude::*,
+    types::SubspaceId,
+    Component,
+};
+
+/// Get the text of each merged component of type `C`, along with the subspace that it came from.
+///
+/// The components of an entity aren't kept in the order that they were added, so the components
+/// from each subspace are sorted by their text.
+async fn texts<C: Component>(
+    merged: &MergedEntity<LeafMemoryStore>,
+    text: impl Fn(C) -> String,
+) -> anyhow::Result<Vec<(SubspaceId, String)>> {
+    let mut texts = merged
+        .get_components::<C>()
+        .await?
+        .into_iter()
+        .map(|x| (x.subspace, text(x.value)))
+        .collect::<Vec<_>>();
+    let position = |subspace| merged.subspaces.iter().position(|&x| x == subspace);
+    texts.sort_by_key(|(subspace, text)| (position(*subspace), text.clone()));
+    Ok(texts)
+}
+
+fn owned(texts: &[(SubspaceId, &str)]) -> Vec<(SubspaceId, String)> {
+    texts.iter().map(|&(s, t)| (s, t.to_owned())).collect()
+}
+
+#[tokio::test]
+async fn merges_overlapping_subspaces() -> anyhow::Result<()> {
+    let leaf = Leaf::new(LeafMemoryStore::new());
+    let namespace = leaf.create_namespace().await?;
+    let instance = leaf.create_subspace().await?;
+    let user = leaf.create_subspace().await?;
+    let empty = leaf.create_subspace().await?;
+
+    let mut entity = leaf
+        .entity((namespace, instance, ["profile"]))
+        .await?
+        .get_or_init();
+    entity.add_component(Name("Instance".into()))?;
+    entity.add_component(Description("From the instance".into()))?;
+    entity.add_component(Utf8("instance note".into()))?;
+    entity.add_component(Utf8("shared note".into()))?;
+    entity.save().await?;
+    let mut entity = leaf
+        .entity((namespace, user, ["profile"]))
+        .await?
+        .get_or_init();
+    entity.add_component(Name("User".into()))?;
+    entity.add_component(Name("Nickname".into()))?;
+    entity.add_component(Utf8("shared note".into()))?;
+    entity.add_component(Utf8("user note".into()))?;
+    entity.save().await?;
+
+    let policies = MergePolicies::default().with_component::<Utf8>(MergePolicy::Union);
+    let merged = leaf
+        .merged_entity(namespace, ["profile"], &[empty, user, instance], &policies)
+        .await?
+        .unwrap();
+    // Subspaces without the entity are skipped.
+    assert_eq!(merged.subspaces, vec![user, instance]);
+    // The first subspace with a component of a first-wins schema provides all of them, and the
+    // later subspaces only provide the schemas that it doesn't have.
+    assert_eq!(
+        texts(&merged, |x: Name| x.0).await?,
+        owned(&[(user, "Nickname"), (user, "User"),])
+    );
+    assert_eq!(
+        texts(&merged, |x: Description| x.0).await?,
+        owned(&[(instance, "From the instance")])
+    );
+    // A union has the components of every subspace in the merge order, with identical components
+    // only taken from the first subspace that has them.
+    assert_eq!(
+        texts(&merged, |x: Utf8| x.0).await?,
+        owned(&[
+            (user, "shared note"),
+            (user, "user note"),
+            (instance, "instance note"),
+        ])
+    );
+
+    // Swapping the order of the subspaces gives the other subspace precedence.
+    let merged = leaf
+        .merged_entity(namespace, ["profile"], &[instance, user], &policies)
+        .await?
+        .unwrap();
+    assert_eq!(merged.subspaces, vec![instance, user]);
+    assert_eq!(
+        texts(&merged, |x: Name| x.0).await?,
+        owned(&[(instance, "Instance")])
+    );
+    assert_eq!(
+        texts(&merged, |x: Utf8| x.0).await?,
+        owned(&[
+            (instance, "instance note"),
+            (instance, "shared note"),
+            (user, "user note"),
+        ])
+    );
+
+    // With only first-wins policies, the first subspace provides all of its schemas.
+    let merged = leaf
+        .merged_entity(
+            namespace,
+            ["profile"],
+            &[user, instance],
+            &Default::default(),
+        )
+        .await?
+        .unwrap();
+    assert_eq!(
+        texts(&merged, |x: Utf8| x.0).await?,
+        owned(&[(user, "shared note"), (user, "user note"),])
+    );
+
+    assert!(leaf
+        .merged_entity(namespace, ["missing"], &[user, instance], &policies)
+        .await?
+        .is_none());
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use std::sync::Arc;
+
+use leaf_protocol::{
+    prelude::*,
+    quota::{Quota, QuotaExceeded, QuotaLimit, SubspaceQuotas, SubspaceUsage},
+    types::{NamespaceId, SubspaceId},
+};
+
+async fn save_name(
+    leaf: &Leaf<LeafMemoryStore>,
+    link: &ExactLink,
+    name: &str,
+) -> anyhow::Result<()> {
+    let mut entity = leaf.entity(link.clone()).await?.get_or_init();
+    entity.set_component(Name(name.into()))?;
+    entity.save().await
+}
+
+fn exceeded(result: anyhow::Result<()>) -> QuotaExceeded {
+    result
+        .expect_err("the save should have exceeded the quota")
+        .downcast::<QuotaExceeded>()
+        .expect("the error should be a quota error")
+}
+
+async fn leaf(quota: Quota) -> anyhow::Result<(Leaf<LeafMemoryStore>, NamespaceId, SubspaceId)> {
+    let leaf = Leaf::new(LeafMemoryStore::new()).with_quotas(SubspaceQuotas::new(quota));
+    let namespace = leaf.create_namespace().await?;
+    let subspace = leaf.create_subspace().await?;
+    Ok((leaf, namespace, subspace))
+}
+
+#[tokio::test]
+async fn reserve_entity_size() -> anyhow::Result<()> {
+    let quotas = SubspaceQuotas::new(Quota {
+        max_bytes: Some(100),
+        max_entities: Some(2),
+        max_component_bytes: None,
+    });
+    let first: ExactLink = ([1; 32], [2; 32], ["first"]).into();
+    let second: ExactLink = ([1; 32], [2; 32], ["second"]).into();
+    let third: ExactLink = ([1; 32], [2; 32], ["third"]).into();
+
+    // Nothing is checked in a subspace that hasn't been counted.
+    assert_eq!(quotas.reserve_entity_size(&first, 1000).await?, None);
+    assert_eq!(quotas.usage(first.namespace, first.subspace), None);
+
+    quotas
+        .count_subspace(first.namespace, first.subspace, Vec::new())
+        .await?;
+    assert_eq!(quotas.reserve_entity_size(&first, 60).await?, None);
+    assert_eq!(quotas.reserve_entity_size(&first, 70).await?, Some(60));
+    let error = quotas
+        .reserve_entity_size(&second, 40)
+        .await
+        .unwrap_err()
+        .downcast::<QuotaExceeded>()?;
+    assert_eq!(error.limit, QuotaLimit::Bytes);
+    assert_eq!((error.max, error.requested), (100, 110));
+    assert_eq!(quotas.reserve_entity_size(&second, 30).await?, None);
+    let error = quotas
+        .reserve_entity_size(&third, 0)
+        .await
+        .unwrap_err()
+        .downcast::<QuotaExceeded>()?;
+    assert_eq!(error.limit, QuotaLimit::Entities);
+    assert_eq!((error.max, error.requested), (2, 3));
+    assert_eq!(
+        quotas.usage(first.namespace, first.subspace),
+        Some(SubspaceUsage {
+            bytes: 100,
+            entities: 2,
+        })
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn saves_that_exceed_the_quota_are_rejected() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf(Quota {
+        max_bytes: None,
+        max_entities: Some(1),
+        max_component_bytes: Some(64),
+    })
+    .await?;
+    let first: ExactLink = (namespace, subspace, ["first"]).into();
+    let second: ExactLink = (namespace, subspace, ["second"]).into();
+
+    let error = exceeded(save_name(&leaf, &first, &"a".repeat(64)).await);
+    assert_eq!(error.limit, QuotaLimit::ComponentBytes);
+    assert!(leaf.store.get_entity(&first).await?.is_none());
+
+    save_name(&leaf, &first, "first").await?;
+    let usage = leaf.subspace_usage(namespace, subspace).await?;
+    assert_eq!(usage.entities, 1);
+    let error = exceeded(save_name(&leaf, &second, "second").await);
+    assert_eq!(
+        (error.limit, error.link),
+        (QuotaLimit::Entities, second.clone())
+    );
+    assert!(leaf.store.get_entity(&second).await?.is_none());
+    assert_eq!(leaf.subspace_usage(namespace, subspace).await?, usage);
+
+    // Changing an entity doesn't add to the entity count, and deleting one frees up space.
+    save_name(&leaf, &first, "renamed").await?;
+    leaf.del_entity(first).await?;
+    assert_eq!(
+        leaf.subspace_usage(namespace, subspace).await?,
+        SubspaceUsage::default()
+    );
+    save_name(&leaf, &second, "second").await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn failed_writes_give_back_their_reservation() -> anyhow::Result<()> {
+    let (leaf, namespace, _) = leaf(Quota {
+        max_bytes: None,
+        max_entities: Some(1),
+        max_component_bytes: None,
+    })
+    .await?;
+    // We don't have the secret of the subspace, so writing the entity fails after its size has
+    // been reserved.
+    let subspace = [7; 32];
+    let link: ExactLink = (namespace, subspace, ["entity"]).into();
+    let error = save_name(&leaf, &link, "name").await.unwrap_err();
+    assert!(error.downcast_ref::<QuotaExceeded>().is_none());
+    assert_eq!(
+        leaf.subspace_usage(namespace, subspace).await?,
+        SubspaceUsage::default()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn usage_is_persisted() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let db = Arc::new(redb::Database::create(dir.path().join("quota.redb"))?);
+    let quotas = || SubspaceQuotas::new(Quota::default()).with_database(db.clone());
+    let leaf = Leaf::new(LeafMemoryStore::new()).with_quotas(quotas()?);
+    let namespace = leaf.create_namespace().await?;
+    let subspace = leaf.create_subspace().await?;
+    let first: ExactLink = (namespace, subspace, ["first"]).into();
+    let second: ExactLink = (namespace, subspace, ["second"]).into();
+    save_name(&leaf, &first, "first").await?;
+    save_name(&leaf, &second, "second").await?;
+    leaf.del_entity(first).await?;
+    let usage = leaf.subspace_usage(namespace, subspace).await?;
+    assert_eq!(usage.entities, 1);
+
+    // The usage is loaded from the database, without counting the subspace again.
+    assert_eq!(quotas()?.usage(namespace, subspace), Some(usage));
+    leaf.del_entity(second).await?;
+    assert_eq!(
+        quotas()?.usage(namespace, subspace),
+        Some(SubspaceUsage::default())
+    );
+    leaf.recount_usage(namespace, subspace).await?;
+    assert_eq!(
+        quotas()?.usage(namespace, subspace),
+        Some(SubspaceUsage::default())
+    );
+    Ok(())
+}
@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use leaf_protocol::{
+    prelude::*,
+    search::SearchIndex,
+    types::{NamespaceId, SubspaceId},
+    Component,
+};
+
+async fn leaf() -> anyhow::Result<(Leaf<LeafMemoryStore>, NamespaceId, SubspaceId)> {
+    let leaf = Leaf::new(LeafMemoryStore::new()).with_search_index(SearchIndex::default());
+    let namespace = leaf.create_namespace().await?;
+    let subspace = leaf.create_subspace().await?;
+    Ok((leaf, namespace, subspace))
+}
+
+/// Replace the component of type `C` on an entity, creating the entity if it doesn't exist.
+async fn save<C: Component>(
+    leaf: &Leaf<LeafMemoryStore>,
+    link: &ExactLink,
+    component: C,
+) -> anyhow::Result<()> {
+    let mut entity = leaf.entity(link.clone()).await?.get_or_init();
+    entity.set_component(component)?;
+    entity.save().await
+}
+
+/// Search a namespace, returning the links of the results in order.
+async fn search(
+    leaf: &Leaf<LeafMemoryStore>,
+    namespace: NamespaceId,
+    query: &str,
+) -> anyhow::Result<Vec<ExactLink>> {
+    let results = leaf.search(namespace, query, 10).await?;
+    Ok(results.into_iter().map(|x| x.link).collect())
+}
+
+#[tokio::test]
+async fn results_are_ranked() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf().await?;
+    let title: ExactLink = (namespace, subspace, ["title"]).into();
+    let body: ExactLink = (namespace, subspace, ["body"]).into();
+    let other: ExactLink = (namespace, subspace, ["other"]).into();
+    // A match in a name is weighted more than a match in a longer body of text.
+    save(
+        &leaf,
+        &body,
+        Utf8("An apple a day keeps the doctor away".into()),
+    )
+    .await?;
+    save(&leaf, &title, Name("Apple".into())).await?;
+    save(&leaf, &other, Utf8("Bananas".into())).await?;
+
+    let results = leaf.search(namespace, "apple", 10).await?;
+    assert_eq!(
+        results.iter().map(|x| &x.link).collect::<Vec<_>>(),
+        [&title, &body]
+    );
+    assert!(results[0].score > results[1].score);
+    assert_eq!(
+        search(&leaf, namespace, "APPLE").await?,
+        [title.clone(), body]
+    );
+    assert_eq!(leaf.search(namespace, "apple", 1).await?.len(), 1);
+    assert!(search(&leaf, namespace, "cherry").await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn last_term_matches_prefixes() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf().await?;
+    let app: ExactLink = (namespace, subspace, ["app"]).into();
+    let apple: ExactLink = (namespace, subspace, ["apple"]).into();
+    save(&leaf, &app, Name("app".into())).await?;
+    save(&leaf, &apple, Name("apple".into())).await?;
+
+    // An exact match ranks above a prefix match.
+    assert_eq!(
+        search(&leaf, namespace, "app").await?,
+        [app.clone(), apple.clone()]
+    );
+    assert_eq!(search(&leaf, namespace, "appl").await?, vec![apple.clone()]);
+    assert_eq!(search(&leaf, namespace, "apple").await?, [apple]);
+    // Only the last term of the query matches prefixes.
+    assert!(search(&leaf, namespace, "ap app").await?.contains(&app));
+    assert!(search(&leaf, namespace, "ap zzz").await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn index_follows_changes() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf().await?;
+    let link: ExactLink = (namespace, subspace, ["entity"]).into();
+    save(&leaf, &link, Name("before".into())).await?;
+    assert_eq!(
+        search(&leaf, namespace, "before").await?,
+        vec![link.clone()]
+    );
+
+    // Saving through the leaf updates the index.
+    save(&leaf, &link, Name("after".into())).await?;
+    assert!(search(&leaf, namespace, "before").await?.is_empty());
+    assert_eq!(search(&leaf, namespace, "after").await?, vec![link.clone()]);
+
+    // Changes made without the leaf are only picked up by re-indexing.
+    let other = Leaf::new(leaf.store.clone());
+    save(&other, &link, Name("elsewhere".into())).await?;
+    assert!(search(&leaf, namespace, "elsewhere").await?.is_empty());
+    leaf.reindex(namespace).await?;
+    assert_eq!(search(&leaf, namespace, "elsewhere").await?, [link]);
+    assert!(search(&leaf, namespace, "after").await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn deleted_entities_are_removed() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf().await?;
+    let deleted: ExactLink = (namespace, subspace, ["deleted"]).into();
+    let loaded: ExactLink = (namespace, subspace, ["loaded"]).into();
+    save(&leaf, &deleted, Name("gone".into())).await?;
+    save(&leaf, &loaded, Name("gone".into())).await?;
+    assert_eq!(search(&leaf, namespace, "gone").await?.len(), 2);
+
+    leaf.del_entity(deleted).await?;
+    assert_eq!(
+        search(&leaf, namespace, "gone").await?,
+        vec![loaded.clone()]
+    );
+    leaf.entity(loaded).await?.entity()?.delete().await?;
+    assert!(search(&leaf, namespace, "gone").await?.is_empty());
+    leaf.reindex(namespace).await?;
+    assert!(search(&leaf, namespace, "gone").await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn trashed_entities_are_removed() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf().await?;
+    let leaf = leaf.with_trash(Duration::from_secs(60));
+    let link: ExactLink = (namespace, subspace, ["entity"]).into();
+    save(&leaf, &link, Name("trashed".into())).await?;
+    assert_eq!(
+        search(&leaf, namespace, "trashed").await?,
+        vec![link.clone()]
+    );
+
+    leaf.del_entity(link.clone()).await?;
+    assert!(search(&leaf, namespace, "trashed").await?.is_empty());
+    // Re-indexing doesn't pick up the entity in the trash either.
+    leaf.reindex(namespace).await?;
+    assert!(search(&leaf, namespace, "trashed").await?.is_empty());
+
+    let trashed = leaf.list_trash(namespace, subspace).await?.remove(0);
+    leaf.restore_trash(trashed.link).await?;
+    assert_eq!(search(&leaf, namespace, "trashed").await?, [link]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn namespaces_are_searched_separately() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf().await?;
+    let other_namespace = leaf.create_namespace().await?;
+    let first: ExactLink = (namespace, subspace, ["entity"]).into();
+    let second: ExactLink = (other_namespace, subspace, ["entity"]).into();
+    save(&leaf, &first, Name("shared".into())).await?;
+    save(&leaf, &second, Name("shared".into())).await?;
+
+    assert_eq!(search(&leaf, namespace, "shared").await?, [first]);
+    assert_eq!(
+        search(&leaf, other_namespace, "shared").await?,
+        vec![second.clone()]
+    );
+    // Deleting from one namespace leaves the other alone.
+    leaf.del_entity(second).await?;
+    assert!(search(&leaf, other_namespace, "shared").await?.is_empty());
+    assert_eq!(search(&leaf, namespace, "shared").await?.len(), 1);
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use std::time::Duration;
+
+use futures::TryStreamExt;
+use leaf_protocol::{
+    prelude::*,
+    trash::{TrashEntry, TRASH_PREFIX_STR},
+    types::{NamespaceId, SubspaceId},
+};
+
+async fn leaf() -> anyhow::Result<(Leaf<LeafMemoryStore>, NamespaceId, SubspaceId)> {
+    let leaf = Leaf::new(LeafMemoryStore::new()).with_trash(Duration::from_secs(60));
+    let namespace = leaf.create_namespace().await?;
+    let subspace = leaf.create_subspace().await?;
+    Ok((leaf, namespace, subspace))
+}
+
+async fn save_name(
+    leaf: &Leaf<LeafMemoryStore>,
+    link: &ExactLink,
+    name: &str,
+) -> anyhow::Result<()> {
+    let mut entity = leaf.entity(link.clone()).await?.get_or_init();
+    entity.set_component(Name(name.into()))?;
+    entity.save().await
+}
+
+#[tokio::test]
+async fn pages_from_the_root_skip_the_trash() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf().await?;
+    let names = ["a", "b", "c", "d", "e", "f"];
+    for name in names {
+        save_name(&leaf, &(namespace, subspace, [name]).into(), name).await?;
+    }
+    // The trash comes before the other entities in the memory store, so the first pages of the
+    // store are all trashed entities.
+    for name in &names[..4] {
+        leaf.del_entity((namespace, subspace, [*name])).await?;
+    }
+
+    let root: ExactLink = (namespace, subspace, ()).into();
+    let page = leaf.list_page(root.clone(), None, None, 2).await?;
+    assert_eq!(page.entities.len(), 2);
+    assert!(page.next.is_none());
+
+    let mut listed = Vec::new();
+    let mut after = None;
+    loop {
+        let page = leaf.list_page(root.clone(), None, after, 1).await?;
+        listed.extend(page.entities);
+        match page.next {
+            Some(next) => after = Some(next),
+            None => break,
+        }
+    }
+    let expected = ["e", "f"].map(|x| ExactLink::from((namespace, subspace, [x])));
+    assert_eq!(listed, expected);
+    Ok(())
+}
+
+/// Get the only entity in the trash of a subspace.
+async fn trashed(
+    leaf: &Leaf<LeafMemoryStore>,
+    namespace: NamespaceId,
+    subspace: SubspaceId,
+) -> anyhow::Result<TrashEntry> {
+    let mut entries = leaf.list_trash(namespace, subspace).await?;
+    assert_eq!(entries.len(), 1);
+    Ok(entries.remove(0))
+}
+
+#[tokio::test]
+async fn deleted_entities_move_to_the_trash() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf().await?;
+    let link: ExactLink = (namespace, subspace, ["entity"]).into();
+    save_name(&leaf, &link, "name").await?;
+    leaf.del_entity(link.clone()).await?;
+
+    assert!(leaf.store.get_entity(&link).await?.is_none());
+    let root: ExactLink = (namespace, subspace, ()).into();
+    let listed = leaf
+        .list(root, None, None)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert!(listed.is_empty());
+
+    let entry = trashed(&leaf, namespace, subspace).await?;
+    assert_eq!(entry.original, link);
+    let entity = leaf.entity(entry.link).await?.entity()?;
+    assert_eq!(entity.get_component::<Name>().await?.unwrap().0, "name");
+    Ok(())
+}
+
+#[tokio::test]
+async fn restored_entities_return_to_their_link() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf().await?;
+    let link: ExactLink = (namespace, subspace, ["entity"]).into();
+    save_name(&leaf, &link, "name").await?;
+    leaf.del_entity(link.clone()).await?;
+    let entry = trashed(&leaf, namespace, subspace).await?;
+
+    // An entity can't be restored over another one.
+    save_name(&leaf, &link, "replacement").await?;
+    assert!(leaf.restore_trash(entry.link.clone()).await.is_err());
+    leaf.del_entity(link.clone()).await?;
+    assert_eq!(leaf.list_trash(namespace, subspace).await?.len(), 2);
+    leaf.purge_trash(leaf.list_trash(namespace, subspace).await?[1].link.clone())
+        .await?;
+
+    assert_eq!(leaf.restore_trash(entry.link.clone()).await?, link);
+    let entity = leaf.entity(link).await?.entity()?;
+    assert_eq!(entity.get_component::<Name>().await?.unwrap().0, "name");
+    assert!(leaf.list_trash(namespace, subspace).await?.is_empty());
+    assert!(leaf.store.get_entity(&entry.link).await?.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn purged_entities_are_deleted() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf().await?;
+    let link: ExactLink = (namespace, subspace, ["entity"]).into();
+    save_name(&leaf, &link, "name").await?;
+
+    // Only entities in the trash can be purged.
+    assert!(leaf.purge_trash(link.clone()).await.is_err());
+    leaf.del_entity(link.clone()).await?;
+    let entry = trashed(&leaf, namespace, subspace).await?;
+    leaf.purge_trash(entry.link.clone()).await?;
+
+    assert!(leaf.list_trash(namespace, subspace).await?.is_empty());
+    assert!(leaf.store.get_entity(&entry.link).await?.is_none());
+    assert!(leaf.restore_trash(entry.link).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn expired_entities_are_purged() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf().await?;
+    let leaf = leaf.with_trash(Duration::from_millis(200));
+    let old: ExactLink = (namespace, subspace, ["old"]).into();
+    let new: ExactLink = (namespace, subspace, ["new"]).into();
+    save_name(&leaf, &old, "old").await?;
+    save_name(&leaf, &new, "new").await?;
+
+    leaf.del_entity(old).await?;
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    leaf.del_entity(new.clone()).await?;
+
+    assert_eq!(leaf.purge_expired_trash(namespace, subspace).await?, 1);
+    assert_eq!(trashed(&leaf, namespace, subspace).await?.original, new);
+    assert_eq!(leaf.purge_expired_trash(namespace, subspace).await?, 0);
+
+    // Expired entities can't be purged if the trash isn't enabled.
+    let without_trash = Leaf::new(leaf.store.clone());
+    assert!(without_trash
+        .purge_expired_trash(namespace, subspace)
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn the_trash_is_reserved() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf().await?;
+    let link: ExactLink = (namespace, subspace, ["entity"]).into();
+    save_name(&leaf, &link, "name").await?;
+    leaf.del_entity(link).await?;
+    let entry = trashed(&leaf, namespace, subspace).await?;
+
+    // Trashed entities can't be changed or deleted, with or without the trash enabled.
+    let without_trash = Leaf::new(leaf.store.clone());
+    for leaf in [&leaf, &without_trash] {
+        assert!(save_name(leaf, &entry.link, "changed").await.is_err());
+        assert!(leaf.del_entity(entry.link.clone()).await.is_err());
+        let mut entity = leaf.entity(entry.link.clone()).await?.entity()?;
+        assert!(entity.delete().await.is_err());
+    }
+    let entity = leaf.entity(entry.link.clone()).await?.entity()?;
+    assert_eq!(entity.get_component::<Name>().await?.unwrap().0, "name");
+
+    // Entities can't be saved anywhere in the trash, even where there is nothing yet.
+    let reserved: ExactLink = (namespace, subspace, [TRASH_PREFIX_STR, "new"]).into();
+    assert!(save_name(&leaf, &reserved, "name").await.is_err());
+    assert!(leaf.store.get_entity(&reserved).await?.is_none());
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use leaf_protocol::{
+    prelude::*,
+    types::{NamespaceId, SubspaceId},
+    unique::{UniqueConstraintViolation, UniqueScope},
+};
+
+async fn leaf(
+    scope: UniqueScope,
+) -> anyhow::Result<(Leaf<LeafMemoryStore>, NamespaceId, SubspaceId)> {
+    let leaf = Leaf::new(LeafMemoryStore::new()).with_unique_component::<Name>(scope);
+    let namespace = leaf.create_namespace().await?;
+    let subspace = leaf.create_subspace().await?;
+    Ok((leaf, namespace, subspace))
+}
+
+async fn save_name(
+    leaf: &Leaf<LeafMemoryStore>,
+    link: &ExactLink,
+    name: &str,
+) -> anyhow::Result<()> {
+    let mut entity = leaf.entity(link.clone()).await?.get_or_init();
+    entity.set_component(Name(name.into()))?;
+    entity.save().await
+}
+
+/// Get the entity that a failed save said already holds the value.
+fn existing(result: anyhow::Result<()>) -> ExactLink {
+    let error = result.expect_err("the save should have violated the unique constraint");
+    error
+        .downcast::<UniqueConstraintViolation>()
+        .expect("the error should be a unique constraint violation")
+        .existing
+}
+
+#[tokio::test]
+async fn namespace_scope_rejects_other_subspaces() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf(UniqueScope::Namespace).await?;
+    let other_subspace = leaf.create_subspace().await?;
+    let first: ExactLink = (namespace, subspace, ["first"]).into();
+    let second: ExactLink = (namespace, other_subspace, ["second"]).into();
+
+    save_name(&leaf, &first, "name").await?;
+    assert_eq!(existing(save_name(&leaf, &second, "name").await), first);
+    // Nothing was written for the rejected entity.
+    assert!(leaf.store.get_entity(&second).await?.is_none());
+
+    // Saving the same value again on the entity that holds it is fine.
+    save_name(&leaf, &first, "name").await?;
+    save_name(&leaf, &second, "other").await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn subspace_scope_allows_other_subspaces() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf(UniqueScope::Subspace).await?;
+    let other_subspace = leaf.create_subspace().await?;
+    let first: ExactLink = (namespace, subspace, ["first"]).into();
+
+    save_name(&leaf, &first, "name").await?;
+    save_name(
+        &leaf,
+        &(namespace, other_subspace, ["other"]).into(),
+        "name",
+    )
+    .await?;
+    let second = (namespace, subspace, ["second"]).into();
+    assert_eq!(existing(save_name(&leaf, &second, "name").await), first);
+    Ok(())
+}
+
+#[tokio::test]
+async fn values_are_released_by_changes_and_deletes() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf(UniqueScope::Namespace).await?;
+    let first: ExactLink = (namespace, subspace, ["first"]).into();
+    let second: ExactLink = (namespace, subspace, ["second"]).into();
+
+    save_name(&leaf, &first, "name").await?;
+    save_name(&leaf, &first, "renamed").await?;
+    save_name(&leaf, &second, "name").await?;
+
+    assert_eq!(existing(save_name(&leaf, &second, "renamed").await), first);
+    leaf.del_entity(first).await?;
+    save_name(&leaf, &second, "renamed").await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn trashed_entities_release_their_values() -> anyhow::Result<()> {
+    let (leaf, namespace, subspace) = leaf(UniqueScope::Namespace).await?;
+    let leaf = leaf.with_trash(Duration::from_secs(60));
+    let first: ExactLink = (namespace, subspace, ["first"]).into();
+    let second: ExactLink = (namespace, subspace, ["second"]).into();
+
+    save_name(&leaf, &first, "name").await?;
+    leaf.del_entity(first.clone()).await?;
+    save_name(&leaf, &second, "name").await?;
+
+    // The value was claimed while the entity was in the trash, so it can't be restored.
+    let trashed = leaf.list_trash(namespace, subspace).await?.remove(0);
+    let error = leaf.restore_trash(trashed.link.clone()).await.unwrap_err();
+    let violation = error.downcast::<UniqueConstraintViolation>().unwrap();
+    assert_eq!(violation.existing, second);
+
+    // Once the value is free again, the entity can be restored and holds it again.
+    leaf.del_entity(second.clone()).await?;
+    assert_eq!(leaf.restore_trash(trashed.link).await?, first);
+    assert_eq!(existing(save_name(&leaf, &second, "name").await), first);
+    Ok(())
+}
+
+#[tokio::test]
+async fn existing_entities_are_indexed() -> anyhow::Result<()> {
+    // Save an entity before the constraint is configured, so that it is only found by indexing the
+    // namespace from the store.
+    let unconstrained = Leaf::new(LeafMemoryStore::new());
+    let namespace = unconstrained.create_namespace().await?;
+    let subspace = unconstrained.create_subspace().await?;
+    let first: ExactLink = (namespace, subspace, ["first"]).into();
+    save_name(&unconstrained, &first, "name").await?;
+
+    let leaf = unconstrained.with_unique_component::<Name>(UniqueScope::Namespace);
+    let second = (namespace, subspace, ["second"]).into();
+    assert_eq!(existing(save_name(&leaf, &second, "name").await), first);
+
+    // Changes made without the constraint are picked up by re-indexing.
+    save_name(&leaf, &second, "other").await?;
+    let third: ExactLink = (namespace, subspace, ["third"]).into();
+    let mut entity = leaf.entity(second.clone()).await?.entity()?;
+    entity.delete().await?;
+    let stale = Leaf::new(leaf.store.clone());
+    save_name(&stale, &third, "other").await?;
+    leaf.reindex(namespace).await?;
+    assert_eq!(existing(save_name(&leaf, &second, "other").await), third);
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use leaf_protocol::prelude::*;
+
+#[derive(EntityView, Debug)]
+struct Profile {
+    username: Username,
+    name: Option<Name>,
+    description: Option<Description>,
+    tags: Vec<Tags>,
+}
+
+#[tokio::test]
+async fn derived_view_is_applied_and_loaded() -> anyhow::Result<()> {
+    let leaf = Leaf::new(LeafMemoryStore::new());
+    let namespace = leaf.create_namespace().await?;
+    let subspace = leaf.create_subspace().await?;
+    let link: ExactLink = (namespace, subspace, ["profile"]).into();
+
+    let profile = Profile {
+        username: Username("username".into()),
+        name: Some(Name("name".into())),
+        description: None,
+        tags: vec![Tags(vec!["a".into()]), Tags(vec!["b".into()])],
+    };
+    let mut entity = leaf.entity(link.clone()).await?.get_or_init();
+    entity.add_component(Description("removed by the view".into()))?;
+    profile.apply(&mut entity)?;
+    entity.save().await?;
+
+    let entity = leaf.entity(link).await?.entity()?;
+    // Loading a view can be done from tasks that move between threads.
+    fn assert_send<T: Send>(x: T) -> T {
+        x
+    }
+    let loaded = assert_send(Profile::load(&entity)).await?;
+    assert_eq!(loaded.username.0, "username");
+    assert_eq!(loaded.name.map(|x| x.0).as_deref(), Some("name"));
+    assert!(loaded.description.is_none());
+    let mut tags = loaded.tags.into_iter().map(|x| x.0).collect::<Vec<_>>();
+    tags.sort();
+    assert_eq!(tags, [vec!["a"], vec!["b"]]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn missing_required_component_fails_to_load() -> anyhow::Result<()> {
+    let leaf = Leaf::new(LeafMemoryStore::new());
+    let namespace = leaf.create_namespace().await?;
+    let subspace = leaf.create_subspace().await?;
+    let mut entity = leaf
+        .entity((namespace, subspace, ["profile"]))
+        .await?
+        .get_or_init();
+    entity.add_component(Name("name".into()))?;
+    entity.save().await?;
+
+    assert!(Profile::load(&entity).await.is_err());
+    Ok(())
+}
@@ -72,6 +72,7 @@ impl Entity {
     PartialOrd,
     Ord,
     Eq,
+    Hash,
 )]
 pub struct ComponentEntry {
     // The schema ID may not be set if the component is encrypted.
@@ -229,10 +230,10 @@ pub enum BorshSchema {
 
 #[derive(borsh::BorshDeserialize, borsh::BorshSerialize, Debug, Clone)]
 pub struct Link {
-    namespace: KeyResolverKind,
-    subspace: KeyResolverKind,
-    path: Vec<PathSegment>,
-    snapshot: Option<Digest>,
+    pub namespace: KeyResolverKind,
+    pub subspace: KeyResolverKind,
+    pub path: Vec<PathSegment>,
+    pub snapshot: Option<Digest>,
 }
 impl HasBorshSchema for Link {
     fn borsh_schema() -> BorshSchema {
@@ -1,8 +1,11 @@
 use clap::Parser;
 use iroh_base::base32;
 use leaf_rpc_client::{
-    leaf_protocol::prelude::{Description, Name},
-    Uri,
+    leaf_protocol::{
+        metadata::{KeyMetadata, MetadataKey},
+        prelude::{Description, Name},
+    },
+    RpcClient, Uri,
 };
 
 // TODO: turn this into a simple CLI or maybe a repl for accessing/modifying leaf data.
@@ -12,22 +15,79 @@ struct Args {
     pub uri: Uri,
     #[arg(short, long)]
     pub auth_token: Option<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+enum Command {
+    /// List the petnames and descriptions of namespaces and subspaces.
+    Metadata,
+    /// Set the petname and description of a namespace or subspace.
+    SetMetadata {
+        /// The base32 encoded namespace or subspace ID.
+        id: String,
+        /// Set the metadata of the subspace with this ID instead of the namespace.
+        #[arg(short, long)]
+        subspace: bool,
+        #[arg(short, long)]
+        petname: Option<String>,
+        #[arg(short, long)]
+        description: Option<String>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let client = RpcClient::connect(args.uri, args.auth_token.as_deref()).await?;
+
+    match args.command {
+        None => demo(&client).await,
+        Some(Command::Metadata) => {
+            for (key, metadata) in client.list_key_metadata().await? {
+                print_metadata(key, &metadata);
+            }
+            Ok(())
+        }
+        Some(Command::SetMetadata {
+            id,
+            subspace,
+            petname,
+            description,
+        }) => {
+            let id = base32::parse_array(&id)?;
+            let key = if subspace {
+                MetadataKey::Subspace(id)
+            } else {
+                MetadataKey::Namespace(id)
+            };
+            let metadata = client.set_key_metadata(key, petname, description).await?;
+            print_metadata(key, &metadata);
+            Ok(())
+        }
+    }
+}
+
+async fn demo(client: &RpcClient) -> anyhow::Result<()> {
     let secret = [
         0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24,
         25, 26, 27, 28, 29, 30, 31,
     ];
     println!("Secret: {}", base32::fmt(secret));
-    let args = Args::parse();
-    let client = leaf_rpc_client::RpcClient::connect(args.uri, args.auth_token.as_deref()).await?;
 
     let ns = client.import_namespace_secret(secret).await?;
     let ss = client.import_subspace_secret(secret).await?;
-    println!("Namespace: {}", base32::fmt(ns));
-    println!("Namespace: {}", base32::fmt(ss));
+    println!(
+        "Namespace: {}{}",
+        base32::fmt(ns),
+        petname(client, MetadataKey::Namespace(ns)).await?
+    );
+    println!(
+        "Subspace: {}{}",
+        base32::fmt(ss),
+        petname(client, MetadataKey::Subspace(ss)).await?
+    );
 
     let link = (ns, ss, ["test"]);
 
@@ -43,3 +103,29 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Print the petname and description of a key.
+fn print_metadata(key: MetadataKey, metadata: &KeyMetadata) {
+    let (kind, id) = match key {
+        MetadataKey::Namespace(id) => ("Namespace", id),
+        MetadataKey::Subspace(id) => ("Subspace", id),
+    };
+    println!(
+        "{kind} {}: {}",
+        base32::fmt(id),
+        metadata.petname.as_deref().unwrap_or("<no petname>")
+    );
+    if let Some(description) = &metadata.description {
+        println!("    {description}");
+    }
+}
+
+/// Get the petname of a key, formatted to be printed after its ID.
+async fn petname(client: &RpcClient, key: MetadataKey) -> anyhow::Result<String> {
+    Ok(client
+        .get_key_metadata(key)
+        .await?
+        .and_then(|x| x.petname)
+        .map(|x| format!(" ({x})"))
+        .unwrap_or_default())
+}
@@ -1,4 +1,7 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use clap::Parser;
 use crossterm::{
@@ -9,7 +12,10 @@ use crossterm::{
 use futures::StreamExt;
 use iroh::{docs::NamespaceId, node::FsNode};
 use layout::Size;
-use leaf_protocol::prelude::*;
+use leaf_protocol::{
+    metadata::{LeafMetadataStore, MetadataKey},
+    prelude::*,
+};
 use once_cell::sync::Lazy;
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
@@ -97,8 +103,20 @@ impl App {
             let (doc, _cap) = doc?;
             docs.push(doc);
         }
+
+        // Show the petnames that the Leaf RPC server has recorded for the namespaces
+        let metadata = LeafMetadataStore::open(ARGS.data_dir.join("metadata.redb"))?;
+        let mut petnames = HashMap::new();
+        for doc in &docs {
+            let key = MetadataKey::Namespace(doc.to_bytes());
+            if let Some(petname) = metadata.get(key).await?.and_then(|x| x.petname) {
+                petnames.insert(*doc, petname);
+            }
+        }
+
         Ok(AppState::Home(HomePage {
             docs,
+            petnames,
             docs_state: ListState::default().with_selected(Some(0)),
             show_help: false,
         }))
@@ -218,6 +236,7 @@ impl Widget for &mut App {
 
 struct HomePage {
     docs: Vec<NamespaceId>,
+    petnames: HashMap<NamespaceId, String>,
     docs_state: ListState,
     show_help: bool,
 }
@@ -239,7 +258,10 @@ impl Widget for &mut HomePage {
             List::new(
                 self.docs
                     .iter()
-                    .map(|x| Text::from(x.to_string()))
+                    .map(|x| match self.petnames.get(x) {
+                        Some(petname) => Text::from(format!("{petname} ({x})")),
+                        None => Text::from(x.to_string()),
+                    })
                     .collect::<Vec<_>>(),
             )
             .block(
@@ -14,7 +14,7 @@ use hyper::{
     header::{CONNECTION, UPGRADE},
     Request,
 };
-use leaf_rpc_proto::{Req, ReqKind, Resp, RespKind};
+use leaf_rpc_proto::{Req, ReqKind, Resp, RespKind, MAX_LIST_ENTITIES_LIMIT};
 use tokio::{
     net::TcpStream,
     sync::{mpsc, oneshot, Mutex},
@@ -23,7 +23,15 @@ use tokio::{
 pub use hyper::Uri;
 pub use leaf_protocol;
 
-use leaf_protocol::prelude::*;
+use leaf_protocol::{
+    backlinks::Backlink,
+    metadata::{KeyMetadata, MetadataKey},
+    prelude::*,
+    quota::{Quota, SubspaceUsage},
+    search::SearchResult,
+    store::{GcReport, NamespaceCapability, ShareMode, StoreMetrics, SyncPeer},
+    trash::TrashEntry,
+};
 use tokio_stream::wrappers::ReceiverStream;
 
 #[derive(Clone)]
@@ -190,19 +198,299 @@ impl RpcClient {
         Ok(())
     }
 
+    /// List the entities at or below `link`.
+    ///
+    /// If `depth` is set, only entities at most `depth` path segments below `link` are listed, so
+    /// a depth of `1` lists the direct children of `link`.
+    ///
+    /// The entities are fetched from the server one page at a time.
     pub async fn list_entities<L: Into<ExactLink>>(
         &self,
         link: L,
+        depth: Option<u32>,
     ) -> anyhow::Result<Vec<ExactLink>> {
         let link = link.into();
-        let resp = self.send_req(ReqKind::ListEntities(link)).await?;
-        let RespKind::ListEntities(entities) = resp
+        let mut entities = Vec::new();
+        let mut after = None;
+        loop {
+            let page = self
+                .list_entities_page(link.clone(), depth, after, LIST_ENTITIES_PAGE_SIZE)
+                .await?;
+            entities.extend(page.entities);
+            match page.next {
+                Some(next) => after = Some(next),
+                None => break,
+            }
+        }
+        Ok(entities)
+    }
+
+    /// List a page of up to `limit` entities at or below `link`, resuming after the `after` path.
+    ///
+    /// Pass the `next` cursor from the returned page as `after` to get the next page.
+    pub async fn list_entities_page<L: Into<ExactLink>>(
+        &self,
+        link: L,
+        depth: Option<u32>,
+        after: Option<EntityPath>,
+        limit: u32,
+    ) -> anyhow::Result<EntityPage> {
+        let link = link.into();
+        let resp = self
+            .send_req(ReqKind::ListEntities {
+                link,
+                depth,
+                after,
+                limit: Some(limit),
+            })
+            .await?;
+        let RespKind::ListEntities(page) = resp
             .result
             .map_err(|s| anyhow::format_err!("Error from Leaf RPC endpoint: {s}"))?
         else {
             anyhow::bail!(INVALID_RPC_RESP_MSG);
         };
-        Ok(entities)
+        Ok(page)
+    }
+
+    /// Full-text search the entities in a namespace, returning up to `limit` results ordered from
+    /// most to least relevant.
+    pub async fn search(
+        &self,
+        namespace: NamespaceId,
+        query: &str,
+        limit: u32,
+    ) -> anyhow::Result<Vec<SearchResult>> {
+        let resp = self
+            .send_req(ReqKind::Search {
+                namespace,
+                query: query.into(),
+                limit,
+            })
+            .await?;
+        let RespKind::Search(results) = resp
+            .result
+            .map_err(|s| anyhow::format_err!("Error from Leaf RPC endpoint: {s}"))?
+        else {
+            anyhow::bail!(INVALID_RPC_RESP_MSG);
+        };
+        Ok(results)
+    }
+
+    /// List the entities that link to `link`, optionally only through components with the given
+    /// schema.
+    pub async fn backlinks<L: Into<ExactLink>>(
+        &self,
+        link: L,
+        schema: Option<Digest>,
+    ) -> anyhow::Result<Vec<Backlink>> {
+        let link = link.into();
+        let resp = self.send_req(ReqKind::Backlinks { link, schema }).await?;
+        let RespKind::Backlinks(backlinks) = resp
+            .result
+            .map_err(|s| anyhow::format_err!("Error from Leaf RPC endpoint: {s}"))?
+        else {
+            anyhow::bail!(INVALID_RPC_RESP_MSG);
+        };
+        Ok(backlinks)
+    }
+
+    /// Remove leaked blob pins from the server's store, returning how much was cleaned up.
+    pub async fn collect_garbage(&self) -> anyhow::Result<GcReport> {
+        let resp = self.send_req(ReqKind::CollectGarbage).await?;
+        let RespKind::CollectGarbage(report) = resp
+            .result
+            .map_err(|s| anyhow::format_err!("Error from Leaf RPC endpoint: {s}"))?
+        else {
+            anyhow::bail!(INVALID_RPC_RESP_MSG);
+        };
+        Ok(report)
+    }
+
+    /// Create a ticket that another node can use to join and sync a namespace.
+    pub async fn share_namespace(
+        &self,
+        namespace: NamespaceId,
+        mode: ShareMode,
+    ) -> anyhow::Result<String> {
+        let resp = self
+            .send_req(ReqKind::ShareNamespace { namespace, mode })
+            .await?;
+        let RespKind::ShareNamespace(ticket) = resp
+            .result
+            .map_err(|s| anyhow::format_err!("Error from Leaf RPC endpoint: {s}"))?
+        else {
+            anyhow::bail!(INVALID_RPC_RESP_MSG);
+        };
+        Ok(ticket)
+    }
+
+    /// Join and start syncing a namespace from a ticket created by
+    /// [`share_namespace()`][Self::share_namespace].
+    pub async fn join_namespace(&self, ticket: &str) -> anyhow::Result<NamespaceId> {
+        let resp = self
+            .send_req(ReqKind::JoinNamespace(ticket.to_string()))
+            .await?;
+        let RespKind::JoinNamespace(namespace) = resp
+            .result
+            .map_err(|s| anyhow::format_err!("Error from Leaf RPC endpoint: {s}"))?
+        else {
+            anyhow::bail!(INVALID_RPC_RESP_MSG);
+        };
+        Ok(namespace)
+    }
+
+    /// List the nodes that a namespace is synced with.
+    pub async fn list_sync_peers(&self, namespace: NamespaceId) -> anyhow::Result<Vec<SyncPeer>> {
+        let resp = self.send_req(ReqKind::ListSyncPeers(namespace)).await?;
+        let RespKind::ListSyncPeers(peers) = resp
+            .result
+            .map_err(|s| anyhow::format_err!("Error from Leaf RPC endpoint: {s}"))?
+        else {
+            anyhow::bail!(INVALID_RPC_RESP_MSG);
+        };
+        Ok(peers)
+    }
+
+    /// List the namespaces in the server's store, and whether we can write to them.
+    pub async fn list_namespaces(&self) -> anyhow::Result<Vec<(NamespaceId, NamespaceCapability)>> {
+        let resp = self.send_req(ReqKind::ListNamespaces).await?;
+        let RespKind::ListNamespaces(namespaces) = resp
+            .result
+            .map_err(|s| anyhow::format_err!("Error from Leaf RPC endpoint: {s}"))?
+        else {
+            anyhow::bail!(INVALID_RPC_RESP_MSG);
+        };
+        Ok(namespaces)
+    }
+
+    /// Get the local petname and description of a namespace or subspace.
+    pub async fn get_key_metadata(&self, key: MetadataKey) -> anyhow::Result<Option<KeyMetadata>> {
+        let resp = self.send_req(ReqKind::GetKeyMetadata(key)).await?;
+        let RespKind::GetKeyMetadata(metadata) = resp
+            .result
+            .map_err(|s| anyhow::format_err!("Error from Leaf RPC endpoint: {s}"))?
+        else {
+            anyhow::bail!(INVALID_RPC_RESP_MSG);
+        };
+        Ok(metadata)
+    }
+
+    /// Set the local petname and description of a namespace or subspace, returning the updated
+    /// metadata.
+    pub async fn set_key_metadata(
+        &self,
+        key: MetadataKey,
+        petname: Option<String>,
+        description: Option<String>,
+    ) -> anyhow::Result<KeyMetadata> {
+        let resp = self
+            .send_req(ReqKind::SetKeyMetadata {
+                key,
+                petname,
+                description,
+            })
+            .await?;
+        let RespKind::SetKeyMetadata(metadata) = resp
+            .result
+            .map_err(|s| anyhow::format_err!("Error from Leaf RPC endpoint: {s}"))?
+        else {
+            anyhow::bail!(INVALID_RPC_RESP_MSG);
+        };
+        Ok(metadata)
+    }
+
+    /// List the metadata of all of the namespaces and subspaces that have it.
+    pub async fn list_key_metadata(&self) -> anyhow::Result<Vec<(MetadataKey, KeyMetadata)>> {
+        let resp = self.send_req(ReqKind::ListKeyMetadata).await?;
+        let RespKind::ListKeyMetadata(metadata) = resp
+            .result
+            .map_err(|s| anyhow::format_err!("Error from Leaf RPC endpoint: {s}"))?
+        else {
+            anyhow::bail!(INVALID_RPC_RESP_MSG);
+        };
+        Ok(metadata)
+    }
+
+    /// Get the per-operation latency histograms and byte counters of the server's store.
+    pub async fn store_metrics(&self) -> anyhow::Result<StoreMetrics> {
+        let resp = self.send_req(ReqKind::StoreMetrics).await?;
+        let RespKind::StoreMetrics(metrics) = resp
+            .result
+            .map_err(|s| anyhow::format_err!("Error from Leaf RPC endpoint: {s}"))?
+        else {
+            anyhow::bail!(INVALID_RPC_RESP_MSG);
+        };
+        Ok(metrics)
+    }
+
+    /// Get how much is stored in a subspace, along with its quota if the server has quotas
+    /// enabled.
+    pub async fn subspace_usage(
+        &self,
+        namespace: NamespaceId,
+        subspace: SubspaceId,
+    ) -> anyhow::Result<(SubspaceUsage, Option<Quota>)> {
+        let resp = self
+            .send_req(ReqKind::SubspaceUsage {
+                namespace,
+                subspace,
+            })
+            .await?;
+        let RespKind::SubspaceUsage { usage, quota } = resp
+            .result
+            .map_err(|s| anyhow::format_err!("Error from Leaf RPC endpoint: {s}"))?
+        else {
+            anyhow::bail!(INVALID_RPC_RESP_MSG);
+        };
+        Ok((usage, quota))
+    }
+
+    /// List the entities in the trash of a subspace, from the least to the most recently deleted.
+    pub async fn list_trash(
+        &self,
+        namespace: NamespaceId,
+        subspace: SubspaceId,
+    ) -> anyhow::Result<Vec<TrashEntry>> {
+        let resp = self
+            .send_req(ReqKind::ListTrash {
+                namespace,
+                subspace,
+            })
+            .await?;
+        let RespKind::ListTrash(entries) = resp
+            .result
+            .map_err(|s| anyhow::format_err!("Error from Leaf RPC endpoint: {s}"))?
+        else {
+            anyhow::bail!(INVALID_RPC_RESP_MSG);
+        };
+        Ok(entries)
+    }
+
+    /// Restore an entity from the trash to the link that it was deleted from, returning that link.
+    pub async fn restore_trash<L: Into<ExactLink>>(&self, link: L) -> anyhow::Result<ExactLink> {
+        let link = link.into();
+        let resp = self.send_req(ReqKind::RestoreTrash(link)).await?;
+        let RespKind::RestoreTrash(original) = resp
+            .result
+            .map_err(|s| anyhow::format_err!("Error from Leaf RPC endpoint: {s}"))?
+        else {
+            anyhow::bail!(INVALID_RPC_RESP_MSG);
+        };
+        Ok(original)
+    }
+
+    /// Permanently delete an entity from the trash.
+    pub async fn purge_trash<L: Into<ExactLink>>(&self, link: L) -> anyhow::Result<()> {
+        let link = link.into();
+        let resp = self.send_req(ReqKind::PurgeTrash(link)).await?;
+        let RespKind::PurgeTrash = resp
+            .result
+            .map_err(|s| anyhow::format_err!("Error from Leaf RPC endpoint: {s}"))?
+        else {
+            anyhow::bail!(INVALID_RPC_RESP_MSG);
+        };
+        Ok(())
     }
 
     // TODO: Support Operating on Multiple Components at a Time.
@@ -379,6 +667,8 @@ impl RpcClient {
     }
 }
 const INVALID_RPC_RESP_MSG: &str = "Invalid response kind from RPC endpoint";
+/// The number of entities requested per page by [`RpcClient::list_entities()`].
+const LIST_ENTITIES_PAGE_SIZE: u32 = MAX_LIST_ENTITIES_LIMIT;
 
 struct SpawnExecutor;
 
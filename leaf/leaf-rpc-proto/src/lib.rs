@@ -1,10 +1,22 @@
 use std::collections::HashMap;
 
-use leaf_protocol::types::{
-    ComponentData, Digest, Entity, EntityPath, ExactLink, NamespaceId, NamespaceSecretKey,
-    SubspaceId, SubspaceSecretKey,
+use leaf_protocol::{
+    backlinks::Backlink,
+    metadata::{KeyMetadata, MetadataKey},
+    quota::{Quota, SubspaceUsage},
+    search::SearchResult,
+    store::{GcReport, NamespaceCapability, ShareMode, StoreMetrics, SyncPeer},
+    trash::TrashEntry,
+    types::{
+        ComponentData, Digest, Entity, EntityPath, ExactLink, NamespaceId, NamespaceSecretKey,
+        SubspaceId, SubspaceSecretKey,
+    },
+    EntityPage,
 };
 
+/// The most entities that the server returns in a single [`ReqKind::ListEntities`] page.
+pub const MAX_LIST_ENTITIES_LIMIT: u32 = 1000;
+
 #[derive(borsh::BorshDeserialize, borsh::BorshSerialize, Debug)]
 pub struct Req {
     pub id: u64,
@@ -42,7 +54,26 @@ pub enum ReqKind {
         /// components.
         replace_existing: bool,
     },
-    ListEntities(ExactLink),
+    /// List the entities at or below `link`, one [`EntityPage`] at a time.
+    ///
+    /// This used to be a tuple variant holding only the link. Its encoding changed when the other
+    /// fields were added, so clients and servers from before that can't talk to each other about
+    /// listing entities.
+    ListEntities {
+        /// The entity to list the children of.
+        link: ExactLink,
+        /// Only list entities at most this many path segments below `link`. A depth of `1` lists
+        /// only the direct children. The entities that are too deep are skipped without being
+        /// read, so listing the direct children of a large tree stays cheap.
+        depth: Option<u32>,
+        /// Resume listing after the entity with this path, which should be the `next` cursor from
+        /// the previous [`EntityPage`].
+        after: Option<EntityPath>,
+        /// The maximum number of entities to return. The server returns at most
+        /// [`MAX_LIST_ENTITIES_LIMIT`] entities, which is also the limit if this is [`None`], so
+        /// keep listing with the `next` cursor of the page until it is [`None`].
+        limit: Option<u32>,
+    },
     CreateNamespace,
     ImportNamespaceSecret(NamespaceSecretKey),
     GetNamespaceSecret(NamespaceId),
@@ -52,8 +83,64 @@ pub enum ReqKind {
     GetLocalSecret(String),
     SetLocalSecret(String, Option<String>),
     ListLocalSecrets,
+    /// Dump the entities of the subspaces that we have the secret of.
+    ///
+    /// Restoring a dump re-signs every entity with the local subspace secrets. Use the server's
+    /// `/archive` endpoint to keep the signatures of the original authors instead.
     CreateDatabaseDump,
     RestoreDatabaseDump(DatabaseDump),
+    /// Full-text search the entities in a namespace.
+    Search {
+        namespace: NamespaceId,
+        query: String,
+        /// The maximum number of results to return.
+        limit: u32,
+    },
+    /// List the entities that link to an entity.
+    Backlinks {
+        link: ExactLink,
+        /// Only list links from components with this schema.
+        schema: Option<Digest>,
+    },
+    /// Remove leaked blob pins from the store.
+    CollectGarbage,
+    /// Create a ticket that another node can use to join the namespace.
+    ShareNamespace {
+        namespace: NamespaceId,
+        mode: ShareMode,
+    },
+    /// Join and start syncing a namespace from a share ticket.
+    JoinNamespace(String),
+    /// List the nodes that a namespace is synced with.
+    ListSyncPeers(NamespaceId),
+    /// List the namespaces in the store, and whether we can write to them.
+    ListNamespaces,
+    /// Get the local petname and description of a namespace or subspace.
+    GetKeyMetadata(MetadataKey),
+    /// Set the local petname and description of a namespace or subspace.
+    SetKeyMetadata {
+        key: MetadataKey,
+        petname: Option<String>,
+        description: Option<String>,
+    },
+    /// List the metadata of all namespaces and subspaces that have it.
+    ListKeyMetadata,
+    /// Get the latency and byte counters of the server's store.
+    StoreMetrics,
+    /// Get how much is stored in a subspace, and its quota if quotas are enabled.
+    SubspaceUsage {
+        namespace: NamespaceId,
+        subspace: SubspaceId,
+    },
+    /// List the entities in the trash of a subspace.
+    ListTrash {
+        namespace: NamespaceId,
+        subspace: SubspaceId,
+    },
+    /// Restore an entity from the trash to the link that it was deleted from.
+    RestoreTrash(ExactLink),
+    /// Permanently delete an entity from the trash.
+    PurgeTrash(ExactLink),
 }
 
 #[derive(borsh::BorshSerialize, borsh::BorshDeserialize, Debug)]
@@ -70,7 +157,7 @@ pub enum RespKind {
     GetComponentBySchema(Option<GetComponentsInner>),
     DelComponentBySchema(Option<Digest>),
     AddComponents(Digest),
-    ListEntities(Vec<ExactLink>),
+    ListEntities(EntityPage),
     CreateNamespace(NamespaceId),
     ImportNamespaceSecret(NamespaceId),
     GetNamespaceSecret(Option<NamespaceSecretKey>),
@@ -82,6 +169,24 @@ pub enum RespKind {
     ListLocalSecrets(HashMap<String, String>),
     CreateDatabaseDump(DatabaseDump),
     RestoreDatabaseDump,
+    Search(Vec<SearchResult>),
+    Backlinks(Vec<Backlink>),
+    CollectGarbage(GcReport),
+    ShareNamespace(String),
+    JoinNamespace(NamespaceId),
+    ListSyncPeers(Vec<SyncPeer>),
+    ListNamespaces(Vec<(NamespaceId, NamespaceCapability)>),
+    GetKeyMetadata(Option<KeyMetadata>),
+    SetKeyMetadata(KeyMetadata),
+    ListKeyMetadata(Vec<(MetadataKey, KeyMetadata)>),
+    StoreMetrics(StoreMetrics),
+    SubspaceUsage {
+        usage: SubspaceUsage,
+        quota: Option<Quota>,
+    },
+    ListTrash(Vec<TrashEntry>),
+    RestoreTrash(ExactLink),
+    PurgeTrash,
 }
 
 #[derive(borsh::BorshSerialize, borsh::BorshDeserialize, Debug, Default)]
@@ -0,0 +1,114 @@
+//! HTTP endpoints for exporting and importing the whole store as a streamed archive.
+//!
+//! Unlike the `CreateDatabaseDump` and `RestoreDatabaseDump` RPC requests, which have to fit the
+//! whole store in a single websocket frame, the archive is streamed in the HTTP body. Requests
+//! must be authenticated with an `Authorization: Bearer <api_key>` header.
+//!
+//! Entities are archived with the signatures of their subspace authors, so entities from
+//! subspaces that we don't have the secret of can be restored, as long as the store backend can
+//! write them.
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures::{channel::mpsc, io::AsyncWrite, ready, SinkExt, TryStreamExt};
+use leaf_protocol::archive::{export_archive, import_archive, ArchiveProgress};
+
+use crate::{AppResult, AppState, ARGS};
+
+/// Log progress every time this many entities have been exported or imported.
+const PROGRESS_INTERVAL: u64 = 1000;
+
+fn is_authorized(headers: &HeaderMap) -> bool {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "))
+        .map(|token| token == ARGS.api_key)
+        .unwrap_or(false)
+}
+
+fn log_progress(action: &str, progress: ArchiveProgress) {
+    if progress.entities.is_multiple_of(PROGRESS_INTERVAL) {
+        tracing::info!("{action} {} entities so far", progress.entities);
+    }
+}
+
+/// Stream an archive of the store in the response body.
+pub async fn export(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if !is_authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let (mut sender, receiver) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let writer = futures::io::BufWriter::new(ChannelWriter(sender.clone()));
+        match export_archive(&state.leaf, writer, |p| log_progress("Exported", p)).await {
+            Ok(progress) => tracing::info!("Exported archive: {progress:?}"),
+            Err(e) => {
+                tracing::error!("Error exporting archive: {e}");
+                // Fail the response body so that the client doesn't mistake the partial archive
+                // for a complete one. This waits for room in the channel rather than dropping the
+                // error when the channel is full, and only fails if the client has gone away.
+                let _ = sender.send(Err(std::io::Error::other(e.to_string()))).await;
+            }
+        }
+    });
+
+    Body::from_stream(receiver).into_response()
+}
+
+/// Import an archive from the request body.
+pub async fn import(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Body,
+) -> AppResult<Response> {
+    if !is_authorized(&headers) {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let reader = body
+        .into_data_stream()
+        .map_err(std::io::Error::other)
+        .into_async_read();
+    let progress = import_archive(&state.leaf, reader, |p| log_progress("Imported", p)).await?;
+    tracing::info!("Imported archive: {progress:?}");
+
+    Ok(format!("{progress:?}").into_response())
+}
+
+/// An [`AsyncWrite`] that sends everything written to it over a channel.
+struct ChannelWriter(mpsc::Sender<std::io::Result<Vec<u8>>>);
+
+impl AsyncWrite for ChannelWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let sender = &mut self.get_mut().0;
+        ready!(sender.poll_ready(cx)).map_err(std::io::Error::other)?;
+        sender
+            .start_send(Ok(buf.to_vec()))
+            .map_err(std::io::Error::other)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.get_mut().0.close_channel();
+        Poll::Ready(Ok(()))
+    }
+}
@@ -1,14 +1,26 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use axum::{response::IntoResponse, routing::get, Router};
 use clap::Parser;
-use futures::StreamExt;
+use futures::{StreamExt, TryStreamExt};
 use http::StatusCode;
 use leaf_protocol::{
+    backlinks::BacklinkIndex,
     borsh::BorshDeserialize,
-    iroh::{client::Iroh, docs::store::Query, node::Node},
-    prelude::{IrohDocumentKeyFormat, LeafGcPath, LeafIroh, LeafIrohStore},
+    iroh::{
+        client::Iroh,
+        docs::{store::Query, NamespaceId},
+        node::{GcPolicy, Node},
+    },
+    metadata::LeafMetadataStore,
+    prelude::{
+        IrohDocumentKeyFormat, LeafGcPath, LeafInstrumentedStore, LeafIrohStore, LeafRedbStore,
+    },
+    quota::{Quota, SubspaceQuotas},
+    search::SearchIndex,
+    store::{LeafStore, NamespaceCapability},
     types::Entity,
+    unique::UniqueConstraint,
     Leaf,
 };
 use once_cell::sync::Lazy;
@@ -16,7 +28,11 @@ use tokio::io::AsyncBufReadExt;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+use store::{Backend, ServerLeaf, ServerStore};
+
+mod archive;
 mod proto;
+mod store;
 
 #[derive(clap::Parser)]
 pub struct Args {
@@ -28,17 +44,66 @@ pub struct Args {
     pub port: u16,
     #[arg(long, env)]
     pub enable_local_store: bool,
+    /// The store backend to keep Leaf data in.
+    #[arg(long, env, value_enum, default_value_t = Backend::Iroh)]
+    pub backend: Backend,
+    /// Component schemas that must have unique values, formatted as `<scope>:<schema_id>` where
+    /// the scope is either `namespace` or `subspace`.
+    #[arg(long, env, value_delimiter = ',')]
+    pub unique_components: Vec<UniqueConstraint>,
+    /// Enable the in-memory full-text search index.
+    #[arg(long, env)]
+    pub enable_search: bool,
+    /// Enable the index of links between entities, which is persisted next to the namespace and
+    /// subspace metadata.
+    #[arg(long, env)]
+    pub enable_backlinks: bool,
+    /// Automatically maintain the `DateCreated` and `DateUpdated` components of entities when
+    /// they are changed.
+    #[arg(long, env)]
+    pub enable_timestamps: bool,
+    /// The maximum total size of the entities in each subspace, in bytes.
+    #[arg(long, env)]
+    pub quota_max_bytes: Option<u64>,
+    /// The maximum number of entities in each subspace.
+    #[arg(long, env)]
+    pub quota_max_entities: Option<u64>,
+    /// The maximum size of a single component, in bytes.
+    #[arg(long, env)]
+    pub quota_max_component_bytes: Option<u64>,
+    /// Collect leaked blob pins every this many seconds. This also enables the Iroh node's blob
+    /// garbage collector.
+    #[arg(long, env)]
+    pub gc_interval: Option<u64>,
+    /// Move deleted entities into the trash, and purge them once they have been in the trash for
+    /// this many seconds.
+    #[arg(long, env)]
+    pub trash_retention: Option<u64>,
+    /// Rewrite the document keys of every writable namespace to the current key format on
+    /// startup. Only applies to the Iroh backend.
+    #[arg(long, env)]
+    pub migrate_keys: bool,
 }
 
 pub static ARGS: Lazy<Args> = Lazy::new(Args::parse);
 pub static CLIENT: Lazy<reqwest::Client> =
     Lazy::new(|| reqwest::ClientBuilder::new().build().unwrap());
 
+/// Blob pins are only collected once they are at least this old, so that we don't collect the pins
+/// of entities that are in the middle of being saved.
+pub const GC_MIN_PIN_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// How often to purge expired entities from the trash, when the trash is enabled.
+pub const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 const SECRET_TABLE: redb::TableDefinition<&str, String> = redb::TableDefinition::new("secrets");
 
 pub type AppState = Arc<AppStateInner>;
 pub struct AppStateInner {
-    pub leaf: LeafIroh,
+    pub leaf: ServerLeaf,
+    /// The Iroh store, if it is the backend, for the operations that only it supports.
+    pub iroh: Option<LeafIrohStore>,
+    pub metadata: LeafMetadataStore,
     pub secretdb: Arc<Option<redb::Database>>,
 }
 
@@ -60,6 +125,46 @@ impl IntoResponse for AppError {
     }
 }
 
+/// Rewrite the keys of every writable namespace in the Iroh store to the current key format.
+async fn migrate_keys(store: &LeafIrohStore) -> anyhow::Result<()> {
+    let namespaces = store
+        .list_namespaces()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    for (namespace, capability) in namespaces {
+        if capability != NamespaceCapability::Write {
+            continue;
+        }
+        let report = store.migrate_keys(namespace).await?;
+        tracing::info!(
+            "Migrated keys of namespace {}: {report:?}",
+            NamespaceId::from(namespace)
+        );
+    }
+    Ok(())
+}
+
+/// Purge the expired entities from the trash of every subspace that we can write to.
+async fn purge_expired_trash(leaf: &ServerLeaf) -> anyhow::Result<u64> {
+    let namespaces = leaf
+        .list_namespaces()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    let subspaces = leaf.list_subspaces().await?.try_collect::<Vec<_>>().await?;
+    let mut purged = 0;
+    for (namespace, capability) in namespaces {
+        if capability != NamespaceCapability::Write {
+            continue;
+        }
+        for &subspace in &subspaces {
+            purged += leaf.purge_expired_trash(namespace, subspace).await?;
+        }
+    }
+    Ok(purged)
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Init logger
@@ -77,10 +182,58 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Parse CLI args.
     let args = &*ARGS;
 
-    // Initialize the leaf store and Iroh node
-    let node = Node::persistent(&ARGS.data_dir).await?.spawn().await?;
-    let leaf_store = LeafIrohStore::new(node.client().clone());
-    let leaf = Leaf::new(leaf_store);
+    // Initialize the leaf store, the database for the namespace and subspace metadata and other
+    // local indexes, and the Iroh node and store if we are using them
+    let (leaf_store, local_db, node, iroh): (ServerStore, _, _, _) = match ARGS.backend {
+        Backend::Iroh => {
+            let mut builder = Node::persistent(&ARGS.data_dir).await?;
+            if let Some(interval) = ARGS.gc_interval {
+                builder = builder.gc_policy(GcPolicy::Interval(Duration::from_secs(interval)));
+            }
+            let node = builder.spawn().await?;
+            let store = LeafIrohStore::new(node.client().clone());
+            if ARGS.migrate_keys {
+                migrate_keys(&store).await?;
+            }
+            let local_db = Arc::new(redb::Database::create(ARGS.data_dir.join("metadata.redb"))?);
+            (Box::new(store.clone()), local_db, Some(node), Some(store))
+        }
+        Backend::Redb => {
+            tracing::info!(
+                "Using the redb backend. Note that namespaces are **not** synced with other peers."
+            );
+            std::fs::create_dir_all(&ARGS.data_dir)?;
+            let store = LeafRedbStore::open(ARGS.data_dir.join("leaf.redb"))?;
+            // Keep the namespace and subspace metadata in the same database as the store.
+            let local_db = store.database().clone();
+            (Box::new(store), local_db, None, None)
+        }
+    };
+    let metadata = LeafMetadataStore::new(local_db.clone())?;
+    let mut leaf = ARGS.unique_components.iter().fold(
+        Leaf::new(LeafInstrumentedStore::new(leaf_store)),
+        |leaf, &constraint| leaf.with_unique_constraint(constraint),
+    );
+    if ARGS.enable_search {
+        leaf = leaf.with_search_index(SearchIndex::default());
+    }
+    if ARGS.enable_backlinks {
+        leaf = leaf.with_backlink_index(BacklinkIndex::default().with_database(local_db.clone())?);
+    }
+    if ARGS.enable_timestamps {
+        leaf = leaf.with_timestamps();
+    }
+    if let Some(retention) = ARGS.trash_retention {
+        leaf = leaf.with_trash(Duration::from_secs(retention));
+    }
+    let quota = Quota {
+        max_bytes: ARGS.quota_max_bytes,
+        max_entities: ARGS.quota_max_entities,
+        max_component_bytes: ARGS.quota_max_component_bytes,
+    };
+    if quota != Quota::default() {
+        leaf = leaf.with_quotas(SubspaceQuotas::new(quota).with_database(local_db)?);
+    }
 
     let secretdb = if ARGS.enable_local_store {
         tracing::info!(
@@ -100,15 +253,53 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Arc::new(None)
     };
 
+    // Spawn a task to collect leaked blob pins
+    if let Some(interval) = ARGS.gc_interval {
+        let iroh = iroh.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval));
+            loop {
+                interval.tick().await;
+                match store::collect_garbage(iroh.as_ref(), GC_MIN_PIN_AGE).await {
+                    Ok(report) => tracing::debug!("Collected garbage: {report:?}"),
+                    Err(e) => tracing::error!("Error collecting garbage: {e}"),
+                }
+            }
+        });
+    }
+
+    // Spawn a task to purge expired entities from the trash
+    if ARGS.trash_retention.is_some() {
+        let leaf = leaf.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TRASH_PURGE_INTERVAL);
+            loop {
+                interval.tick().await;
+                match purge_expired_trash(&leaf).await {
+                    Ok(purged) => tracing::debug!("Purged {purged} entities from the trash"),
+                    Err(e) => tracing::error!("Error purging the trash: {e}"),
+                }
+            }
+        });
+    }
+
     // Spawn a task to handle the debug CLI commands
-    let iroh = node.client().clone();
-    tokio::spawn(handle_cli_prompts(iroh));
+    if let Some(node) = &node {
+        let iroh = node.client().clone();
+        tokio::spawn(handle_cli_prompts(iroh));
+    }
 
     // Construct router
     let router = Router::new()
         .route("/", get(proto::ws_handler))
+        .route("/archive", get(archive::export).post(archive::import))
         .layer(TraceLayer::new_for_http())
-        .with_state(Arc::new(AppStateInner { leaf, secretdb }));
+        .with_state(Arc::new(AppStateInner {
+            leaf,
+            iroh,
+            metadata,
+            secretdb,
+        }));
 
     let listener = tokio::net::TcpListener::bind(("0.0.0.0", args.port)).await?;
     tracing::info!("Starting server on port {}", args.port);
@@ -2,11 +2,18 @@ use std::{collections::HashMap, sync::Arc};
 
 use axum::{extract::State, response::IntoResponse};
 use fastwebsockets::{Frame, OpCode, Payload, WebSocketError};
-use futures::{pin_mut, StreamExt};
-use leaf_protocol::prelude::*;
+use futures::{pin_mut, StreamExt, TryStreamExt};
+use leaf_protocol::{
+    metadata::{LeafMetadataStore, MetadataKey},
+    prelude::*,
+    store::ShareMode,
+};
 use leaf_rpc_proto::*;
 
-use crate::{AppState, ARGS, SECRET_TABLE};
+use crate::{
+    store::{self, ServerLeaf},
+    AppState, ARGS, GC_MIN_PIN_AGE, SECRET_TABLE,
+};
 
 pub async fn ws_handler(
     state: State<AppState>,
@@ -28,6 +35,8 @@ async fn handle_client(
     fut: fastwebsockets::upgrade::UpgradeFut,
 ) -> Result<(), WebSocketError> {
     let leaf = &state.leaf;
+    let iroh = state.iroh.as_ref();
+    let metadata = &state.metadata;
     let secretdb = state.secretdb.clone();
     let mut ws = fastwebsockets::FragmentCollector::new(fut.await?);
     let mut authenticated = false;
@@ -49,7 +58,7 @@ async fn handle_client(
                     let req = Req::deserialize(&mut &*frame.payload)?;
 
                     if authenticated {
-                        let resp = handle_req(leaf, secretdb.clone(), req).await;
+                        let resp = handle_req(leaf, iroh, metadata, secretdb.clone(), req).await;
                         let mut buf = Vec::new();
                         resp.serialize(&mut buf)?;
                         ws.write_frame(Frame::binary(Payload::Owned(buf))).await?;
@@ -104,7 +113,13 @@ async fn handle_client(
     Ok(())
 }
 
-async fn handle_req(leaf: &LeafIroh, secretdb: Arc<Option<redb::Database>>, req: Req) -> Resp {
+async fn handle_req(
+    leaf: &ServerLeaf,
+    iroh: Option<&LeafIrohStore>,
+    metadata: &LeafMetadataStore,
+    secretdb: Arc<Option<redb::Database>>,
+    req: Req,
+) -> Resp {
     let kind = match req.kind {
         ReqKind::Authenticate(_) => {
             // TODO: we can hit this somehow when restarting the RPC server while Weird tries to
@@ -124,18 +139,56 @@ async fn handle_req(leaf: &LeafIroh, secretdb: Arc<Option<redb::Database>>, req:
             components,
             replace_existing,
         } => add_components(leaf, link, components, replace_existing).await,
-        ReqKind::ListEntities(link) => list_entities(leaf, link).await,
-        ReqKind::CreateNamespace => create_namespace(leaf).await,
-        ReqKind::ImportNamespaceSecret(secret) => import_namespace_secret(leaf, secret).await,
+        ReqKind::ListEntities {
+            link,
+            depth,
+            after,
+            limit,
+        } => list_entities(leaf, link, depth, after, limit).await,
+        ReqKind::CreateNamespace => create_namespace(leaf, metadata).await,
+        ReqKind::ImportNamespaceSecret(secret) => {
+            import_namespace_secret(leaf, metadata, secret).await
+        }
         ReqKind::GetNamespaceSecret(namespace) => get_namespace_secret(leaf, namespace).await,
-        ReqKind::CreateSubspace => create_subspace(leaf).await,
-        ReqKind::ImportSubspaceSecret(secret) => import_subspace_secret(leaf, secret).await,
+        ReqKind::CreateSubspace => create_subspace(leaf, metadata).await,
+        ReqKind::ImportSubspaceSecret(secret) => {
+            import_subspace_secret(leaf, metadata, secret).await
+        }
         ReqKind::GetSubspaceSecret(subspace) => get_subspace_secret(leaf, subspace).await,
         ReqKind::GetLocalSecret(key) => get_local_secret(secretdb, key).await,
         ReqKind::SetLocalSecret(key, value) => set_local_secret(secretdb, key, value).await,
         ReqKind::ListLocalSecrets => list_local_secrets(secretdb).await,
         ReqKind::CreateDatabaseDump => create_database_dump(leaf).await,
         ReqKind::RestoreDatabaseDump(dump) => restore_database_dump(leaf, dump).await,
+        ReqKind::Search {
+            namespace,
+            query,
+            limit,
+        } => search(leaf, namespace, query, limit).await,
+        ReqKind::Backlinks { link, schema } => backlinks(leaf, link, schema).await,
+        ReqKind::CollectGarbage => collect_garbage(iroh).await,
+        ReqKind::ShareNamespace { namespace, mode } => share_namespace(leaf, namespace, mode).await,
+        ReqKind::JoinNamespace(ticket) => join_namespace(leaf, metadata, ticket).await,
+        ReqKind::ListSyncPeers(namespace) => list_sync_peers(leaf, namespace).await,
+        ReqKind::ListNamespaces => list_namespaces(leaf).await,
+        ReqKind::GetKeyMetadata(key) => get_key_metadata(metadata, key).await,
+        ReqKind::SetKeyMetadata {
+            key,
+            petname,
+            description,
+        } => set_key_metadata(metadata, key, petname, description).await,
+        ReqKind::ListKeyMetadata => list_key_metadata(metadata).await,
+        ReqKind::StoreMetrics => store_metrics(leaf).await,
+        ReqKind::SubspaceUsage {
+            namespace,
+            subspace,
+        } => subspace_usage(leaf, namespace, subspace).await,
+        ReqKind::ListTrash {
+            namespace,
+            subspace,
+        } => list_trash(leaf, namespace, subspace).await,
+        ReqKind::RestoreTrash(link) => restore_trash(leaf, link).await,
+        ReqKind::PurgeTrash(link) => purge_trash(leaf, link).await,
     };
     Resp {
         id: req.id,
@@ -143,7 +196,7 @@ async fn handle_req(leaf: &LeafIroh, secretdb: Arc<Option<redb::Database>>, req:
     }
 }
 
-async fn read_entity(leaf: &LeafIroh, link: ExactLink) -> anyhow::Result<RespKind> {
+async fn read_entity(leaf: &ServerLeaf, link: ExactLink) -> anyhow::Result<RespKind> {
     let entry = leaf.entity(link).await?;
     let entity = entry
         .entity()
@@ -152,14 +205,14 @@ async fn read_entity(leaf: &LeafIroh, link: ExactLink) -> anyhow::Result<RespKin
     Ok(RespKind::ReadEntity(entity))
 }
 async fn del_entity(
-    leaf: &Leaf<LeafIrohStore>,
+    leaf: &ServerLeaf,
     link: ExactLink,
 ) -> std::result::Result<RespKind, anyhow::Error> {
     leaf.del_entity(link).await?;
     Ok(RespKind::DelEntity)
 }
 async fn get_components_by_schema(
-    leaf: &LeafIroh,
+    leaf: &ServerLeaf,
     link: ExactLink,
     schemas: Vec<Digest>,
 ) -> anyhow::Result<RespKind> {
@@ -177,7 +230,7 @@ async fn get_components_by_schema(
     })))
 }
 async fn del_components_by_schema(
-    leaf: &LeafIroh,
+    leaf: &ServerLeaf,
     link: ExactLink,
     schemas: Vec<Digest>,
 ) -> anyhow::Result<RespKind> {
@@ -194,7 +247,7 @@ async fn del_components_by_schema(
     Ok(RespKind::DelComponentBySchema(resp))
 }
 async fn add_components(
-    leaf: &LeafIroh,
+    leaf: &ServerLeaf,
     link: ExactLink,
     components: Vec<ComponentData>,
     replace_existing: bool,
@@ -209,31 +262,139 @@ async fn add_components(
     entity.save().await?;
     Ok(RespKind::AddComponents(entity.digest))
 }
-async fn list_entities(leaf: &LeafIroh, link: ExactLink) -> anyhow::Result<RespKind> {
-    let entities = leaf
-        .list(link)
-        .await?
-        .collect::<Vec<_>>()
-        .await
-        .into_iter()
-        .collect::<Result<Vec<_>, _>>()?;
-    Ok(RespKind::ListEntities(entities))
+async fn list_entities(
+    leaf: &ServerLeaf,
+    link: ExactLink,
+    depth: Option<u32>,
+    after: Option<EntityPath>,
+    limit: Option<u32>,
+) -> anyhow::Result<RespKind> {
+    // Pages are capped so that a single request can't make us collect a whole namespace.
+    let limit = limit
+        .unwrap_or(MAX_LIST_ENTITIES_LIMIT)
+        .min(MAX_LIST_ENTITIES_LIMIT);
+    let page = leaf.list_page(link, depth, after, limit as u64).await?;
+    Ok(RespKind::ListEntities(page))
+}
+async fn search(
+    leaf: &ServerLeaf,
+    namespace: NamespaceId,
+    query: String,
+    limit: u32,
+) -> anyhow::Result<RespKind> {
+    let results = leaf.search(namespace, &query, limit as usize).await?;
+    Ok(RespKind::Search(results))
+}
+async fn backlinks(
+    leaf: &ServerLeaf,
+    link: ExactLink,
+    schema: Option<Digest>,
+) -> anyhow::Result<RespKind> {
+    let backlinks = leaf.backlinks(link, schema).await?;
+    Ok(RespKind::Backlinks(backlinks))
+}
+
+async fn collect_garbage(iroh: Option<&LeafIrohStore>) -> anyhow::Result<RespKind> {
+    let report = store::collect_garbage(iroh, GC_MIN_PIN_AGE).await?;
+    Ok(RespKind::CollectGarbage(report))
+}
+
+async fn store_metrics(leaf: &ServerLeaf) -> anyhow::Result<RespKind> {
+    Ok(RespKind::StoreMetrics(leaf.store.metrics()))
+}
+async fn subspace_usage(
+    leaf: &ServerLeaf,
+    namespace: NamespaceId,
+    subspace: SubspaceId,
+) -> anyhow::Result<RespKind> {
+    let usage = leaf.subspace_usage(namespace, subspace).await?;
+    let quota = leaf.config.quotas.as_ref().map(|x| x.quota(subspace));
+    Ok(RespKind::SubspaceUsage { usage, quota })
+}
+async fn list_trash(
+    leaf: &ServerLeaf,
+    namespace: NamespaceId,
+    subspace: SubspaceId,
+) -> anyhow::Result<RespKind> {
+    Ok(RespKind::ListTrash(
+        leaf.list_trash(namespace, subspace).await?,
+    ))
+}
+async fn restore_trash(leaf: &ServerLeaf, link: ExactLink) -> anyhow::Result<RespKind> {
+    Ok(RespKind::RestoreTrash(leaf.restore_trash(link).await?))
+}
+async fn purge_trash(leaf: &ServerLeaf, link: ExactLink) -> anyhow::Result<RespKind> {
+    leaf.purge_trash(link).await?;
+    Ok(RespKind::PurgeTrash)
+}
+async fn share_namespace(
+    leaf: &ServerLeaf,
+    namespace: NamespaceId,
+    mode: ShareMode,
+) -> anyhow::Result<RespKind> {
+    Ok(RespKind::ShareNamespace(
+        leaf.share_namespace(namespace, mode).await?,
+    ))
+}
+
+async fn join_namespace(
+    leaf: &ServerLeaf,
+    metadata: &LeafMetadataStore,
+    ticket: String,
+) -> anyhow::Result<RespKind> {
+    let namespace = leaf.join_namespace(&ticket).await?;
+    metadata.record(MetadataKey::Namespace(namespace)).await?;
+    Ok(RespKind::JoinNamespace(namespace))
+}
+
+async fn list_sync_peers(leaf: &ServerLeaf, namespace: NamespaceId) -> anyhow::Result<RespKind> {
+    Ok(RespKind::ListSyncPeers(
+        leaf.list_sync_peers(namespace).await?,
+    ))
+}
+
+async fn list_namespaces(leaf: &ServerLeaf) -> anyhow::Result<RespKind> {
+    let namespaces = leaf.list_namespaces().await?.try_collect().await?;
+    Ok(RespKind::ListNamespaces(namespaces))
+}
+async fn get_key_metadata(
+    metadata: &LeafMetadataStore,
+    key: MetadataKey,
+) -> anyhow::Result<RespKind> {
+    Ok(RespKind::GetKeyMetadata(metadata.get(key).await?))
+}
+async fn set_key_metadata(
+    metadata: &LeafMetadataStore,
+    key: MetadataKey,
+    petname: Option<String>,
+    description: Option<String>,
+) -> anyhow::Result<RespKind> {
+    Ok(RespKind::SetKeyMetadata(
+        metadata.set(key, petname, description).await?,
+    ))
+}
+async fn list_key_metadata(metadata: &LeafMetadataStore) -> anyhow::Result<RespKind> {
+    Ok(RespKind::ListKeyMetadata(metadata.list().await?))
 }
 async fn create_namespace(
-    leaf: &Leaf<LeafIrohStore>,
+    leaf: &ServerLeaf,
+    metadata: &LeafMetadataStore,
 ) -> std::result::Result<RespKind, anyhow::Error> {
-    Ok(RespKind::CreateNamespace(leaf.create_namespace().await?))
+    let namespace = leaf.create_namespace().await?;
+    metadata.record(MetadataKey::Namespace(namespace)).await?;
+    Ok(RespKind::CreateNamespace(namespace))
 }
 async fn import_namespace_secret(
-    leaf: &Leaf<LeafIrohStore>,
+    leaf: &ServerLeaf,
+    metadata: &LeafMetadataStore,
     secret: [u8; 32],
 ) -> std::result::Result<RespKind, anyhow::Error> {
-    Ok(RespKind::ImportNamespaceSecret(
-        leaf.import_namespace_secret(secret).await?,
-    ))
+    let namespace = leaf.import_namespace_secret(secret).await?;
+    metadata.record(MetadataKey::Namespace(namespace)).await?;
+    Ok(RespKind::ImportNamespaceSecret(namespace))
 }
 async fn get_namespace_secret(
-    leaf: &Leaf<LeafIrohStore>,
+    leaf: &ServerLeaf,
     namespace: [u8; 32],
 ) -> std::result::Result<RespKind, anyhow::Error> {
     Ok(RespKind::GetNamespaceSecret(
@@ -241,20 +402,24 @@ async fn get_namespace_secret(
     ))
 }
 async fn create_subspace(
-    leaf: &Leaf<LeafIrohStore>,
+    leaf: &ServerLeaf,
+    metadata: &LeafMetadataStore,
 ) -> std::result::Result<RespKind, anyhow::Error> {
-    Ok(RespKind::CreateSubspace(leaf.create_subspace().await?))
+    let subspace = leaf.create_subspace().await?;
+    metadata.record(MetadataKey::Subspace(subspace)).await?;
+    Ok(RespKind::CreateSubspace(subspace))
 }
 async fn import_subspace_secret(
-    leaf: &Leaf<LeafIrohStore>,
+    leaf: &ServerLeaf,
+    metadata: &LeafMetadataStore,
     secret: [u8; 32],
 ) -> std::result::Result<RespKind, anyhow::Error> {
-    Ok(RespKind::ImportSubspaceSecret(
-        leaf.import_subspace_secret(secret).await?,
-    ))
+    let subspace = leaf.import_subspace_secret(secret).await?;
+    metadata.record(MetadataKey::Subspace(subspace)).await?;
+    Ok(RespKind::ImportSubspaceSecret(subspace))
 }
 async fn get_subspace_secret(
-    leaf: &Leaf<LeafIrohStore>,
+    leaf: &ServerLeaf,
     subspace: [u8; 32],
 ) -> std::result::Result<RespKind, anyhow::Error> {
     Ok(RespKind::GetSubspaceSecret(
@@ -336,7 +501,7 @@ async fn list_local_secrets(
     .map_err(|_| anyhow::format_err!("Error executing database operation"))?
 }
 
-async fn create_database_dump(leaf: &Leaf<LeafIrohStore>) -> anyhow::Result<RespKind> {
+async fn create_database_dump(leaf: &ServerLeaf) -> anyhow::Result<RespKind> {
     let mut dump = DatabaseDump::default();
 
     let mut stream = leaf.list_subspaces().await?;
@@ -350,7 +515,7 @@ async fn create_database_dump(leaf: &Leaf<LeafIrohStore>) -> anyhow::Result<Resp
 
     let mut stream = leaf.list_namespaces().await?;
     while let Some(namespace) = stream.next().await {
-        let namespace = namespace?;
+        let (namespace, _) = namespace?;
         let Some(namespace_secret) = leaf.get_namespace_secret(namespace).await? else {
             tracing::warn!(
                 "Skipping namespace {namespace:?} in database dump \
@@ -371,7 +536,7 @@ async fn create_database_dump(leaf: &Leaf<LeafIrohStore>) -> anyhow::Result<Resp
                 subspace,
                 path: EntityPath::default(),
             };
-            let stream = leaf.list(link).await?;
+            let stream = leaf.list(link, None, None).await?;
             pin_mut!(stream);
             while let Some(link) = stream.next().await {
                 let link = link?;
@@ -400,10 +565,7 @@ async fn create_database_dump(leaf: &Leaf<LeafIrohStore>) -> anyhow::Result<Resp
     Ok(RespKind::CreateDatabaseDump(dump))
 }
 
-async fn restore_database_dump(
-    leaf: &Leaf<LeafIrohStore>,
-    dump: DatabaseDump,
-) -> anyhow::Result<RespKind> {
+async fn restore_database_dump(leaf: &ServerLeaf, dump: DatabaseDump) -> anyhow::Result<RespKind> {
     for (subspace, secret) in dump.subspace_secrets {
         let s = leaf.import_subspace_secret(secret).await?;
         if s != subspace {
@@ -0,0 +1,37 @@
+//! The Leaf store used by the server, which may be any of the supported backends.
+
+use std::time::Duration;
+
+use leaf_protocol::{
+    prelude::*,
+    store::{boxed::DynLeafStore, GcReport},
+};
+
+/// The server's [`Leaf`], which records metrics for every call to its store.
+pub type ServerLeaf = Leaf<LeafInstrumentedStore<ServerStore>>;
+
+/// The store of whichever backend was selected when the server was started.
+pub type ServerStore = Box<dyn DynLeafStore>;
+
+/// The store backend to use.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Store data in an Iroh node, which can sync namespaces with other peers.
+    Iroh,
+    /// Store data in a single redb database file, without running an Iroh node.
+    Redb,
+}
+
+/// Remove leaked blob pins that are older than `min_age` from the Iroh store, if it is in use.
+///
+/// The redb store cleans up its pins in the same transaction that replaces an entity, so it
+/// never has anything to collect.
+pub async fn collect_garbage(
+    iroh: Option<&LeafIrohStore>,
+    min_age: Duration,
+) -> anyhow::Result<GcReport> {
+    match iroh {
+        Some(store) => store.collect_garbage(min_age).await,
+        None => Ok(GcReport::default()),
+    }
+}
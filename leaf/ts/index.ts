@@ -111,6 +111,60 @@ export const DatabaseDumpSchema = BorshSchema.Struct({
 	subspace_secrets: BorshSchema.HashMap(SubspaceIdSchema, SubspaceSecretKeySchema)
 });
 
+export type ShareMode = { Read: Unit } | { Write: Unit };
+export const ShareModeSchema = BorshSchema.Enum({
+	Read: BorshSchema.Unit,
+	Write: BorshSchema.Unit
+});
+
+export type MetadataKey = { Namespace: NamespaceId } | { Subspace: SubspaceId };
+export const MetadataKeySchema = BorshSchema.Enum({
+	Namespace: NamespaceIdSchema,
+	Subspace: SubspaceIdSchema
+});
+
+export type KeyMetadata = {
+	petname: string | null;
+	description: string | null;
+	created_at: bigint;
+};
+export const KeyMetadataSchema = BorshSchema.Struct({
+	petname: BorshSchema.Option(BorshSchema.String),
+	description: BorshSchema.Option(BorshSchema.String),
+	created_at: BorshSchema.u64
+});
+
+export type Quota = {
+	max_bytes: bigint | null;
+	max_entities: bigint | null;
+	max_component_bytes: bigint | null;
+};
+export const QuotaSchema = BorshSchema.Struct({
+	max_bytes: BorshSchema.Option(BorshSchema.u64),
+	max_entities: BorshSchema.Option(BorshSchema.u64),
+	max_component_bytes: BorshSchema.Option(BorshSchema.u64)
+});
+
+export type SubspaceUsage = {
+	bytes: bigint;
+	entities: bigint;
+};
+export const SubspaceUsageSchema = BorshSchema.Struct({
+	bytes: BorshSchema.u64,
+	entities: BorshSchema.u64
+});
+
+export type TrashEntry = {
+	link: ExactLink;
+	original: ExactLink;
+	deleted_at: bigint;
+};
+export const TrashEntrySchema = BorshSchema.Struct({
+	link: ExactLinkSchema,
+	original: ExactLinkSchema,
+	deleted_at: BorshSchema.u64
+});
+
 export type ReqKind =
 	| { Authenticate: string }
 	| { ReadEntity: ExactLink }
@@ -118,7 +172,9 @@ export type ReqKind =
 	| { GetComponentsBySchema: { link: ExactLink; schemas: Digest[] } }
 	| { DelComponentsBySchema: { link: ExactLink; schemas: Digest[] } }
 	| { AddComponents: { link: ExactLink; components: ComponentData[]; replace_existing: boolean } }
-	| { ListEntities: ExactLink }
+	| {
+			ListEntities: { link: ExactLink; depth?: number; after?: EntityPath; limit?: number };
+	  }
 	| { CreateNamespace: Unit }
 	| { ImportNamespaceSecret: NamespaceId }
 	| { GetNamespaceSecret: NamespaceSecretKey }
@@ -129,7 +185,28 @@ export type ReqKind =
 	| { SetLocalSecret: { key: string; value?: string } }
 	| { ListLocalSecrets: Unit }
 	| { CreateDatabaseDump: Unit }
-	| { RestoreDatabaseDump: DatabaseDump };
+	| { RestoreDatabaseDump: DatabaseDump }
+	| { Search: { namespace: NamespaceId; query: string; limit: number } }
+	| { Backlinks: { link: ExactLink; schema?: Digest } }
+	| { CollectGarbage: Unit }
+	| { ShareNamespace: { namespace: NamespaceId; mode: ShareMode } }
+	| { JoinNamespace: string }
+	| { ListSyncPeers: NamespaceId }
+	| { ListNamespaces: Unit }
+	| { GetKeyMetadata: MetadataKey }
+	| {
+			SetKeyMetadata: {
+				key: MetadataKey;
+				petname: string | null;
+				description: string | null;
+			};
+	  }
+	| { ListKeyMetadata: Unit }
+	| { StoreMetrics: Unit }
+	| { SubspaceUsage: { namespace: NamespaceId; subspace: SubspaceId } }
+	| { ListTrash: { namespace: NamespaceId; subspace: SubspaceId } }
+	| { RestoreTrash: ExactLink }
+	| { PurgeTrash: ExactLink };
 export const ReqKindSchema = BorshSchema.Enum({
 	Authenticate: BorshSchema.String,
 	ReadEntity: ExactLinkSchema,
@@ -147,7 +224,12 @@ export const ReqKindSchema = BorshSchema.Enum({
 		components: BorshSchema.Vec(ComponentDataSchema),
 		replace_existing: BorshSchema.bool
 	}),
-	ListEntities: ExactLinkSchema,
+	ListEntities: BorshSchema.Struct({
+		link: ExactLinkSchema,
+		depth: BorshSchema.Option(BorshSchema.u32),
+		after: BorshSchema.Option(EntityPathSchema),
+		limit: BorshSchema.Option(BorshSchema.u32)
+	}),
 	CreateNamespace: BorshSchema.Unit,
 	ImportNamespaceSecret: NamespaceSecretKeySchema,
 	GetNamespaceSecret: NamespaceIdSchema,
@@ -161,7 +243,42 @@ export const ReqKindSchema = BorshSchema.Enum({
 	}),
 	ListLocalSecrets: BorshSchema.Unit,
 	CreateDatabaseDump: BorshSchema.Unit,
-	RestoreDatabaseDump: DatabaseDumpSchema
+	RestoreDatabaseDump: DatabaseDumpSchema,
+	Search: BorshSchema.Struct({
+		namespace: NamespaceIdSchema,
+		query: BorshSchema.String,
+		limit: BorshSchema.u32
+	}),
+	Backlinks: BorshSchema.Struct({
+		link: ExactLinkSchema,
+		schema: BorshSchema.Option(DigestSchema)
+	}),
+	CollectGarbage: BorshSchema.Unit,
+	ShareNamespace: BorshSchema.Struct({
+		namespace: NamespaceIdSchema,
+		mode: ShareModeSchema
+	}),
+	JoinNamespace: BorshSchema.String,
+	ListSyncPeers: NamespaceIdSchema,
+	ListNamespaces: BorshSchema.Unit,
+	GetKeyMetadata: MetadataKeySchema,
+	SetKeyMetadata: BorshSchema.Struct({
+		key: MetadataKeySchema,
+		petname: BorshSchema.Option(BorshSchema.String),
+		description: BorshSchema.Option(BorshSchema.String)
+	}),
+	ListKeyMetadata: BorshSchema.Unit,
+	StoreMetrics: BorshSchema.Unit,
+	SubspaceUsage: BorshSchema.Struct({
+		namespace: NamespaceIdSchema,
+		subspace: SubspaceIdSchema
+	}),
+	ListTrash: BorshSchema.Struct({
+		namespace: NamespaceIdSchema,
+		subspace: SubspaceIdSchema
+	}),
+	RestoreTrash: ExactLinkSchema,
+	PurgeTrash: ExactLinkSchema
 });
 
 export type Req = {
@@ -208,6 +325,143 @@ export const GetComponentsInnerSchema = BorshSchema.Struct({
 	components: BorshSchema.HashMap(DigestSchema, BorshSchema.Vec(BorshSchema.Vec(BorshSchema.u8)))
 });
 
+export type SearchResult = {
+	link: ExactLink;
+	score: number;
+};
+export const SearchResultSchema = BorshSchema.Struct({
+	link: ExactLinkSchema,
+	score: BorshSchema.f32
+});
+
+export type Backlink = {
+	link: ExactLink;
+	schema: Digest;
+};
+export const BacklinkSchema = BorshSchema.Struct({
+	link: ExactLinkSchema,
+	schema: DigestSchema
+});
+
+export type EntityPage = {
+	entities: ExactLink[];
+	next: EntityPath | null;
+};
+export const EntityPageSchema = BorshSchema.Struct({
+	entities: BorshSchema.Vec(ExactLinkSchema),
+	next: BorshSchema.Option(EntityPathSchema)
+});
+
+export type GcReport = {
+	pins: bigint;
+	blobs: bigint;
+	bytes: bigint;
+};
+export const GcReportSchema = BorshSchema.Struct({
+	pins: BorshSchema.u64,
+	blobs: BorshSchema.u64,
+	bytes: BorshSchema.u64
+});
+
+export type NamespaceCapability = { Read: Unit } | { Write: Unit };
+export const NamespaceCapabilitySchema = BorshSchema.Enum({
+	Read: BorshSchema.Unit,
+	Write: BorshSchema.Unit
+});
+
+export type PeerConnection = { None: Unit } | { Direct: Unit } | { Relay: Unit } | { Mixed: Unit };
+export const PeerConnectionSchema = BorshSchema.Enum({
+	None: BorshSchema.Unit,
+	Direct: BorshSchema.Unit,
+	Relay: BorshSchema.Unit,
+	Mixed: BorshSchema.Unit
+});
+
+export type SyncPeer = {
+	node_id: Uint8Array;
+	connection: PeerConnection;
+	latency_ms: bigint | null;
+};
+export const SyncPeerSchema = BorshSchema.Struct({
+	node_id: BorshSchema.Array(BorshSchema.u8, 32),
+	connection: PeerConnectionSchema,
+	latency_ms: BorshSchema.Option(BorshSchema.u64)
+});
+
+export type StoreOperation =
+	| { CreateSubspace: Unit }
+	| { GetSubspaceSecret: Unit }
+	| { ListSubspaces: Unit }
+	| { ImportSubspaceSecret: Unit }
+	| { CreateNamespace: Unit }
+	| { ListNamespaces: Unit }
+	| { GetNamespaceSecret: Unit }
+	| { ImportNamespaceSecret: Unit }
+	| { ListNamespaceSubspaces: Unit }
+	| { StoreBlob: Unit }
+	| { DelBlobs: Unit }
+	| { GetBlob: Unit }
+	| { StoreEntity: Unit }
+	| { DelEntity: Unit }
+	| { GetEntity: Unit }
+	| { List: Unit }
+	| { ShareNamespace: Unit }
+	| { JoinNamespace: Unit }
+	| { ListSyncPeers: Unit }
+	| { StoreSignedEntity: Unit }
+	| { GetEntitySignature: Unit };
+export const StoreOperationSchema = BorshSchema.Enum({
+	CreateSubspace: BorshSchema.Unit,
+	GetSubspaceSecret: BorshSchema.Unit,
+	ListSubspaces: BorshSchema.Unit,
+	ImportSubspaceSecret: BorshSchema.Unit,
+	CreateNamespace: BorshSchema.Unit,
+	ListNamespaces: BorshSchema.Unit,
+	GetNamespaceSecret: BorshSchema.Unit,
+	ImportNamespaceSecret: BorshSchema.Unit,
+	ListNamespaceSubspaces: BorshSchema.Unit,
+	StoreBlob: BorshSchema.Unit,
+	DelBlobs: BorshSchema.Unit,
+	GetBlob: BorshSchema.Unit,
+	StoreEntity: BorshSchema.Unit,
+	DelEntity: BorshSchema.Unit,
+	GetEntity: BorshSchema.Unit,
+	List: BorshSchema.Unit,
+	ShareNamespace: BorshSchema.Unit,
+	JoinNamespace: BorshSchema.Unit,
+	ListSyncPeers: BorshSchema.Unit,
+	StoreSignedEntity: BorshSchema.Unit,
+	GetEntitySignature: BorshSchema.Unit
+});
+
+export type OperationMetrics = {
+	operation: StoreOperation;
+	calls: bigint;
+	errors: bigint;
+	total_us: bigint;
+	latency_buckets: bigint[];
+};
+export const OperationMetricsSchema = BorshSchema.Struct({
+	operation: StoreOperationSchema,
+	calls: BorshSchema.u64,
+	errors: BorshSchema.u64,
+	total_us: BorshSchema.u64,
+	latency_buckets: BorshSchema.Vec(BorshSchema.u64)
+});
+
+export type StoreMetrics = {
+	operations: OperationMetrics[];
+	blob_bytes_read: bigint;
+	blob_bytes_written: bigint;
+	entity_bytes_written: bigint;
+};
+export const StoreMetricsSchema = BorshSchema.Struct({
+	operations: BorshSchema.Vec(OperationMetricsSchema),
+	blob_bytes_read: BorshSchema.u64,
+	blob_bytes_written: BorshSchema.u64,
+	entity_bytes_written: BorshSchema.u64
+});
+
 export type RespKind =
 	| { Authenticated: Unit }
 	| { ReadEntity: { digest: Digest; entity: Entity } | null }
@@ -215,7 +469,7 @@ export type RespKind =
 	| { GetComponentsBySchema: GetComponentsInner | null }
 	| { DelComponentsBySchema: Digest | null }
 	| { AddComponents: Digest }
-	| { ListEntities: ExactLink[] }
+	| { ListEntities: EntityPage }
 	| { CreateNamespace: NamespaceId }
 	| { ImportNamespaceSecret: NamespaceId }
 	| { GetNamespaceSecret: NamespaceSecretKey | null }
@@ -226,7 +480,22 @@ export type RespKind =
 	| { SetLocalSecret: Unit }
 	| { ListLocalSecrets: { key: string; value: string }[] }
 	| { CreateDatabaseDump: DatabaseDump }
-	| { RestoreDatabaseDump: Unit };
+	| { RestoreDatabaseDump: Unit }
+	| { Search: SearchResult[] }
+	| { Backlinks: Backlink[] }
+	| { CollectGarbage: GcReport }
+	| { ShareNamespace: string }
+	| { JoinNamespace: NamespaceId }
+	| { ListSyncPeers: SyncPeer[] }
+	| { ListNamespaces: { id: NamespaceId; capability: NamespaceCapability }[] }
+	| { GetKeyMetadata: KeyMetadata | null }
+	| { SetKeyMetadata: KeyMetadata }
+	| { ListKeyMetadata: { key: MetadataKey; metadata: KeyMetadata }[] }
+	| { StoreMetrics: StoreMetrics }
+	| { SubspaceUsage: { usage: SubspaceUsage; quota: Quota | null } }
+	| { ListTrash: TrashEntry[] }
+	| { RestoreTrash: ExactLink }
+	| { PurgeTrash: Unit };
 export const RespKindSchema = BorshSchema.Enum({
 	Authenticated: BorshSchema.Unit,
 	ReadEntity: BorshSchema.Option(
@@ -236,7 +505,7 @@ export const RespKindSchema = BorshSchema.Enum({
 	GetComponentsBySchema: BorshSchema.Option(GetComponentsInnerSchema),
 	DelComponentsBySchema: BorshSchema.Option(DigestSchema),
 	AddComponents: DigestSchema,
-	ListEntities: BorshSchema.Vec(ExactLinkSchema),
+	ListEntities: EntityPageSchema,
 	CreateNamespace: NamespaceIdSchema,
 	ImportNamespaceSecret: NamespaceIdSchema,
 	GetNamespaceSecret: BorshSchema.Option(NamespaceSecretKeySchema),
@@ -249,7 +518,29 @@ export const RespKindSchema = BorshSchema.Enum({
 		BorshSchema.Struct({ key: BorshSchema.String, value: BorshSchema.String })
 	),
 	CreateDatabaseDump: DatabaseDumpSchema,
-	RestoreDatabaseDump: BorshSchema.Unit
+	RestoreDatabaseDump: BorshSchema.Unit,
+	Search: BorshSchema.Vec(SearchResultSchema),
+	Backlinks: BorshSchema.Vec(BacklinkSchema),
+	CollectGarbage: GcReportSchema,
+	ShareNamespace: BorshSchema.String,
+	JoinNamespace: NamespaceIdSchema,
+	ListSyncPeers: BorshSchema.Vec(SyncPeerSchema),
+	ListNamespaces: BorshSchema.Vec(
+		BorshSchema.Struct({ id: NamespaceIdSchema, capability: NamespaceCapabilitySchema })
+	),
+	GetKeyMetadata: BorshSchema.Option(KeyMetadataSchema),
+	SetKeyMetadata: KeyMetadataSchema,
+	ListKeyMetadata: BorshSchema.Vec(
+		BorshSchema.Struct({ key: MetadataKeySchema, metadata: KeyMetadataSchema })
+	),
+	StoreMetrics: StoreMetricsSchema,
+	SubspaceUsage: BorshSchema.Struct({
+		usage: SubspaceUsageSchema,
+		quota: BorshSchema.Option(QuotaSchema)
+	}),
+	ListTrash: BorshSchema.Vec(TrashEntrySchema),
+	RestoreTrash: ExactLinkSchema,
+	PurgeTrash: BorshSchema.Unit
 });
 
 export type RespResult = { Err: string } | { Ok: RespKind };
@@ -545,17 +836,53 @@ export class RpcClient {
 		}
 	}
 
-	async list_entities(link: ExactLink): Promise<ExactLink[]> {
-		const resp = await this.#send_req({ ListEntities: link });
+	/**
+	 * List the entities at or below the given link.
+	 *
+	 * @param link the entity to list the children of.
+	 * @param depth only list entities at most this many path segments below `link`. A depth of `1`
+	 * lists only the direct children.
+	 */
+	async list_entities(link: ExactLink, depth?: number): Promise<ExactLink[]> {
+		const entities: ExactLink[] = [];
+		let after: EntityPath | undefined = undefined;
+		for (;;) {
+			const page: EntityPage = await this.list_entities_page(link, depth, after, 1000);
+			entities.push(...page.entities);
+			if (!page.next) break;
+			after = page.next;
+		}
+		return entities;
+	}
+
+	/**
+	 * List a page of up to `limit` entities at or below the given link.
+	 *
+	 * @param link the entity to list the children of.
+	 * @param depth only list entities at most this many path segments below `link`.
+	 * @param after resume listing after the entity with this path, which should be the `next`
+	 * cursor from the previous page.
+	 * @param limit the maximum number of entities to return.
+	 */
+	async list_entities_page(
+		link: ExactLink,
+		depth: number | undefined,
+		after: EntityPath | undefined,
+		limit: number
+	): Promise<EntityPage> {
+		const resp = await this.#send_req({ ListEntities: { link, depth, after, limit } });
 		const respKind = this.#unwrap_resp(resp);
 		if ('ListEntities' in respKind) {
-			return respKind.ListEntities.map((ent) => {
-				return {
-					namespace: new Uint8Array(ent.namespace),
-					subspace: new Uint8Array(ent.subspace),
-					path: ent.path
-				};
-			});
+			return {
+				entities: respKind.ListEntities.entities.map((ent) => {
+					return {
+						namespace: new Uint8Array(ent.namespace),
+						subspace: new Uint8Array(ent.subspace),
+						path: ent.path
+					};
+				}),
+				next: respKind.ListEntities.next
+			};
 		} else {
 			throw 'Invalid RPC response';
 		}
@@ -824,4 +1151,258 @@ export class RpcClient {
 			throw 'Invalid RPC response';
 		}
 	}
+
+	/**
+	 * Full-text search the textual components of the entities in a namespace.
+	 *
+	 * The RPC server must have search enabled.
+	 *
+	 * @param namespace the namespace to search.
+	 * @param query the search query.
+	 * @param limit the maximum number of results to return.
+	 * @returns the matching entities, ordered from most to least relevant.
+	 */
+	async search(namespace: NamespaceId, query: string, limit = 20): Promise<SearchResult[]> {
+		const resp = await this.#send_req({ Search: { namespace, query, limit } });
+		const respKind = this.#unwrap_resp(resp);
+		if ('Search' in respKind) {
+			return respKind.Search.map((result) => {
+				return {
+					link: {
+						namespace: new Uint8Array(result.link.namespace),
+						subspace: new Uint8Array(result.link.subspace),
+						path: result.link.path
+					},
+					score: result.score
+				};
+			});
+		} else {
+			throw 'Invalid RPC response';
+		}
+	}
+
+	/**
+	 * List the entities that link to the given entity.
+	 *
+	 * The RPC server must have backlinks enabled.
+	 *
+	 * @param link the entity to get the backlinks of.
+	 * @param schema only list links from components of this type.
+	 * @returns the entities that link to `link`, and the schema of the component with the link.
+	 */
+	async backlinks(
+		link: ExactLink,
+		schema?: new (...any: any) => Component
+	): Promise<Backlink[]> {
+		const resp = await this.#send_req({
+			Backlinks: { link, schema: schema && (schema as any).schemaId() }
+		});
+		const respKind = this.#unwrap_resp(resp);
+		if ('Backlinks' in respKind) {
+			return respKind.Backlinks.map((backlink) => {
+				return {
+					link: {
+						namespace: new Uint8Array(backlink.link.namespace),
+						subspace: new Uint8Array(backlink.link.subspace),
+						path: backlink.link.path
+					},
+					schema: new Uint8Array(backlink.schema)
+				};
+			});
+		} else {
+			throw 'Invalid RPC response';
+		}
+	}
+
+	/**
+	 * Remove leaked blob pins from the RPC server's store.
+	 *
+	 * @returns the number of pins removed, and the number and size of the blobs that are no
+	 * longer pinned.
+	 */
+	async collect_garbage(): Promise<GcReport> {
+		const resp = await this.#send_req({ CollectGarbage: {} });
+		const respKind = this.#unwrap_resp(resp);
+		if ('CollectGarbage' in respKind) {
+			return respKind.CollectGarbage;
+		} else {
+			throw 'Invalid RPC response';
+		}
+	}
+
+	/**
+	 * Create a ticket that another node can use to join and sync a namespace.
+	 *
+	 * A `Write` ticket contains the namespace secret.
+	 */
+	async share_namespace(namespace: NamespaceId, mode: ShareMode): Promise<string> {
+		const resp = await this.#send_req({ ShareNamespace: { namespace, mode } });
+		const respKind = this.#unwrap_resp(resp);
+		if ('ShareNamespace' in respKind) {
+			return respKind.ShareNamespace;
+		} else {
+			throw 'Invalid RPC response';
+		}
+	}
+
+	/** Join and start syncing a namespace from a share ticket. */
+	async join_namespace(ticket: string): Promise<NamespaceId> {
+		const resp = await this.#send_req({ JoinNamespace: ticket });
+		const respKind = this.#unwrap_resp(resp);
+		if ('JoinNamespace' in respKind) {
+			return new Uint8Array(respKind.JoinNamespace);
+		} else {
+			throw 'Invalid RPC response';
+		}
+	}
+
+	/** List the nodes that a namespace is synced with. */
+	async list_sync_peers(namespace: NamespaceId): Promise<SyncPeer[]> {
+		const resp = await this.#send_req({ ListSyncPeers: namespace });
+		const respKind = this.#unwrap_resp(resp);
+		if ('ListSyncPeers' in respKind) {
+			return respKind.ListSyncPeers.map((peer) => ({
+				...peer,
+				node_id: new Uint8Array(peer.node_id)
+			}));
+		} else {
+			throw 'Invalid RPC response';
+		}
+	}
+
+	/**
+	 * List the namespaces in the RPC server's store.
+	 *
+	 * @returns each namespace ID, along with whether we can only `Read` it or can also `Write` to
+	 * it.
+	 */
+	async list_namespaces(): Promise<{ id: NamespaceId; capability: NamespaceCapability }[]> {
+		const resp = await this.#send_req({ ListNamespaces: {} });
+		const respKind = this.#unwrap_resp(resp);
+		if ('ListNamespaces' in respKind) {
+			return respKind.ListNamespaces.map(({ id, capability }) => ({
+				id: new Uint8Array(id),
+				capability
+			}));
+		} else {
+			throw 'Invalid RPC response';
+		}
+	}
+
+	/** Get the local petname and description of a namespace or subspace. */
+	async get_key_metadata(key: MetadataKey): Promise<KeyMetadata | null> {
+		const resp = await this.#send_req({ GetKeyMetadata: key });
+		const respKind = this.#unwrap_resp(resp);
+		if ('GetKeyMetadata' in respKind) {
+			return respKind.GetKeyMetadata;
+		} else {
+			throw 'Invalid RPC response';
+		}
+	}
+
+	/**
+	 * Set the local petname and description of a namespace or subspace.
+	 *
+	 * @returns the updated metadata.
+	 */
+	async set_key_metadata(
+		key: MetadataKey,
+		petname: string | null,
+		description: string | null
+	): Promise<KeyMetadata> {
+		const resp = await this.#send_req({ SetKeyMetadata: { key, petname, description } });
+		const respKind = this.#unwrap_resp(resp);
+		if ('SetKeyMetadata' in respKind) {
+			return respKind.SetKeyMetadata;
+		} else {
+			throw 'Invalid RPC response';
+		}
+	}
+
+	/** List the metadata of all of the namespaces and subspaces that have it. */
+	async list_key_metadata(): Promise<{ key: MetadataKey; metadata: KeyMetadata }[]> {
+		const resp = await this.#send_req({ ListKeyMetadata: {} });
+		const respKind = this.#unwrap_resp(resp);
+		if ('ListKeyMetadata' in respKind) {
+			return respKind.ListKeyMetadata.map(({ key, metadata }) => ({
+				key:
+					'Namespace' in key
+						? { Namespace: new Uint8Array(key.Namespace) }
+						: { Subspace: new Uint8Array(key.Subspace) },
+				metadata
+			}));
+		} else {
+			throw 'Invalid RPC response';
+		}
+	}
+
+	/** Get the per-operation latency histograms and byte counters of the server's store. */
+	async store_metrics(): Promise<StoreMetrics> {
+		const resp = await this.#send_req({ StoreMetrics: {} });
+		const respKind = this.#unwrap_resp(resp);
+		if ('StoreMetrics' in respKind) {
+			return respKind.StoreMetrics;
+		} else {
+			throw 'Invalid RPC response';
+		}
+	}
+
+	/**
+	 * Get how much is stored in a subspace.
+	 *
+	 * @returns the usage of the subspace, and its quota if the server has quotas enabled.
+	 */
+	async subspace_usage(
+		namespace: NamespaceId,
+		subspace: SubspaceId
+	): Promise<{ usage: SubspaceUsage; quota: Quota | null }> {
+		const resp = await this.#send_req({ SubspaceUsage: { namespace, subspace } });
+		const respKind = this.#unwrap_resp(resp);
+		if ('SubspaceUsage' in respKind) {
+			return respKind.SubspaceUsage;
+		} else {
+			throw 'Invalid RPC response';
+		}
+	}
+
+	/**
+	 * List the entities in the trash of a subspace, from the least to the most recently deleted.
+	 */
+	async list_trash(namespace: NamespaceId, subspace: SubspaceId): Promise<TrashEntry[]> {
+		const resp = await this.#send_req({ ListTrash: { namespace, subspace } });
+		const respKind = this.#unwrap_resp(resp);
+		if ('ListTrash' in respKind) {
+			return respKind.ListTrash;
+		} else {
+			throw 'Invalid RPC response';
+		}
+	}
+
+	/**
+	 * Restore an entity from the trash to the link that it was deleted from.
+	 *
+	 * @returns the link that the entity was restored to.
+	 */
+	async restore_trash(link: ExactLink): Promise<ExactLink> {
+		const resp = await this.#send_req({ RestoreTrash: link });
+		const respKind = this.#unwrap_resp(resp);
+		if ('RestoreTrash' in respKind) {
+			return respKind.RestoreTrash;
+		} else {
+			throw 'Invalid RPC response';
+		}
+	}
+
+	/**
+	 * Permanently delete an entity from the trash.
+	 */
+	async purge_trash(link: ExactLink): Promise<Unit> {
+		const resp = await this.#send_req({ PurgeTrash: link });
+		const respKind = this.#unwrap_resp(resp);
+		if ('PurgeTrash' in respKind) {
+			return respKind.PurgeTrash;
+		} else {
+			throw 'Invalid RPC response';
+		}
+	}
 }